@@ -0,0 +1,349 @@
+// The coffee record shared by the parsing and persistence modules.
+//
+// The lessons each define a small private `Coffee` next to the demo that uses it. Reading
+// coffees from text and writing them to files needs one type that all the formats agree on, so
+// this one has every field the lessons use: the `id` and `count` from module_04 and module_06,
+// and the `name` from module_03.
+//
+// The text form is `id=1000;name=Latte;count=10`. Fields can come in any order, with spaces
+// around keys and values and an optional `;` at the end. A value can also be in double quotes,
+// like `name="Latte; large"`, with `\"`, `\\`, `\n`, `\r` and `\t` escapes inside. That is how
+// a name with a `;`, a line break or white space at either end is written.
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Coffee {
+    pub id: i32,
+    pub name: String,
+    pub count: i32,
+}
+
+impl Coffee {
+    pub fn new(id: i32, name: impl Into<String>, count: i32) -> Coffee {
+        Coffee { id, name: name.into(), count }
+    }
+}
+
+// Writes the form `FromStr` reads, so `coffee.to_string().parse()` gives the coffee back as long
+// as its name is not empty
+impl fmt::Display for Coffee {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "id={};name=", self.id)?;
+        let plain = !self.name.contains([';', '\n', '\r']) && !self.name.starts_with('"') && self.name.trim() == self.name;
+        if plain {
+            f.write_str(&self.name)?;
+        } else {
+            f.write_str("\"")?;
+            for c in self.name.chars() {
+                match c {
+                    '"' => f.write_str("\\\"")?,
+                    '\\' => f.write_str("\\\\")?,
+                    '\n' => f.write_str("\\n")?,
+                    '\r' => f.write_str("\\r")?,
+                    '\t' => f.write_str("\\t")?,
+                    c => write!(f, "{}", c)?,
+                }
+            }
+            f.write_str("\"")?;
+        }
+        write!(f, ";count={}", self.count)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Id,
+    Name,
+    Count,
+}
+
+impl Field {
+    const ALL: [Field; 3] = [Field::Id, Field::Name, Field::Count];
+
+    pub fn key(self) -> &'static str {
+        match self {
+            Field::Id => "id",
+            Field::Name => "name",
+            Field::Count => "count",
+        }
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.key())
+    }
+}
+
+/// Why a coffee record could not be parsed, and the byte offset where it went wrong.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseCoffeeError {
+    // Nothing but white space
+    Empty,
+    // A part between two `;` that is not `key=value`
+    MissingEquals { offset: usize },
+    UnknownField { key: String, offset: usize },
+    DuplicateField { field: Field, offset: usize },
+    // The offset is the end of the record, where the field should have been
+    MissingField { field: Field, offset: usize },
+    EmptyName { offset: usize },
+    // The offset is the opening quote
+    UnclosedQuote { offset: usize },
+    InvalidEscape { offset: usize },
+    TextAfterQuote { offset: usize },
+    InvalidNumber { field: Field, offset: usize, source: ParseIntError },
+}
+
+impl ParseCoffeeError {
+    pub fn offset(&self) -> usize {
+        match *self {
+            ParseCoffeeError::Empty => 0,
+            ParseCoffeeError::MissingEquals { offset }
+            | ParseCoffeeError::UnknownField { offset, .. }
+            | ParseCoffeeError::DuplicateField { offset, .. }
+            | ParseCoffeeError::MissingField { offset, .. }
+            | ParseCoffeeError::EmptyName { offset }
+            | ParseCoffeeError::UnclosedQuote { offset }
+            | ParseCoffeeError::InvalidEscape { offset }
+            | ParseCoffeeError::TextAfterQuote { offset }
+            | ParseCoffeeError::InvalidNumber { offset, .. } => offset,
+        }
+    }
+
+    // The field the error is about, if it is about a known one
+    pub fn field(&self) -> Option<Field> {
+        match *self {
+            ParseCoffeeError::DuplicateField { field, .. }
+            | ParseCoffeeError::MissingField { field, .. }
+            | ParseCoffeeError::InvalidNumber { field, .. } => Some(field),
+            ParseCoffeeError::EmptyName { .. } => Some(Field::Name),
+            _ => None,
+        }
+    }
+
+    // The same error for a record that starts `base` bytes into a longer text
+    fn shifted(mut self, base: usize) -> ParseCoffeeError {
+        match &mut self {
+            ParseCoffeeError::Empty => {}
+            ParseCoffeeError::MissingEquals { offset }
+            | ParseCoffeeError::UnknownField { offset, .. }
+            | ParseCoffeeError::DuplicateField { offset, .. }
+            | ParseCoffeeError::MissingField { offset, .. }
+            | ParseCoffeeError::EmptyName { offset }
+            | ParseCoffeeError::UnclosedQuote { offset }
+            | ParseCoffeeError::InvalidEscape { offset }
+            | ParseCoffeeError::TextAfterQuote { offset }
+            | ParseCoffeeError::InvalidNumber { offset, .. } => *offset += base,
+        }
+        self
+    }
+}
+
+impl fmt::Display for ParseCoffeeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseCoffeeError::Empty => write!(f, "empty coffee record"),
+            ParseCoffeeError::MissingEquals { offset } => write!(f, "expected `key=value` at byte {}", offset),
+            ParseCoffeeError::UnknownField { key, offset } => write!(f, "unknown field `{}` at byte {}", key, offset),
+            ParseCoffeeError::DuplicateField { field, offset } => write!(f, "field `{}` repeated at byte {}", field, offset),
+            ParseCoffeeError::MissingField { field, offset } => write!(f, "field `{}` missing at byte {}", field, offset),
+            ParseCoffeeError::EmptyName { offset } => write!(f, "field `name` is empty at byte {}", offset),
+            ParseCoffeeError::UnclosedQuote { offset } => write!(f, "quote at byte {} is never closed", offset),
+            ParseCoffeeError::InvalidEscape { offset } => write!(f, "invalid escape at byte {}", offset),
+            ParseCoffeeError::TextAfterQuote { offset } => write!(f, "unexpected text after the closing quote at byte {}", offset),
+            ParseCoffeeError::InvalidNumber { field, offset, source } => {
+                write!(f, "field `{}` at byte {} is not a valid number: {}", field, offset, source)
+            }
+        }
+    }
+}
+
+impl Error for ParseCoffeeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseCoffeeError::InvalidNumber { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+// The text with the white space around it removed, and the byte offset where it starts in `part`
+fn trimmed(part: &str) -> (&str, usize) {
+    let start = part.len() - part.trim_start().len();
+    (part.trim(), start)
+}
+
+// Where the part that starts at `start` ends: at the next `;` that is not inside a quoted value
+fn part_end(record: &str, start: usize) -> usize {
+    let rest = &record[start..];
+    let mut end = 0;
+    if let Some(equals) = rest.find(['=', ';']).filter(|&index| rest.as_bytes()[index] == b'=') {
+        let value = &rest[equals + 1..];
+        let quote = equals + 1 + value.len() - value.trim_start().len();
+        if rest[quote..].starts_with('"') {
+            // An unclosed quote takes the rest of the record, and `unquote` reports it
+            end = closing_quote(&rest[quote..]).map_or(rest.len(), |close| quote + close + 1);
+        }
+    }
+    start + end + rest[end..].find(';').unwrap_or(rest.len() - end)
+}
+
+// The byte offset of the `"` that closes the quoted text at the start of `text`
+fn closing_quote(text: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (index, c) in text.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return Some(index),
+            _ => {}
+        }
+    }
+    None
+}
+
+// The text inside the quotes of a quoted value, with the escapes replaced. The error is the byte
+// offset in `value` of what is wrong with it.
+fn unquote(value: &str) -> Result<String, ParseCoffeeError> {
+    let close = closing_quote(value).ok_or(ParseCoffeeError::UnclosedQuote { offset: 0 })?;
+    let after = &value[close + 1..];
+    if !after.is_empty() {
+        return Err(ParseCoffeeError::TextAfterQuote { offset: close + 1 + trimmed(after).1 });
+    }
+    let mut text = String::with_capacity(close);
+    let mut chars = value[1..close].char_indices();
+    while let Some((index, c)) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        text.push(match chars.next() {
+            Some((_, '"')) => '"',
+            Some((_, '\\')) => '\\',
+            Some((_, 'n')) => '\n',
+            Some((_, 'r')) => '\r',
+            Some((_, 't')) => '\t',
+            _ => return Err(ParseCoffeeError::InvalidEscape { offset: 1 + index }),
+        });
+    }
+    Ok(text)
+}
+
+impl FromStr for Coffee {
+    type Err = ParseCoffeeError;
+
+    fn from_str(record: &str) -> Result<Coffee, ParseCoffeeError> {
+        if record.trim().is_empty() {
+            return Err(ParseCoffeeError::Empty);
+        }
+
+        let mut id = None;
+        let mut name = None;
+        let mut count = None;
+        let mut part_start = 0;
+        while part_start <= record.len() {
+            let offset = part_start;
+            let end = part_end(record, offset);
+            let part = &record[offset..end];
+            part_start = end + 1;
+            // `id=1;` ends with an empty part, and so does a blank one in the middle
+            if part.trim().is_empty() {
+                continue;
+            }
+
+            let Some(equals) = part.find('=') else {
+                return Err(ParseCoffeeError::MissingEquals { offset: offset + trimmed(part).1 });
+            };
+            let (key, key_start) = trimmed(&part[..equals]);
+            let (value, value_start) = trimmed(&part[equals + 1..]);
+            let key_offset = offset + key_start;
+            let value_offset = offset + equals + 1 + value_start;
+            let value = if value.starts_with('"') {
+                Cow::Owned(unquote(value).map_err(|error| error.shifted(value_offset))?)
+            } else {
+                Cow::Borrowed(value)
+            };
+
+            let field = match Field::ALL.into_iter().find(|field| field.key() == key) {
+                Some(field) => field,
+                None => return Err(ParseCoffeeError::UnknownField { key: key.to_string(), offset: key_offset }),
+            };
+            let number = |value: &str| {
+                value
+                    .parse::<i32>()
+                    .map_err(|source| ParseCoffeeError::InvalidNumber { field, offset: value_offset, source })
+            };
+            let duplicate = match field {
+                Field::Id => id.replace(number(&value)?).is_some(),
+                Field::Count => count.replace(number(&value)?).is_some(),
+                Field::Name => {
+                    if value.is_empty() {
+                        return Err(ParseCoffeeError::EmptyName { offset: value_offset });
+                    }
+                    name.replace(value.into_owned()).is_some()
+                }
+            };
+            if duplicate {
+                return Err(ParseCoffeeError::DuplicateField { field, offset: key_offset });
+            }
+        }
+
+        let missing = |field| ParseCoffeeError::MissingField { field, offset: record.len() };
+        Ok(Coffee {
+            id: id.ok_or_else(|| missing(Field::Id))?,
+            name: name.ok_or_else(|| missing(Field::Name))?,
+            count: count.ok_or_else(|| missing(Field::Count))?,
+        })
+    }
+}
+
+/// A record that failed to parse in a multi-line text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineError {
+    // Counted from 1, like an editor does
+    pub line: usize,
+    // With the offset from the start of the whole text
+    pub error: ParseCoffeeError,
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.error)
+    }
+}
+
+impl Error for LineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+// One coffee per line, blank lines are skipped. Every line is parsed even after one fails, so
+// all the mistakes in a file are reported at once.
+pub fn parse_coffees(text: &str) -> Result<Vec<Coffee>, Vec<LineError>> {
+    let mut coffees = Vec::new();
+    let mut errors = Vec::new();
+    let mut line_start = 0;
+    for (index, line) in text.split('\n').enumerate() {
+        let base = line_start;
+        line_start += line.len() + 1;
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.trim().is_empty() {
+            continue;
+        }
+        match line.parse::<Coffee>() {
+            Ok(coffee) => coffees.push(coffee),
+            Err(error) => errors.push(LineError { line: index + 1, error: error.shifted(base) }),
+        }
+    }
+    if errors.is_empty() {
+        Ok(coffees)
+    } else {
+        Err(errors)
+    }
+}
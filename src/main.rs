@@ -1,10 +1,14 @@
 #![allow(unused)]
 
+mod coffee;
 mod module_02;
 mod module_03;
 mod module_04;
 mod module_05;
 mod module_06;
+mod module_07;
+mod module_08;
+mod module_09;
 
 use module_02::collection_fundamentals;
 use module_03::working_with_sequences;
@@ -13,8 +17,29 @@ use module_03::working_with_sequences_v3;
 use module_04::using_maps_and_sets;
 use module_04::using_maps_and_sets_v2;
 use module_05::working_with_strings;
+use module_05::text_editing;
+use module_05::text_search;
+use module_05::fuzzy_matching;
+use module_05::parsing_records;
 use module_06::managing_collections_in_memory;
 use module_06::managing_collections_in_memory_demo;
+use module_06::allocation_strategies;
+use module_06::persistent_collections;
+use module_06::inline_storage;
+use module_07::using_collections_concurrently;
+use module_08::reading_and_writing_files;
+use module_08::binary_encoding;
+use module_08::durable_storage;
+use module_08::sorted_tables;
+use module_09::property_testing;
+use module_09::benchmarking;
+
+// Opt-in allocation tracking for the capacity demos in module 06, turned on with
+// `cargo run --features alloc-tracking`. Without it every `track()` report is a notice that
+// tracking is off.
+#[cfg(feature = "alloc-tracking")]
+#[global_allocator]
+static GLOBAL: module_06::alloc_tracking::CountingAllocator = module_06::alloc_tracking::CountingAllocator;
 
 fn main() {
     // Module 02 - Collection Fundamentals
@@ -44,10 +69,51 @@ fn main() {
     // working_with_strings::useful_string_functions();
     // working_with_strings::primitive_string();
     // working_with_strings::demo_strings();
+    // working_with_strings::demo_graphemes();
+    // working_with_strings::demo_case_mapping();
+    // text_editing::demo_rope();
+    // text_editing::benchmark_rope_vs_string();
+    // text_editing::demo_gap_buffer();
+    // text_search::demo_search();
+    // text_search::benchmark_search_vs_find();
+    // fuzzy_matching::demo_fuzzy_find();
+    // fuzzy_matching::demo_bk_tree();
+    // parsing_records::demo_parse_coffee();
 
     // Module 06 - Managing Collections in Memory
     // managing_collections_in_memory::collections_and_memory_management();
+    // managing_collections_in_memory::demo_growth_curves();
     // managing_collections_in_memory::moving_copying_and_cloning_collections();
     // managing_collections_in_memory_demo::demo_cloning_and_copying_collections();
     managing_collections_in_memory_demo::demo_capacity_management();
+    // managing_collections_in_memory_demo::demo_memory_footprint();
+    // managing_collections_in_memory_demo::demo_clone_costs();
+    // managing_collections_in_memory_demo::demo_interned_keys();
+    // allocation_strategies::demo_arenas();
+    // allocation_strategies::benchmark_arena_list_vs_linked_list();
+    // persistent_collections::demo_persistent_collections();
+    // persistent_collections::compare_clone_costs();
+    // inline_storage::demo_small_vec();
+    // inline_storage::demo_inline_string();
+
+    // Module 07 - Using Collections Concurrently
+    // using_collections_concurrently::demo_lock_free_collections();
+    // using_collections_concurrently::benchmark_lock_free_vs_mutex();
+    // using_collections_concurrently::demo_work_stealing();
+
+    // Module 08 - Saving Collections
+    // reading_and_writing_files::demo_csv();
+    // reading_and_writing_files::demo_json();
+    // binary_encoding::demo_binary_encoding();
+    // binary_encoding::check_round_trips();
+    // durable_storage::demo_disk_map();
+    // durable_storage::check_crash_recovery();
+    // sorted_tables::demo_sstable();
+    // sorted_tables::check_sstable();
+
+    // Module 09 - Testing Collections
+    // property_testing::demo_property_testing();
+    // property_testing::check_collections();
+    // benchmarking::benchmark_sequences();
+    // benchmarking::benchmark_sequences_csv();
 }
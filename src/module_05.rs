@@ -1,4 +1,16 @@
+pub mod fuzzy;
+pub mod gap_buffer;
+pub mod rope;
+pub mod search;
+pub mod text;
+pub mod text_buffer;
+
 pub mod working_with_strings {
+    use std::collections::{BTreeMap, HashMap};
+
+    use super::text;
+    use super::text::case::{self, CaseInsensitiveStr, CaseInsensitiveString};
+
     pub fn introduction_to_strings() {
         let primitive_str = "Hello World";
         let mut dynamic_string = String::new();
@@ -88,6 +100,7 @@ pub mod working_with_strings {
 
         // Don't do this!
         // println!("First char in greeting: {}\n", greeting[0]);
+        // (the two ways below are only right for ASCII text, see `demo_graphemes()`)
 
         let first_char = greeting.as_str().chars().nth(0);
         println!("First char in greeting: {:?}", first_char);
@@ -140,6 +153,7 @@ pub mod working_with_strings {
 
         let maybe_eleven = "eleven".parse::<u32>();
         println!("Error: {:?}\n", maybe_eleven);
+        // `parsing_records::demo_parse_coffee` parses whole records with errors that say where
 
         // Trimming a string
         let str_with_spaces = "   Hello World        ";
@@ -172,4 +186,531 @@ pub mod working_with_strings {
         // Splitting at a specific index
         println!("{:?}\n", greeting.split_at(4));
     }
-}
\ No newline at end of file
+
+    pub fn demo_graphemes() {
+        // `chars().nth(0)` and `as_bytes()[0]` from `demo_strings` only work for ASCII text
+        let names = ["Latte", "Café", "Cafe\u{301}", "Crème brûlée", "👩\u{200D}☕ Barista", "🇮🇹 Espresso"];
+        for name in names {
+            println!("{:?}", name);
+            println!("  bytes: {}, chars: {}, graphemes: {}", name.len(), name.chars().count(), text::grapheme_len(name));
+            println!("  first byte: {:?}, first char: {:?}, first grapheme: {:?}",
+                     name.as_bytes()[0], name.chars().next(), text::nth_grapheme(name, 0));
+            println!("  last char: {:?}, last grapheme: {:?}",
+                     name.chars().last(), text::graphemes(name).last());
+        }
+        println!();
+
+        // The fourth letter of "Café" is one char, in "Cafe\u{301}" it is two
+        let composed = "Café";
+        let decomposed = "Cafe\u{301}";
+        println!("4th char:     {:?} vs {:?}", composed.chars().nth(3), decomposed.chars().nth(3));
+        println!("4th grapheme: {:?} vs {:?}\n", text::nth_grapheme(composed, 3), text::nth_grapheme(decomposed, 3));
+
+        // `String::truncate` takes a byte index and panics in the middle of a char,
+        // `truncate_graphemes` can only cut between whole characters
+        let mut order = String::from("👩\u{200D}☕🇮🇹 Café");
+        // order.truncate(1);    // Panic! byte 1 is inside the first emoji
+        text::truncate_graphemes(&mut order, 2);
+        println!("First two graphemes of the order: {:?}", order);
+
+        // Converting between the three kinds of index
+        let menu = "Café 🇮🇹 Latte";
+        let latte = menu.find("Latte").unwrap();
+        println!("{:?}: \"Latte\" starts at byte {}, char {:?}, grapheme {:?}",
+                 menu, latte, text::byte_to_char(menu, latte), text::byte_to_grapheme(menu, latte));
+        println!("  grapheme 5 starts at byte {:?} and char {:?}",
+                 text::grapheme_to_byte(menu, 5), text::grapheme_to_char(menu, 5));
+        println!("  byte 4 is inside a char: byte_to_char gives {:?}", text::byte_to_char(menu, 4));
+        println!("  (segmentation rules from Unicode {:?})", text::unicode_version());
+    }
+
+    pub fn demo_case_mapping() {
+        // `make_ascii_lowercase`/`make_ascii_uppercase` from `demo_strings` skip every
+        // non-ASCII letter
+        let mut ascii_only = String::from("CAFÉ CRÈME");
+        ascii_only.make_ascii_lowercase();
+        println!("make_ascii_lowercase(): {}", ascii_only);
+        println!("to_lowercase():         {}\n", case::to_lowercase("CAFÉ CRÈME"));
+
+        // The full mappings can change the length, and depend on the position in the word
+        println!("Upper case of {:?}: {:?}", "Straße", case::to_uppercase("Straße"));
+        println!("Lower case of {:?}: {:?}", "ΚΑΦΕΣ", case::to_lowercase("ΚΑΦΕΣ"));
+        println!("Title case of {:?}: {:?}", "caffè latte", case::to_titlecase("caffè latte"));
+        println!("Title case of {:?}: {:?}", "ǆem ﬂat white", case::to_titlecase("ǆem ﬂat white"));
+        println!("Title case of {:?}: {:?}\n", "STRASSE-café", case::to_titlecase("STRASSE-café"));
+
+        // Case folding is for comparing, not for display
+        println!("Folded {:?}: {:?}", "Straße", case::fold_case("Straße"));
+        println!("\"STRASSE\" == \"straße\" ignoring case: {}", case::eq_ignore_case("STRASSE", "straße"));
+        println!("\"ΚΑΦΕΣ\" == \"καφες\" ignoring case: {}\n", case::eq_ignore_case("ΚΑΦΕΣ", "καφες"));
+
+        // Keys that ignore case: every spelling finds the same coffee
+        let mut coffee_prices: HashMap<CaseInsensitiveString, f64> = HashMap::new();
+        coffee_prices.insert(CaseInsensitiveString::from("Latte"), 3.50);
+        coffee_prices.insert(CaseInsensitiveString::from("Café Crème"), 4.00);
+        // Replaces the price, the key keeps its first spelling
+        coffee_prices.insert(CaseInsensitiveString::from("LATTE"), 3.75);
+        println!("coffee_prices: {:?}", coffee_prices);
+        for name in ["latte", "LATTE", "CAFÉ CRÈME", "café crème", "Mocha"] {
+            // Looked up with a borrowed `CaseInsensitiveStr`, no String is built for the lookup
+            println!("  {:<10} -> {:?}", name, coffee_prices.get(CaseInsensitiveStr::new(name)));
+        }
+
+        // A BTreeMap also orders its keys ignoring case
+        let menu: BTreeMap<CaseInsensitiveString, i32> = BTreeMap::from([
+            (CaseInsensitiveString::from("mocha"), 20),
+            (CaseInsensitiveString::from("Espresso"), 30),
+            (CaseInsensitiveString::from("latte"), 10),
+            (CaseInsensitiveString::from("Americano"), 40),
+        ]);
+        println!("\nmenu: {:?}", menu);
+        println!("menu[\"ESPRESSO\"]: {}", menu[CaseInsensitiveStr::new("ESPRESSO")]);
+    }
+}
+
+pub mod text_editing {
+    use std::time::{Duration, Instant};
+
+    use super::gap_buffer::GapBuffer;
+    use super::rope::Rope;
+    use super::text_buffer::TextBuffer;
+
+    pub fn demo_rope() {
+        // The edits from `demo_strings`, on a rope. Positions are char indices, not bytes.
+        let mut greeting = Rope::from("Hello World");
+        println!("{}", greeting);
+        println!("First char in greeting: {:?}\n", greeting.char(0));
+
+        greeting.push_str(", my name is Shaphil");
+        println!("{}", greeting);
+
+        greeting.remove(0..1);
+        println!("{}", greeting);
+
+        greeting.pop();
+        println!("{}\n", greeting);
+
+        greeting.push('l');
+        println!("{}", greeting);
+
+        greeting.insert_char(0, 'H');
+        println!("{}", greeting);
+
+        greeting.insert(0, "Well, ");
+        println!("{}\n", greeting);
+
+        let substr = "Hello World";
+        let hello_world_start = greeting.find(substr).unwrap_or(greeting.len_chars());
+        let hello_world_end = hello_world_start + substr.chars().count();
+        greeting.replace_range(hello_world_start..hello_world_end, "hello world");
+        println!("{}", greeting);
+        assert_eq!(greeting, "Well, hello world, my name is Shaphil");
+
+        // Slicing shares the chunks with the original rope
+        println!("Slice 6..17: {}\n", greeting.slice(6..17));
+
+        // Lines are counted in every node, so finding one does not scan the text before it
+        let mut menu = Rope::from("Latte\nMocha\nEspresso\n");
+        menu.insert(menu.line_to_char(2), "Cappuccino\n");
+        println!("Menu has {} lines:", menu.len_lines());
+        for line in 0..menu.len_lines() {
+            println!("  line {}: {:?}", line, menu.line(line).to_string());
+        }
+        println!("'E' of Espresso is on line {}\n", menu.char_to_line(menu.find("Espresso").unwrap()));
+
+        // A large document is kept in chunks of at most 1 KB in a shallow tree
+        let document = Rope::from("Coffee ☕\n".repeat(100_000).as_str());
+        println!(
+            "Document: {} bytes, {} chars, {} lines, {} chunks, tree height {}",
+            document.len_bytes(),
+            document.len_chars(),
+            document.len_lines(),
+            document.chunks().count(),
+            document.height()
+        );
+    }
+
+    pub fn benchmark_rope_vs_string() {
+        // Inserting and removing text in the middle of a document: a `String` moves everything
+        // after the edit, a rope only rebuilds one path through its tree
+        const EDITS: usize = 1000;
+        println!("{:>10} | {:>14} | {:>14}", "size", "String", "Rope");
+        for megabytes in [1, 4, 16] {
+            let text = "Coffee, Latte, Mocha\n".repeat(megabytes * 1024 * 1024 / 21);
+            let positions: Vec<usize> = (0..EDITS).map(|n| (n * 7919) % text.len()).collect();
+
+            let mut string = text.clone();
+            let string_time = time(|| {
+                for &position in &positions {
+                    // All the text is ASCII, so every byte is a char boundary
+                    string.insert_str(position, "Espresso");
+                    string.replace_range(position..position + 4, "");
+                }
+            });
+
+            let mut rope = Rope::from(text.as_str());
+            let rope_time = time(|| {
+                for &position in &positions {
+                    rope.insert(position, "Espresso");
+                    rope.remove(position..position + 4);
+                }
+            });
+
+            assert!(rope == string.as_str());
+            println!("{:>7} MB | {:>14?} | {:>14?}", megabytes, string_time, rope_time);
+        }
+        println!("({} inserts and {} removes each)", EDITS, EDITS);
+    }
+
+    fn time(f: impl FnOnce()) -> Duration {
+        let start = Instant::now();
+        f();
+        start.elapsed()
+    }
+
+    // The edits from `demo_strings`, for any `TextBuffer`
+    fn edit_greeting<T: TextBuffer>(greeting: &mut T) {
+        greeting.push_str(", my name is Shaphil");
+        println!("{}", greeting);
+
+        greeting.remove(0);
+        println!("{}", greeting);
+
+        greeting.pop();
+        println!("{}", greeting);
+
+        greeting.push('l');
+        println!("{}", greeting);
+
+        greeting.insert(0, 'H');
+        println!("{}", greeting);
+
+        greeting.insert_str(0, "Well, ");
+        println!("{}", greeting);
+
+        let substr = "Hello World";
+        let hello_world_start = greeting.find(substr).unwrap_or(greeting.len());
+        let hello_world_end = hello_world_start + substr.len();
+        greeting.replace_range(hello_world_start..hello_world_end, "hello world");
+        println!("{}\n", greeting);
+    }
+
+    pub fn demo_gap_buffer() {
+        // The same edits on a String and on a gap buffer give the same text
+        let mut string = String::from("Hello World");
+        println!("String:");
+        edit_greeting(&mut string);
+
+        let mut buffer = GapBuffer::from("Hello World");
+        println!("GapBuffer:");
+        edit_greeting(&mut buffer);
+        assert_eq!(buffer.to_string(), string);
+
+        // Editing at the cursor, like typing in an editor. `{:?}` shows the cursor as `|`.
+        let mut menu = GapBuffer::from("Latte, Espresso");
+        menu.set_cursor("Latte, ".len());
+        println!("{:?}", menu);
+        for ch in "Mocha, ".chars() {
+            menu.insert_at_cursor(ch.encode_utf8(&mut [0; 4]));
+        }
+        println!("{:?} (after typing)", menu);
+        menu.delete_backward();
+        menu.delete_backward();
+        menu.insert_at_cursor(" & ");
+        println!("{:?} (after two backspaces)", menu);
+        menu.move_to_end();
+        menu.insert_at_cursor(" ☕");
+        menu.move_left();
+        println!("{:?} (after moving to the end, typing and moving left)", menu);
+        println!("Bytes moved so far: {}\n", menu.bytes_moved());
+
+        // Typing 100,000 chars in the middle of a 1 MB document. The String moves the text
+        // after the cursor for every single char, the gap buffer moves it once.
+        let document = "Coffee, Latte, Mocha\n".repeat(50_000);
+        let middle = document.len() / 2;
+
+        let mut string = document.clone();
+        let start = Instant::now();
+        for n in 0..100_000 {
+            string.insert(middle + n, 'x');
+        }
+        let string_time = start.elapsed();
+
+        let mut buffer = GapBuffer::from(document.as_str());
+        let start = Instant::now();
+        buffer.set_cursor(middle);
+        for _ in 0..100_000 {
+            buffer.insert_at_cursor("x");
+        }
+        let buffer_time = start.elapsed();
+
+        assert_eq!(String::from(buffer.make_contiguous()), string);
+        println!("Typing 100,000 chars into a 1 MB document:");
+        println!("  String:    {:?}", string_time);
+        println!("  GapBuffer: {:?} ({} bytes moved)", buffer_time, buffer.bytes_moved());
+    }
+}
+
+pub mod text_search {
+    use std::collections::BTreeSet;
+    use std::time::{Duration, Instant};
+
+    use super::search::{AhoCorasick, Horspool, Kmp};
+
+    const MENU: &str = "Today at the Coffee Corner: a classic Latte, an iced Mocha and a Cappuccino \
+        with oat milk. Try the Mocha Latte, our Latte of the week, or a decaf Cappuccino. \
+        Cappuccinos are half price before 9 ☕ and every Latte comes with a biscuit.";
+
+    pub fn demo_search() {
+        // The set from `useful_btreeset_functions`
+        let coffees = BTreeSet::from(["Latte", "Mocha", "Cappuccino", "Latte", "Latte", "Latte"]);
+        println!("coffees: {:?}\n", coffees);
+
+        // `str::find` only gives the first match of one pattern, `match_indices` all of them
+        println!("MENU.find(\"Latte\"): {:?}", MENU.find("Latte"));
+        println!("match_indices(\"Latte\"): {:?}\n", MENU.match_indices("Latte").map(|(index, _)| index).collect::<Vec<_>>());
+
+        // The single-pattern searchers give the same positions
+        let kmp = Kmp::new("Latte");
+        let horspool = Horspool::new("Latte");
+        println!("Kmp::find_all(\"Latte\"): {:?}", kmp.find_all(MENU));
+        println!("Horspool::find_all(\"Latte\"): {:?}\n", horspool.find_all(MENU));
+        assert_eq!(kmp.find_all(MENU), horspool.find_all(MENU));
+
+        // Every coffee name in one pass over the menu. The positions are byte offsets, so they
+        // slice the menu directly.
+        let automaton = AhoCorasick::new(&coffees);
+        for found in automaton.find_all(MENU) {
+            println!("{:>3}..{:<3} {}", found.start, found.end, &MENU[found.start..found.end]);
+        }
+        println!();
+
+        for (name, count) in automaton.patterns().iter().zip(automaton.count_matches(MENU)) {
+            println!("{} is on the menu {} times", name, count);
+            assert_eq!(count, MENU.matches(name.as_str()).count());
+        }
+        println!();
+
+        // Matches may overlap or sit inside each other, `match_indices` skips those
+        let nested = AhoCorasick::new(["Mocha Latte", "Mocha", "Latte", "a L"]);
+        for found in nested.find_all(MENU) {
+            println!("{:>3}..{:<3} {:?}", found.start, found.end, &MENU[found.start..found.end]);
+        }
+        println!("\"aa\" in \"aaaa\": {:?} (match_indices: {:?})", Kmp::new("aa").find_all("aaaa"), "aaaa".match_indices("aa").map(|(index, _)| index).collect::<Vec<_>>());
+    }
+
+    pub fn benchmark_search_vs_find() {
+        let text = MENU.repeat(20_000);
+        println!("Searching {} KB of menu text\n", text.len() / 1024);
+
+        // One pattern: every match with `str::find`, KMP and Horspool
+        println!("{:>16} | {:>12} | {:>12} | {:>12}", "pattern", "str::find", "Kmp", "Horspool");
+        for pattern in ["Latte", "Cappuccino with oat milk", "decaf Espresso"] {
+            let (expected, find_time) = time(|| find_every(&text, pattern));
+            let kmp = Kmp::new(pattern);
+            let (kmp_matches, kmp_time) = time(|| kmp.find_all(&text));
+            let horspool = Horspool::new(pattern);
+            let (horspool_matches, horspool_time) = time(|| horspool.find_all(&text));
+            assert_eq!(kmp_matches, expected);
+            assert_eq!(horspool_matches, expected);
+            println!("{:>16.16} | {:>12?} | {:>12?} | {:>12?}", pattern, find_time, kmp_time, horspool_time);
+        }
+        println!();
+
+        // Many patterns: one `str::find` scan per pattern against one pass of the automaton
+        println!("{:>9} | {:>12} | {:>12}", "patterns", "str::find", "AhoCorasick");
+        let names = [
+            "Latte", "Mocha", "Cappuccino", "Espresso", "Americano", "Macchiato", "Cortado", "Flat White",
+            "Ristretto", "Lungo", "Affogato", "Frappe", "Irish Coffee", "Doppio", "Red Eye", "Con Panna",
+        ];
+        for count in [1, 4, 16] {
+            let patterns = &names[..count];
+            let (expected, find_time) = time(|| patterns.iter().map(|pattern| find_every(&text, pattern).len()).collect::<Vec<_>>());
+            let automaton = AhoCorasick::new(patterns);
+            let (counts, automaton_time) = time(|| automaton.count_matches(&text));
+            assert_eq!(counts, expected);
+            println!("{:>9} | {:>12?} | {:>12?}", count, find_time, automaton_time);
+        }
+    }
+
+    // Every match of `pattern`, overlapping ones included, by calling `str::find` again just
+    // after the previous match
+    fn find_every(text: &str, pattern: &str) -> Vec<usize> {
+        let mut matches = Vec::new();
+        let mut start = 0;
+        while let Some(index) = text[start..].find(pattern) {
+            matches.push(start + index);
+            start += index + text[start + index..].chars().next().map_or(1, char::len_utf8);
+        }
+        matches
+    }
+
+    fn time<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+        let start = Instant::now();
+        let result = f();
+        (result, start.elapsed())
+    }
+}
+
+pub mod fuzzy_matching {
+    use std::collections::BTreeSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::fuzzy::{self, BkTree};
+
+    pub fn demo_fuzzy_find() {
+        // The set from `useful_btreeset_functions`, plus the rest of the menu from module_04
+        let coffees = BTreeSet::from([
+            "Latte", "Mocha", "Cappuccino", "Espresso", "Americano", "Macchiato", "Flat White", "Cortado",
+        ]);
+        println!("coffees: {:?}\n", coffees);
+
+        // "Capuccino" is missing a 'p', "Expresso" has a wrong letter, "Cappucicno" swaps two
+        println!("levenshtein(\"Capuccino\", \"Cappuccino\"): {}", fuzzy::levenshtein("Capuccino", "Cappuccino"));
+        println!("levenshtein(\"Cappucicno\", \"Cappuccino\"): {}", fuzzy::levenshtein("Cappucicno", "Cappuccino"));
+        println!("damerau_levenshtein(\"Cappucicno\", \"Cappuccino\"): {}\n", fuzzy::damerau_levenshtein("Cappucicno", "Cappuccino"));
+        assert_eq!(fuzzy::levenshtein("Capuccino", "Cappuccino"), 1);
+        assert_eq!(fuzzy::damerau_levenshtein("Cappucicno", "Cappuccino"), 1);
+
+        for query in ["Capuccino", "Cappucicno", "expresso", "LATTE", "mac", "Moca", "Flat Whit", "Tea"] {
+            let suggestions = fuzzy::fuzzy_find(query, &coffees);
+            let names: Vec<String> = suggestions.iter().map(|suggestion| format!("{} ({})", suggestion.name, suggestion.distance)).collect();
+            println!("{:>12} -> {}", query, if names.is_empty() { String::from("no suggestions") } else { names.join(", ") });
+        }
+        assert_eq!(fuzzy::fuzzy_find("Capuccino", &coffees)[0].name, "Cappuccino");
+        assert!(fuzzy::fuzzy_find("Tea", &coffees).is_empty());
+    }
+
+    static DISTANCES_COMPUTED: AtomicUsize = AtomicUsize::new(0);
+
+    fn counting_levenshtein(a: &str, b: &str) -> usize {
+        DISTANCES_COMPUTED.fetch_add(1, Ordering::Relaxed);
+        fuzzy::levenshtein(a, b)
+    }
+
+    pub fn demo_bk_tree() {
+        let mut tree = BkTree::with_distance(counting_levenshtein);
+        for name in ["Latte", "Mocha", "Cappuccino", "Espresso", "Americano", "Macchiato", "Flat White", "Cortado"] {
+            tree.insert(name);
+        }
+        println!("Inserting \"Latte\" again: {}", tree.insert("Latte"));
+        println!("tree.len(): {}\n", tree.len());
+
+        println!("Within 2 edits of \"Capuccino\": {:?}", tree.find("Capuccino", 2));
+        println!("Within 3 edits of \"Lattes\": {:?}", tree.find("Lattes", 3));
+        println!("Nearest to \"Moccha\": {:?}\n", tree.nearest("Moccha"));
+
+        // A dictionary of made-up drink names, to show how much of the tree a lookup skips
+        let syllables = ["ca", "pu", "ci", "no", "la", "te", "mo", "ch", "a", "es", "pre", "so", "ma", "to", "ri", "do"];
+        let mut drinks = BkTree::with_distance(counting_levenshtein);
+        let mut seed: u32 = 2021;
+        for _ in 0..20_000 {
+            let mut name = String::new();
+            for _ in 0..4 {
+                // A small linear congruential generator keeps the names the same on every run
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                name.push_str(syllables[(seed >> 16) as usize % syllables.len()]);
+            }
+            drinks.insert(name);
+        }
+        println!("{} distinct drink names", drinks.len());
+        for (query, max_distance) in [("capucino", 1), ("capucino", 2), ("lattemocha", 1), ("espresso", 3)] {
+            DISTANCES_COMPUTED.store(0, Ordering::Relaxed);
+            let found = drinks.find(query, max_distance);
+            let computed = DISTANCES_COMPUTED.load(Ordering::Relaxed);
+            let expected = drinks.iter().filter(|name| fuzzy::levenshtein(name, query) <= max_distance).count();
+            assert_eq!(found.len(), expected);
+            println!(
+                "{:>10} within {}: {:>4} matches, {:>5} distances computed ({:.1}% of the tree)",
+                query,
+                max_distance,
+                found.len(),
+                computed,
+                computed as f64 * 100.0 / drinks.len() as f64
+            );
+        }
+    }
+}
+
+pub mod parsing_records {
+    use std::error::Error;
+
+    use crate::coffee::{self, Coffee, ParseCoffeeError};
+
+    // Prints the text with a `^` under the byte the error points at
+    fn show_error(text: &str, error: &dyn Error, offset: usize) {
+        println!("  {}", text);
+        // The marker lines up with the char at `offset`, not with its byte
+        println!("  {}^ {}", " ".repeat(text[..offset].chars().count()), error);
+    }
+
+    pub fn demo_parse_coffee() {
+        // Like `"5000".parse::<u32>()` in `demo_strings`, but for a whole record
+        let latte: Result<Coffee, _> = "id=1000;name=Latte;count=10".parse();
+        println!("Parsed: {:?}", latte);
+
+        // Any order, spaces and a trailing `;` are fine, and `Display` writes the record back
+        let mocha: Coffee = " count = 5 ; name = Mocha ; id = 1001 ; ".parse().unwrap();
+        println!("Parsed: {:?}", mocha);
+        println!("Written back: {}", mocha);
+        assert_eq!(mocha.to_string().parse::<Coffee>(), Ok(mocha));
+
+        // A name that would not survive that is written in quotes
+        for name in ["Latte; large", " Mocha ", "\"Flat\" White\\\n", "Café \"Crème\""] {
+            let coffee = Coffee::new(1002, name, 3);
+            println!("Written back: {}", coffee);
+            assert_eq!(coffee.to_string().parse::<Coffee>(), Ok(coffee));
+        }
+
+        // `ParseIntError` only says that a number is invalid, the record error also says which
+        // field it was and where in the text
+        let maybe_eleven = "eleven".parse::<u32>();
+        println!("\nError: {:?}\n", maybe_eleven);
+        for record in [
+            "id=1000;name=Latte;count=eleven",
+            "id=1000;name=Café Crème;count=99999999999",
+            "id=1000;price=3.50;name=Latte;count=10",
+            "id=1000;name=Latte;id=1001;count=10",
+            "id=1000;name=;count=10",
+            "id=1000;Latte;count=10",
+            "id=1000;name=Latte",
+            "id=1000;name=\"Latte;count=10",
+            "id=1000;name=\"Latte\\q\";count=10",
+            "id=1000;name=\"Latte\" large;count=10",
+        ] {
+            let error = record.parse::<Coffee>().unwrap_err();
+            println!("{:?}", error);
+            show_error(record, &error, error.offset());
+            if let Some(source) = error.source() {
+                println!("  caused by: {}", source);
+            }
+        }
+        let error = "id=1000;name=Latte;count=eleven".parse::<Coffee>().unwrap_err();
+        assert!(matches!(error, ParseCoffeeError::InvalidNumber { offset: 25, .. }));
+        assert_eq!(error.field(), Some(coffee::Field::Count));
+
+        // A bulk parse goes on after a bad line, so every problem is reported in one go
+        let menu = "id=1000;name=Latte;count=10\n\
+                    id=1001;name=Mocha;count=five\n\
+                    \n\
+                    id=1002;name=Cappuccino;count=7\n\
+                    id=1003;count=2\n\
+                    id=x;name=Espresso;count=1\n";
+        match coffee::parse_coffees(menu) {
+            Ok(coffees) => println!("\nAll {} coffees parsed", coffees.len()),
+            Err(errors) => {
+                println!("\n{} of the lines have errors:", errors.len());
+                for error in &errors {
+                    // The offsets count from the start of the whole menu
+                    let line_start = menu[..error.error.offset()].rfind('\n').map_or(0, |index| index + 1);
+                    let line = menu[line_start..].lines().next().unwrap_or("");
+                    println!("line {} (byte {}):", error.line, error.error.offset());
+                    show_error(line, &error.error, error.error.offset() - line_start);
+                }
+                assert_eq!(errors.iter().map(|error| error.line).collect::<Vec<_>>(), vec![2, 5, 6]);
+            }
+        }
+        let good: Vec<Coffee> = menu.lines().filter_map(|line| line.parse().ok()).collect();
+        println!("\nThe lines that did parse: {:?}", good);
+    }
+}
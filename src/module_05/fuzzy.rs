@@ -0,0 +1,240 @@
+// Edit distances and fuzzy lookups, for names that customers type with mistakes.
+//
+// The edit distance between two strings is the fewest single-char edits that turn one into the
+// other. Levenshtein distance counts insertions, deletions and substitutions; Damerau-Levenshtein
+// distance also counts swapping two neighbouring chars as one edit, so "Cappucicno" is one edit
+// away from "Cappuccino" instead of two. Both count chars, not bytes, so "é" is a single edit.
+//
+// A `BkTree` (Burkhard-Keller tree) stores strings so that the ones within a distance of a query
+// can be found without measuring the distance to every one of them. It relies on the triangle
+// inequality, which both distances here satisfy: the Damerau-Levenshtein distance is the
+// unrestricted one, not the cheaper "optimal string alignment" variant that is not a metric.
+
+use std::collections::{BTreeSet, HashMap};
+
+use super::text::case;
+
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // Only the previous row of the table is needed to compute the next one
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a_char) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, &b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + (a_char != b_char) as usize;
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+// Lowrance and Wagner's algorithm. Unlike the optimal string alignment distance it allows edits
+// between the two swapped chars, so "ca" to "abc" is 2 (swap, then insert) and not 3.
+pub fn damerau_levenshtein(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let width = b.len() + 2;
+    let infinity = a.len() + b.len();
+    // `table[(i + 1) * width + (j + 1)]` is the distance between `a[..i]` and `b[..j]`. The
+    // extra first row and column hold `infinity`, so a swap is never taken across the start.
+    let mut table = vec![0; (a.len() + 2) * width];
+    table[0] = infinity;
+    for i in 0..=a.len() {
+        table[(i + 1) * width] = infinity;
+        table[(i + 1) * width + 1] = i;
+    }
+    for j in 0..=b.len() {
+        table[j + 1] = infinity;
+        table[width + j + 1] = j;
+    }
+
+    // The last row of `a` each char was seen in
+    let mut last_row: HashMap<char, usize> = HashMap::new();
+    for i in 1..=a.len() {
+        // The last column of `b` in this row where the chars matched
+        let mut last_match_column = 0;
+        for j in 1..=b.len() {
+            let k = last_row.get(&b[j - 1]).copied().unwrap_or(0);
+            let l = last_match_column;
+            let cost = if a[i - 1] == b[j - 1] {
+                last_match_column = j;
+                0
+            } else {
+                1
+            };
+            let substitution = table[i * width + j] + cost;
+            let insertion = table[(i + 1) * width + j] + 1;
+            let deletion = table[i * width + j + 1] + 1;
+            // Swap `a[k - 1]` with `a[i - 1]`, with the chars between them deleted from `a` and
+            // the ones between `b[l - 1]` and `b[j - 1]` inserted
+            let transposition = table[k * width + l] + (i - k - 1) + 1 + (j - l - 1);
+            table[(i + 1) * width + j + 1] = substitution.min(insertion).min(deletion).min(transposition);
+        }
+        last_row.insert(a[i - 1], i);
+    }
+    table[(a.len() + 1) * width + b.len() + 1]
+}
+
+struct BkNode<T> {
+    value: T,
+    // Each child with its distance to this node. No two children have the same distance.
+    children: Vec<(usize, usize)>,
+}
+
+/// A Burkhard-Keller tree: strings indexed by their edit distance to each other.
+pub struct BkTree<T> {
+    nodes: Vec<BkNode<T>>,
+    distance: fn(&str, &str) -> usize,
+}
+
+impl<T: AsRef<str>> BkTree<T> {
+    // A tree that uses the Levenshtein distance
+    pub fn new() -> BkTree<T> {
+        BkTree::with_distance(levenshtein)
+    }
+
+    // `distance` has to be a metric, or lookups miss values
+    pub fn with_distance(distance: fn(&str, &str) -> usize) -> BkTree<T> {
+        BkTree { nodes: Vec::new(), distance }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // Returns `false` and leaves the tree as it was if an equal string is already in it
+    pub fn insert(&mut self, value: T) -> bool {
+        if self.nodes.is_empty() {
+            self.nodes.push(BkNode { value, children: Vec::new() });
+            return true;
+        }
+        let mut node = 0;
+        loop {
+            let distance = (self.distance)(self.nodes[node].value.as_ref(), value.as_ref());
+            if distance == 0 {
+                return false;
+            }
+            match self.nodes[node].children.iter().find(|&&(child_distance, _)| child_distance == distance) {
+                Some(&(_, child)) => node = child,
+                None => {
+                    let child = self.nodes.len();
+                    self.nodes.push(BkNode { value, children: Vec::new() });
+                    self.nodes[node].children.push((distance, child));
+                    return true;
+                }
+            }
+        }
+    }
+
+    // Every value within `max_distance` of `query`, closest first
+    pub fn find(&self, query: &str, max_distance: usize) -> Vec<(usize, &T)> {
+        let mut found = Vec::new();
+        let mut pending = if self.nodes.is_empty() { Vec::new() } else { vec![0] };
+        while let Some(node) = pending.pop() {
+            let node = &self.nodes[node];
+            let distance = (self.distance)(node.value.as_ref(), query);
+            if distance <= max_distance {
+                found.push((distance, &node.value));
+            }
+            // By the triangle inequality, a child at `child_distance` from this node is at least
+            // `|child_distance - distance|` away from the query
+            for &(child_distance, child) in &node.children {
+                if child_distance.abs_diff(distance) <= max_distance {
+                    pending.push(child);
+                }
+            }
+        }
+        found.sort_by(|(a_distance, a), (b_distance, b)| a_distance.cmp(b_distance).then_with(|| a.as_ref().cmp(b.as_ref())));
+        found
+    }
+
+    // The closest value to `query`, the first one in string order on a tie
+    pub fn nearest(&self, query: &str) -> Option<(usize, &T)> {
+        let mut best: Option<(usize, &T)> = None;
+        let mut pending = if self.nodes.is_empty() { Vec::new() } else { vec![0] };
+        while let Some(node) = pending.pop() {
+            let node = &self.nodes[node];
+            let distance = (self.distance)(node.value.as_ref(), query);
+            let better = match best {
+                None => true,
+                Some((best_distance, best_value)) => {
+                    (distance, node.value.as_ref()) < (best_distance, best_value.as_ref())
+                }
+            };
+            if better {
+                best = Some((distance, &node.value));
+            }
+            // The search radius shrinks as closer values are found
+            let radius = best.map_or(usize::MAX, |(best_distance, _)| best_distance);
+            for &(child_distance, child) in &node.children {
+                if child_distance.abs_diff(distance) <= radius {
+                    pending.push(child);
+                }
+            }
+        }
+        best
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        self.nodes.iter().map(|node| &node.value)
+    }
+}
+
+impl<T: AsRef<str>> Default for BkTree<T> {
+    fn default() -> Self {
+        BkTree::new()
+    }
+}
+
+impl<T: AsRef<str>> FromIterator<T> for BkTree<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = BkTree::new();
+        for value in iter {
+            tree.insert(value);
+        }
+        tree
+    }
+}
+
+/// A name close to what was typed, with how many edits away it is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Suggestion<'a> {
+    pub name: &'a str,
+    pub distance: usize,
+}
+
+// How many typos a query of `length` chars may have: one for short words, then one more for
+// every four chars, so "Lat" does not suggest "Tea" but "Capuccino" still finds "Cappuccino"
+pub fn max_typos(length: usize) -> usize {
+    (length / 4).max(1)
+}
+
+// The names that are close to `query`, best first. Case is ignored, so "latte" is an exact
+// match for "Latte", and names that start with the query rank right after the exact matches.
+pub fn fuzzy_find<'a>(query: &str, names: &BTreeSet<&'a str>) -> Vec<Suggestion<'a>> {
+    let query = case::fold_case(query.trim());
+    let max_distance = max_typos(query.chars().count());
+    let mut suggestions: Vec<(bool, Suggestion<'a>)> = names
+        .iter()
+        .filter_map(|&name| {
+            let folded = case::fold_case(name);
+            let distance = damerau_levenshtein(&query, &folded);
+            let is_prefix = !query.is_empty() && folded.starts_with(&query);
+            if distance <= max_distance || is_prefix {
+                Some((is_prefix && distance > 0, Suggestion { name, distance }))
+            } else {
+                None
+            }
+        })
+        .collect();
+    // A prefix match is what is being typed, so it beats a typo but not an exact match. The set
+    // is already in name order, and the sort is stable.
+    suggestions.sort_by_key(|&(is_prefix, suggestion)| (suggestion.distance > 0, !is_prefix, suggestion.distance));
+    suggestions.into_iter().map(|(_, suggestion)| suggestion).collect()
+}
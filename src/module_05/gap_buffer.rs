@@ -0,0 +1,267 @@
+// A gap buffer: the text container behind many text editors.
+//
+// The text is kept in one array with a hole (the gap) at the cursor. Typing fills the gap from
+// the front and backspace widens it again, so edits at the cursor never move any text. Moving
+// the cursor moves the gap, which only copies the text between the old and the new position.
+// Because editing tends to happen close to the previous edit, that cost stays small; it only
+// adds up like a `String::insert_str` when the edits jump around the whole document.
+
+use std::fmt;
+use std::ops::Range;
+use std::str;
+
+use super::text_buffer::TextBuffer;
+
+const MIN_GAP: usize = 64;
+
+pub struct GapBuffer {
+    // `buffer[..gap_start]` and `buffer[gap_end..]` are valid UTF-8, the gap in between is not
+    // part of the text
+    buffer: Vec<u8>,
+    gap_start: usize,
+    gap_end: usize,
+    bytes_moved: usize,
+}
+
+impl GapBuffer {
+    pub fn new() -> GapBuffer {
+        GapBuffer::with_capacity(0)
+    }
+
+    pub fn with_capacity(capacity: usize) -> GapBuffer {
+        GapBuffer { buffer: vec![0; capacity], gap_start: 0, gap_end: capacity, bytes_moved: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len() - self.gap_len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn gap_len(&self) -> usize {
+        self.gap_end - self.gap_start
+    }
+
+    // The byte offset of the cursor, which is where the gap is
+    pub fn cursor(&self) -> usize {
+        self.gap_start
+    }
+
+    // How many bytes had to be copied to move the gap around, growing the buffer included
+    pub fn bytes_moved(&self) -> usize {
+        self.bytes_moved
+    }
+
+    // The text before and after the cursor
+    pub fn as_slices(&self) -> (&str, &str) {
+        unsafe {
+            (
+                str::from_utf8_unchecked(&self.buffer[..self.gap_start]),
+                str::from_utf8_unchecked(&self.buffer[self.gap_end..]),
+            )
+        }
+    }
+
+    pub fn set_cursor(&mut self, position: usize) {
+        assert!(position <= self.len(), "cursor position {position} is out of bounds");
+        assert!(self.is_char_boundary(position), "cursor position {position} is not a char boundary");
+        if position < self.gap_start {
+            // The text between the new position and the gap moves behind the gap
+            let count = self.gap_start - position;
+            self.buffer.copy_within(position..self.gap_start, self.gap_end - count);
+            self.gap_start -= count;
+            self.gap_end -= count;
+            self.bytes_moved += count;
+        } else if position > self.gap_start {
+            let count = position - self.gap_start;
+            self.buffer.copy_within(self.gap_end..self.gap_end + count, self.gap_start);
+            self.gap_start += count;
+            self.gap_end += count;
+            self.bytes_moved += count;
+        }
+    }
+
+    pub fn move_left(&mut self) -> bool {
+        match self.as_slices().0.chars().next_back() {
+            Some(ch) => {
+                self.set_cursor(self.gap_start - ch.len_utf8());
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn move_right(&mut self) -> bool {
+        match self.as_slices().1.chars().next() {
+            Some(ch) => {
+                self.set_cursor(self.gap_start + ch.len_utf8());
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn move_to_start(&mut self) {
+        self.set_cursor(0);
+    }
+
+    pub fn move_to_end(&mut self) {
+        self.set_cursor(self.len());
+    }
+
+    // Makes the gap at least `additional` bytes long. The buffer at least doubles, so typing
+    // into a full buffer is amortized O(1) per char.
+    fn reserve(&mut self, additional: usize) {
+        if self.gap_len() >= additional {
+            return;
+        }
+        let new_capacity = (self.buffer.len() * 2).max(self.len() + additional + MIN_GAP);
+        let after = self.buffer.len() - self.gap_end;
+        self.buffer.resize(new_capacity, 0);
+        self.buffer.copy_within(self.gap_end..self.gap_end + after, new_capacity - after);
+        self.gap_end = new_capacity - after;
+        self.bytes_moved += after;
+    }
+
+    pub fn insert_at_cursor(&mut self, text: &str) {
+        self.reserve(text.len());
+        self.buffer[self.gap_start..self.gap_start + text.len()].copy_from_slice(text.as_bytes());
+        self.gap_start += text.len();
+    }
+
+    // Backspace
+    pub fn delete_backward(&mut self) -> Option<char> {
+        let ch = self.as_slices().0.chars().next_back()?;
+        self.gap_start -= ch.len_utf8();
+        Some(ch)
+    }
+
+    // Delete
+    pub fn delete_forward(&mut self) -> Option<char> {
+        let ch = self.as_slices().1.chars().next()?;
+        self.gap_end += ch.len_utf8();
+        Some(ch)
+    }
+
+    pub fn is_char_boundary(&self, index: usize) -> bool {
+        let (before, after) = self.as_slices();
+        if index <= before.len() {
+            before.is_char_boundary(index)
+        } else {
+            after.is_char_boundary(index - before.len())
+        }
+    }
+
+    // The whole text as one string slice. The gap, and with it the cursor, moves to the end.
+    pub fn make_contiguous(&mut self) -> &str {
+        self.move_to_end();
+        self.as_slices().0
+    }
+}
+
+impl TextBuffer for GapBuffer {
+    fn len(&self) -> usize {
+        GapBuffer::len(self)
+    }
+
+    fn char_at(&self, index: usize) -> Option<char> {
+        let (before, after) = self.as_slices();
+        if index < before.len() {
+            before[index..].chars().next()
+        } else {
+            after[index - before.len()..].chars().next()
+        }
+    }
+
+    fn char_before(&self, index: usize) -> Option<char> {
+        let (before, after) = self.as_slices();
+        if index <= before.len() {
+            before[..index].chars().next_back()
+        } else {
+            after[..index - before.len()].chars().next_back()
+        }
+    }
+
+    fn insert_str(&mut self, index: usize, text: &str) {
+        self.set_cursor(index);
+        self.insert_at_cursor(text);
+    }
+
+    fn delete(&mut self, range: Range<usize>) {
+        assert!(range.start <= range.end && range.end <= self.len(), "range {range:?} is out of bounds");
+        assert!(self.is_char_boundary(range.end), "range end {} is not a char boundary", range.end);
+        self.set_cursor(range.start);
+        self.gap_end += range.end - range.start;
+    }
+
+    fn find(&self, pattern: &str) -> Option<usize> {
+        let (before, after) = self.as_slices();
+        if let Some(index) = before.find(pattern) {
+            return Some(index);
+        }
+
+        // A match that starts before the gap and ends after it is at most one pattern long
+        // on either side
+        if !pattern.is_empty() && !after.is_empty() {
+            let mut start = before.len().saturating_sub(pattern.len() - 1);
+            while !before.is_char_boundary(start) {
+                start += 1;
+            }
+            let mut end = (pattern.len() - 1).min(after.len());
+            while !after.is_char_boundary(end) {
+                end += 1;
+            }
+            let window = format!("{}{}", &before[start..], &after[..end]);
+            if let Some(index) = window.find(pattern) {
+                return Some(start + index);
+            }
+        }
+
+        after.find(pattern).map(|index| before.len() + index)
+    }
+}
+
+impl Default for GapBuffer {
+    fn default() -> Self {
+        GapBuffer::new()
+    }
+}
+
+// The cursor starts at the end, like after typing the text
+impl From<&str> for GapBuffer {
+    fn from(text: &str) -> Self {
+        let mut buffer = GapBuffer::with_capacity(text.len() + MIN_GAP);
+        buffer.insert_at_cursor(text);
+        buffer
+    }
+}
+
+impl From<GapBuffer> for String {
+    fn from(mut buffer: GapBuffer) -> Self {
+        buffer.move_to_end();
+        buffer.buffer.truncate(buffer.gap_start);
+        unsafe { String::from_utf8_unchecked(buffer.buffer) }
+    }
+}
+
+impl fmt::Display for GapBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (before, after) = self.as_slices();
+        f.write_str(before)?;
+        f.write_str(after)
+    }
+}
+
+// Shows where the cursor is
+impl fmt::Debug for GapBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (before, after) = self.as_slices();
+        write!(f, "{:?}|{:?}", before, after)
+    }
+}
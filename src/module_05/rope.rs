@@ -0,0 +1,509 @@
+// A rope: a string stored as a balanced tree of small chunks.
+//
+// `String::insert_str`, `remove` and `replace_range` have to move every byte after the edit,
+// which is fine for a greeting but slow for a document of several megabytes. A `Rope` keeps the
+// text in chunks of at most `MAX_LEAF` bytes at the leaves of an AVL tree. Every node knows how
+// many bytes, chars and line breaks are below it, so finding a position, inserting, removing
+// and slicing only touch the nodes on one path from the root: O(log n) instead of O(n).
+//
+// Nodes are shared through `Rc`, so cloning and slicing a rope is cheap, and an edit only copies
+// the nodes it changes.
+
+use std::fmt;
+use std::ops::{Bound, RangeBounds};
+use std::rc::Rc;
+
+const MAX_LEAF: usize = 1024;
+
+#[derive(Clone)]
+enum Kind {
+    Leaf(String),
+    Branch(Rc<Node>, Rc<Node>),
+}
+
+#[derive(Clone)]
+struct Node {
+    bytes: usize,
+    chars: usize,
+    line_breaks: usize,
+    height: u32,
+    kind: Kind,
+}
+
+impl Node {
+    fn leaf(text: String) -> Rc<Node> {
+        Rc::new(Node {
+            bytes: text.len(),
+            chars: text.chars().count(),
+            line_breaks: text.bytes().filter(|&b| b == b'\n').count(),
+            height: 0,
+            kind: Kind::Leaf(text),
+        })
+    }
+
+    fn branch(left: Rc<Node>, right: Rc<Node>) -> Rc<Node> {
+        Rc::new(Node {
+            bytes: left.bytes + right.bytes,
+            chars: left.chars + right.chars,
+            line_breaks: left.line_breaks + right.line_breaks,
+            height: left.height.max(right.height) + 1,
+            kind: Kind::Branch(left, right),
+        })
+    }
+
+    fn children(&self) -> (Rc<Node>, Rc<Node>) {
+        match &self.kind {
+            Kind::Branch(left, right) => (left.clone(), right.clone()),
+            Kind::Leaf(_) => unreachable!("a leaf has no children"),
+        }
+    }
+
+    // Builds a perfectly balanced tree, `text` is cut into chunks on char boundaries
+    fn build(text: &str) -> Rc<Node> {
+        let mut leaves = Vec::new();
+        let mut rest = text;
+        while rest.len() > MAX_LEAF {
+            let mut end = MAX_LEAF;
+            while !rest.is_char_boundary(end) {
+                end -= 1;
+            }
+            leaves.push(Node::leaf(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+        leaves.push(Node::leaf(rest.to_string()));
+        Node::build_from_leaves(&leaves)
+    }
+
+    fn build_from_leaves(leaves: &[Rc<Node>]) -> Rc<Node> {
+        if leaves.len() == 1 {
+            return leaves[0].clone();
+        }
+        let (left, right) = leaves.split_at(leaves.len() / 2);
+        Node::branch(Node::build_from_leaves(left), Node::build_from_leaves(right))
+    }
+}
+
+// Joins two balanced trees whose heights differ by at most 2 with one or two rotations
+fn balance(left: Rc<Node>, right: Rc<Node>) -> Rc<Node> {
+    if left.height > right.height + 1 {
+        let (left_left, left_right) = left.children();
+        if left_left.height >= left_right.height {
+            Node::branch(left_left, Node::branch(left_right, right))
+        } else {
+            let (middle_left, middle_right) = left_right.children();
+            Node::branch(Node::branch(left_left, middle_left), Node::branch(middle_right, right))
+        }
+    } else if right.height > left.height + 1 {
+        let (right_left, right_right) = right.children();
+        if right_right.height >= right_left.height {
+            Node::branch(Node::branch(left, right_left), right_right)
+        } else {
+            let (middle_left, middle_right) = right_left.children();
+            Node::branch(Node::branch(left, middle_left), Node::branch(middle_right, right_right))
+        }
+    } else {
+        Node::branch(left, right)
+    }
+}
+
+// Concatenates two trees of any height: the shorter one is attached where the taller one's
+// spine has the same height, then every node on the way back up is rebalanced
+fn join(left: Rc<Node>, right: Rc<Node>) -> Rc<Node> {
+    if left.bytes == 0 {
+        return right;
+    }
+    if right.bytes == 0 {
+        return left;
+    }
+    if let (Kind::Leaf(a), Kind::Leaf(b)) = (&left.kind, &right.kind) {
+        if a.len() + b.len() <= MAX_LEAF {
+            return Node::leaf(format!("{a}{b}"));
+        }
+    }
+
+    if left.height > right.height + 1 {
+        let (left_left, left_right) = left.children();
+        balance(left_left, join(left_right, right))
+    } else if right.height > left.height + 1 {
+        let (right_left, right_right) = right.children();
+        balance(join(left, right_left), right_right)
+    } else {
+        Node::branch(left, right)
+    }
+}
+
+// Splits at a byte offset, which must be on a char boundary
+fn split(node: &Rc<Node>, at: usize) -> (Rc<Node>, Rc<Node>) {
+    if at == 0 {
+        return (Node::leaf(String::new()), node.clone());
+    }
+    if at >= node.bytes {
+        return (node.clone(), Node::leaf(String::new()));
+    }
+    match &node.kind {
+        Kind::Leaf(text) => (Node::leaf(text[..at].to_string()), Node::leaf(text[at..].to_string())),
+        Kind::Branch(left, right) => {
+            if at < left.bytes {
+                let (left_left, left_right) = split(left, at);
+                (left_left, join(left_right, right.clone()))
+            } else {
+                let (right_left, right_right) = split(right, at - left.bytes);
+                (join(left.clone(), right_left), right_right)
+            }
+        }
+    }
+}
+
+// Inserts text that fits into a leaf. A leaf that overflows turns into a branch of two leaves,
+// which makes the path one taller, so every node on the way back up is rebalanced like in an
+// AVL tree.
+fn insert_small(node: &Rc<Node>, at: usize, text: &str) -> Rc<Node> {
+    match &node.kind {
+        Kind::Leaf(leaf) => {
+            let mut combined = String::with_capacity(leaf.len() + text.len());
+            combined.push_str(&leaf[..at]);
+            combined.push_str(text);
+            combined.push_str(&leaf[at..]);
+            if combined.len() <= MAX_LEAF {
+                return Node::leaf(combined);
+            }
+            let mut middle = combined.len() / 2;
+            while !combined.is_char_boundary(middle) {
+                middle -= 1;
+            }
+            let right = combined.split_off(middle);
+            Node::branch(Node::leaf(combined), Node::leaf(right))
+        }
+        Kind::Branch(left, right) => {
+            if at <= left.bytes {
+                balance(insert_small(left, at, text), right.clone())
+            } else {
+                balance(left.clone(), insert_small(right, at - left.bytes, text))
+            }
+        }
+    }
+}
+
+fn char_to_byte(node: &Node, char_index: usize) -> usize {
+    match &node.kind {
+        Kind::Leaf(text) => text.char_indices().nth(char_index).map_or(text.len(), |(byte, _)| byte),
+        Kind::Branch(left, right) => {
+            if char_index < left.chars {
+                char_to_byte(left, char_index)
+            } else {
+                left.bytes + char_to_byte(right, char_index - left.chars)
+            }
+        }
+    }
+}
+
+// The char index right after the `count`-th line break, `count` is at least 1
+fn after_line_break(node: &Node, count: usize) -> usize {
+    match &node.kind {
+        Kind::Leaf(text) => {
+            let mut seen = 0;
+            for (index, ch) in text.chars().enumerate() {
+                if ch == '\n' {
+                    seen += 1;
+                    if seen == count {
+                        return index + 1;
+                    }
+                }
+            }
+            unreachable!("the leaf holds fewer line breaks than counted")
+        }
+        Kind::Branch(left, right) => {
+            if count <= left.line_breaks {
+                after_line_break(left, count)
+            } else {
+                left.chars + after_line_break(right, count - left.line_breaks)
+            }
+        }
+    }
+}
+
+// How many line breaks come before `char_index`
+fn line_breaks_before(node: &Node, char_index: usize) -> usize {
+    match &node.kind {
+        Kind::Leaf(text) => text.chars().take(char_index).filter(|&ch| ch == '\n').count(),
+        Kind::Branch(left, right) => {
+            if char_index < left.chars {
+                line_breaks_before(left, char_index)
+            } else {
+                left.line_breaks + line_breaks_before(right, char_index - left.chars)
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Rope {
+    root: Rc<Node>,
+}
+
+impl Rope {
+    pub fn new() -> Rope {
+        Rope { root: Node::leaf(String::new()) }
+    }
+
+    pub fn len_bytes(&self) -> usize {
+        self.root.bytes
+    }
+
+    pub fn len_chars(&self) -> usize {
+        self.root.chars
+    }
+
+    // Like an editor counts them: an empty rope has one line, and so does "abc\n" plus the
+    // empty line after the break
+    pub fn len_lines(&self) -> usize {
+        self.root.line_breaks + 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.bytes == 0
+    }
+
+    // The height of the tree, which stays within about 1.44 * log2 of the number of chunks
+    pub fn height(&self) -> u32 {
+        self.root.height
+    }
+
+    // Turns a range of char indices into byte offsets, panicking like slicing a `str` does
+    fn byte_range<R: RangeBounds<usize>>(&self, range: R) -> (usize, usize) {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.len_chars(),
+        };
+        assert!(
+            start <= end && end <= self.len_chars(),
+            "char range {start}..{end} is out of bounds for a rope of {} chars",
+            self.len_chars()
+        );
+        (self.char_to_byte(start), self.char_to_byte(end))
+    }
+
+    pub fn char_to_byte(&self, char_index: usize) -> usize {
+        assert!(char_index <= self.len_chars(), "char index {char_index} is out of bounds");
+        char_to_byte(&self.root, char_index)
+    }
+
+    pub fn char(&self, char_index: usize) -> char {
+        assert!(char_index < self.len_chars(), "char index {char_index} is out of bounds");
+        let mut node = &self.root;
+        let mut index = char_index;
+        loop {
+            match &node.kind {
+                Kind::Leaf(text) => return text.chars().nth(index).unwrap(),
+                Kind::Branch(left, right) => {
+                    if index < left.chars {
+                        node = left;
+                    } else {
+                        index -= left.chars;
+                        node = right;
+                    }
+                }
+            }
+        }
+    }
+
+    // The char index where line `line_index` starts
+    pub fn line_to_char(&self, line_index: usize) -> usize {
+        assert!(line_index < self.len_lines(), "line index {line_index} is out of bounds");
+        if line_index == 0 {
+            0
+        } else {
+            after_line_break(&self.root, line_index)
+        }
+    }
+
+    // The line that the char at `char_index` is on
+    pub fn char_to_line(&self, char_index: usize) -> usize {
+        assert!(char_index <= self.len_chars(), "char index {char_index} is out of bounds");
+        line_breaks_before(&self.root, char_index)
+    }
+
+    // Line `line_index`, including its line break if it has one
+    pub fn line(&self, line_index: usize) -> Rope {
+        let start = self.line_to_char(line_index);
+        let end = if line_index + 1 < self.len_lines() {
+            self.line_to_char(line_index + 1)
+        } else {
+            self.len_chars()
+        };
+        self.slice(start..end)
+    }
+
+    pub fn insert(&mut self, char_index: usize, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let at = self.char_to_byte(char_index);
+        self.root = if text.len() <= MAX_LEAF {
+            insert_small(&self.root, at, text)
+        } else {
+            let (left, right) = split(&self.root, at);
+            join(join(left, Node::build(text)), right)
+        };
+    }
+
+    pub fn insert_char(&mut self, char_index: usize, ch: char) {
+        self.insert(char_index, ch.encode_utf8(&mut [0; 4]));
+    }
+
+    pub fn push_str(&mut self, text: &str) {
+        self.insert(self.len_chars(), text);
+    }
+
+    pub fn push(&mut self, ch: char) {
+        self.insert_char(self.len_chars(), ch);
+    }
+
+    pub fn pop(&mut self) -> Option<char> {
+        let last = self.len_chars().checked_sub(1)?;
+        let ch = self.char(last);
+        self.remove(last..);
+        Some(ch)
+    }
+
+    pub fn remove<R: RangeBounds<usize>>(&mut self, char_range: R) {
+        let (start, end) = self.byte_range(char_range);
+        if start == end {
+            return;
+        }
+        let (left, rest) = split(&self.root, start);
+        let (_, right) = split(&rest, end - start);
+        self.root = join(left, right);
+    }
+
+    pub fn replace_range<R: RangeBounds<usize>>(&mut self, char_range: R, text: &str) {
+        let (start, end) = self.byte_range(char_range);
+        let (left, rest) = split(&self.root, start);
+        let (_, right) = split(&rest, end - start);
+        let left = if text.is_empty() { left } else { join(left, Node::build(text)) };
+        self.root = join(left, right);
+    }
+
+    // A new rope sharing all the chunks inside the range with this one
+    pub fn slice<R: RangeBounds<usize>>(&self, char_range: R) -> Rope {
+        let (start, end) = self.byte_range(char_range);
+        let (rest, _) = split(&self.root, end);
+        let (_, slice) = split(&rest, start);
+        Rope { root: slice }
+    }
+
+    pub fn chunks(&self) -> Chunks<'_> {
+        Chunks { stack: vec![&self.root] }
+    }
+
+    pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        self.chunks().flat_map(str::chars)
+    }
+
+    // The char index of the first match of `pattern`, comparing char by char (see the `search`
+    // module for faster ways to do this)
+    pub fn find(&self, pattern: &str) -> Option<usize> {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let mut window: Vec<char> = Vec::with_capacity(pattern.len());
+        for (index, ch) in self.chars().enumerate() {
+            if window.len() == pattern.len() {
+                window.remove(0);
+            }
+            window.push(ch);
+            if window == pattern {
+                return Some(index + 1 - pattern.len());
+            }
+        }
+        if pattern.is_empty() { Some(0) } else { None }
+    }
+}
+
+impl Default for Rope {
+    fn default() -> Self {
+        Rope::new()
+    }
+}
+
+impl From<&str> for Rope {
+    fn from(text: &str) -> Self {
+        Rope { root: Node::build(text) }
+    }
+}
+
+impl From<String> for Rope {
+    fn from(text: String) -> Self {
+        Rope::from(text.as_str())
+    }
+}
+
+impl From<&Rope> for String {
+    fn from(rope: &Rope) -> Self {
+        let mut text = String::with_capacity(rope.len_bytes());
+        text.extend(rope.chunks());
+        text
+    }
+}
+
+impl fmt::Display for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in self.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&String::from(self), f)
+    }
+}
+
+impl PartialEq for Rope {
+    fn eq(&self, other: &Self) -> bool {
+        self.len_bytes() == other.len_bytes() && self.chars().eq(other.chars())
+    }
+}
+
+impl Eq for Rope {}
+
+impl PartialEq<str> for Rope {
+    fn eq(&self, other: &str) -> bool {
+        self.len_bytes() == other.len() && self.chars().eq(other.chars())
+    }
+}
+
+impl PartialEq<&str> for Rope {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+/// The chunks of a rope in order, as the string slices they are stored in.
+pub struct Chunks<'a> {
+    stack: Vec<&'a Rc<Node>>,
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        while let Some(node) = self.stack.pop() {
+            match &node.kind {
+                Kind::Leaf(text) if text.is_empty() => continue,
+                Kind::Leaf(text) => return Some(text),
+                Kind::Branch(left, right) => {
+                    self.stack.push(right);
+                    self.stack.push(left);
+                }
+            }
+        }
+        None
+    }
+}
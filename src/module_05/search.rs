@@ -0,0 +1,272 @@
+// String search algorithms that find every match of a pattern in one pass over the text.
+//
+// `str::find` answers "where is the first match", and looking for several patterns means one
+// scan of the text per pattern. The searchers here do some work on the patterns up front and then
+// report all matches in a single scan:
+//
+// - `Kmp` (Knuth-Morris-Pratt) never looks at a byte of the text twice. After a mismatch it knows
+//   from the pattern alone how much of it still matches, so the scan is O(text + pattern).
+// - `Horspool` (Boyer-Moore-Horspool) compares from the end of the pattern and, on a mismatch,
+//   skips ahead by up to a whole pattern length. It is sublinear on typical text and long
+//   patterns, but O(text * pattern) in the worst case.
+// - `AhoCorasick` builds a trie of many patterns with failure links, the multi-pattern version of
+//   KMP. One scan finds every match of every pattern, however many patterns there are.
+//
+// All of them work on bytes. Patterns are `&str`, so a match always starts and ends on a char
+// boundary: no char's UTF-8 encoding appears in the middle of another's. Positions are byte
+// offsets like the ones `str::find` returns, and overlapping matches are all reported ("aa" is
+// found at 0, 1 and 2 in "aaaa", where `match_indices` gives 0 and 2).
+
+use std::collections::VecDeque;
+
+// An empty pattern matches at every char boundary, like it does for `str::match_indices`
+fn empty_matches(text: &str) -> Vec<usize> {
+    (0..=text.len()).filter(|&index| text.is_char_boundary(index)).collect()
+}
+
+/// A pattern prepared for Knuth-Morris-Pratt search.
+pub struct Kmp {
+    pattern: Vec<u8>,
+    // `prefix[i]` is the length of the longest proper prefix of `pattern[..=i]` that is also a
+    // suffix of it: how much of the pattern is still matched after a mismatch at `i + 1`
+    prefix: Vec<usize>,
+}
+
+impl Kmp {
+    pub fn new(pattern: &str) -> Kmp {
+        let pattern = pattern.as_bytes().to_vec();
+        let mut prefix = vec![0; pattern.len()];
+        let mut matched = 0;
+        for i in 1..pattern.len() {
+            while matched > 0 && pattern[i] != pattern[matched] {
+                matched = prefix[matched - 1];
+            }
+            if pattern[i] == pattern[matched] {
+                matched += 1;
+            }
+            prefix[i] = matched;
+        }
+        Kmp { pattern, prefix }
+    }
+
+    pub fn find(&self, text: &str) -> Option<usize> {
+        self.scan(text, true).into_iter().next()
+    }
+
+    pub fn find_all(&self, text: &str) -> Vec<usize> {
+        self.scan(text, false)
+    }
+
+    fn scan(&self, text: &str, first_only: bool) -> Vec<usize> {
+        if self.pattern.is_empty() {
+            return empty_matches(text);
+        }
+        let mut matches = Vec::new();
+        let mut matched = 0;
+        for (index, &byte) in text.as_bytes().iter().enumerate() {
+            while matched > 0 && byte != self.pattern[matched] {
+                matched = self.prefix[matched - 1];
+            }
+            if byte == self.pattern[matched] {
+                matched += 1;
+            }
+            if matched == self.pattern.len() {
+                matches.push(index + 1 - matched);
+                if first_only {
+                    break;
+                }
+                matched = self.prefix[matched - 1];
+            }
+        }
+        matches
+    }
+}
+
+/// A pattern prepared for Boyer-Moore-Horspool search.
+pub struct Horspool {
+    pattern: Vec<u8>,
+    // How far the pattern can move when the text byte under its last position is `byte`
+    shift: [usize; 256],
+}
+
+impl Horspool {
+    pub fn new(pattern: &str) -> Horspool {
+        let pattern = pattern.as_bytes().to_vec();
+        let mut shift = [pattern.len(); 256];
+        // The last byte is left out, or a match of it would give a shift of zero
+        for (index, &byte) in pattern.iter().enumerate().take(pattern.len().saturating_sub(1)) {
+            shift[byte as usize] = pattern.len() - 1 - index;
+        }
+        Horspool { pattern, shift }
+    }
+
+    pub fn find(&self, text: &str) -> Option<usize> {
+        self.scan(text, true).into_iter().next()
+    }
+
+    pub fn find_all(&self, text: &str) -> Vec<usize> {
+        self.scan(text, false)
+    }
+
+    fn scan(&self, text: &str, first_only: bool) -> Vec<usize> {
+        if self.pattern.is_empty() {
+            return empty_matches(text);
+        }
+        let text = text.as_bytes();
+        let last = self.pattern.len() - 1;
+        let mut matches = Vec::new();
+        let mut start = 0;
+        while start + self.pattern.len() <= text.len() {
+            let window = &text[start..start + self.pattern.len()];
+            // Checking the last byte first rules out most windows with a single comparison
+            if window[last] == self.pattern[last] && window[..last] == self.pattern[..last] {
+                matches.push(start);
+                if first_only {
+                    break;
+                }
+            }
+            start += self.shift[window[last] as usize];
+        }
+        matches
+    }
+}
+
+/// One match found by `AhoCorasick`: which pattern, and the bytes of the text it covers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Match {
+    pub pattern: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+const ROOT: usize = 0;
+const ALPHABET: usize = 256;
+
+struct Node {
+    // Sorted by byte, most nodes only have one or two children
+    children: Vec<(u8, usize)>,
+    // The node for the longest proper suffix of this node's text that is also in the trie
+    fail: usize,
+    // The pattern that ends at this node, if any
+    pattern: Option<usize>,
+    // The closest node along the failure links that ends a pattern, so the matches at a
+    // position are found without walking through the nodes that do not end one
+    output: Option<usize>,
+    depth: usize,
+}
+
+impl Node {
+    fn new(depth: usize) -> Node {
+        Node { children: Vec::new(), fail: ROOT, pattern: None, output: None, depth }
+    }
+
+    fn child(&self, byte: u8) -> Option<usize> {
+        self.children
+            .binary_search_by_key(&byte, |&(key, _)| key)
+            .ok()
+            .map(|index| self.children[index].1)
+    }
+}
+
+/// An automaton that finds every match of a set of patterns in one pass.
+pub struct AhoCorasick {
+    nodes: Vec<Node>,
+    // The next node for every node and byte, `ALPHABET` entries per node. With the failure links
+    // already followed, reading a byte of the text is a single lookup.
+    transitions: Vec<u32>,
+    patterns: Vec<String>,
+}
+
+impl AhoCorasick {
+    pub fn new<I>(patterns: I) -> AhoCorasick
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let patterns: Vec<String> = patterns.into_iter().map(|pattern| pattern.as_ref().to_string()).collect();
+        let mut nodes = vec![Node::new(0)];
+
+        for (id, pattern) in patterns.iter().enumerate() {
+            let mut node = ROOT;
+            for &byte in pattern.as_bytes() {
+                node = match nodes[node].child(byte) {
+                    Some(child) => child,
+                    None => {
+                        let child = nodes.len();
+                        nodes.push(Node::new(nodes[node].depth + 1));
+                        let children = &mut nodes[node].children;
+                        let index = children.partition_point(|&(key, _)| key < byte);
+                        children.insert(index, (byte, child));
+                        child
+                    }
+                };
+            }
+            // The same pattern twice is reported under its first index
+            nodes[node].pattern.get_or_insert(id);
+        }
+
+        // Failure links point to shallower nodes, so they are filled in breadth first. A node
+        // reads a byte it has no child for the same way the node its failure link points to
+        // does, so its row of the table starts as a copy of that node's row.
+        let mut transitions = vec![ROOT as u32; nodes.len() * ALPHABET];
+        let mut queue = VecDeque::from([ROOT]);
+        nodes[ROOT].output = nodes[ROOT].pattern.map(|_| ROOT);
+        while let Some(node) = queue.pop_front() {
+            let fail = nodes[node].fail;
+            if node != ROOT {
+                transitions.copy_within(fail * ALPHABET..(fail + 1) * ALPHABET, node * ALPHABET);
+            }
+            for index in 0..nodes[node].children.len() {
+                let (byte, child) = nodes[node].children[index];
+                // The root's children can only fall back to the root itself
+                let child_fail = if node == ROOT { ROOT } else { transitions[fail * ALPHABET + byte as usize] as usize };
+                nodes[child].fail = child_fail;
+                nodes[child].output = if nodes[child].pattern.is_some() { Some(child) } else { nodes[child_fail].output };
+                transitions[node * ALPHABET + byte as usize] = child as u32;
+                queue.push_back(child);
+            }
+        }
+
+        AhoCorasick { nodes, transitions, patterns }
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    // Every match, ordered by where it ends and then from the longest to the shortest pattern
+    pub fn find_all(&self, text: &str) -> Vec<Match> {
+        let mut matches = Vec::new();
+        self.for_each_match(text, |found| matches.push(found));
+        matches
+    }
+
+    fn for_each_match(&self, text: &str, mut report: impl FnMut(Match)) {
+        let mut node = ROOT;
+        self.report_matches(node, 0, &mut report);
+        for (index, &byte) in text.as_bytes().iter().enumerate() {
+            node = self.transitions[node * ALPHABET + byte as usize] as usize;
+            // Only an empty pattern can end inside a char, and like in `Kmp` it should not
+            if self.nodes[node].output.is_some() && text.is_char_boundary(index + 1) {
+                self.report_matches(node, index + 1, &mut report);
+            }
+        }
+    }
+
+    fn report_matches(&self, node: usize, end: usize, report: &mut impl FnMut(Match)) {
+        let mut output = self.nodes[node].output;
+        while let Some(found) = output {
+            let found_node = &self.nodes[found];
+            let pattern = found_node.pattern.expect("output links only point to pattern ends");
+            report(Match { pattern, start: end - found_node.depth, end });
+            output = if found == ROOT { None } else { self.nodes[found_node.fail].output };
+        }
+    }
+
+    // How many times each pattern occurs, indexed like the patterns
+    pub fn count_matches(&self, text: &str) -> Vec<usize> {
+        let mut counts = vec![0; self.patterns.len()];
+        self.for_each_match(text, |found| counts[found.pattern] += 1);
+        counts
+    }
+}
@@ -0,0 +1,243 @@
+// Unicode-aware indexing: bytes, chars and grapheme clusters.
+//
+// A `String` is indexed by bytes, and `chars()` walks Unicode scalar values. Neither is what a
+// reader calls a character: "é" can be one char or an "e" followed by a combining accent, and
+// "👩‍☕" (woman barista) is three chars joined into one symbol. Those user-perceived characters
+// are grapheme clusters, and where one ends is defined by the extended grapheme cluster rules of
+// Unicode Standard Annex #29. This module implements those rules (GB3 to GB999) on top of the
+// property tables in `tables.rs`, and builds safe indexing helpers on them.
+//
+// The tables are from Unicode 14.0, so it does not know the conjunct rule (GB9c) that was added
+// in Unicode 15.1. Case conversion lives in `case`.
+
+pub mod case;
+mod case_tables;
+mod tables;
+
+use std::cmp::Ordering;
+
+/// The Grapheme_Cluster_Break property of a char, plus Extended_Pictographic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GraphemeCategory {
+    Other,
+    Cr,
+    Lf,
+    Control,
+    Extend,
+    Zwj,
+    RegionalIndicator,
+    Prepend,
+    SpacingMark,
+    // Hangul jamo: leading consonant, vowel, trailing consonant, and the precomposed syllables
+    L,
+    V,
+    T,
+    Lv,
+    Lvt,
+    ExtendedPictographic,
+}
+
+const HANGUL_SYLLABLES_START: u32 = 0xAC00;
+const HANGUL_SYLLABLES_END: u32 = 0xD7A3;
+const HANGUL_TRAILING_COUNT: u32 = 28;
+
+pub fn category(ch: char) -> GraphemeCategory {
+    let code = ch as u32;
+    // Every 28th syllable has no trailing consonant
+    if (HANGUL_SYLLABLES_START..=HANGUL_SYLLABLES_END).contains(&code) {
+        return if (code - HANGUL_SYLLABLES_START).is_multiple_of(HANGUL_TRAILING_COUNT) {
+            GraphemeCategory::Lv
+        } else {
+            GraphemeCategory::Lvt
+        };
+    }
+
+    let found = tables::GRAPHEME_CATEGORIES.binary_search_by(|&(start, end, _)| {
+        if end < code {
+            Ordering::Less
+        } else if start > code {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        }
+    });
+    match found {
+        Ok(index) => tables::GRAPHEME_CATEGORIES[index].2,
+        Err(_) => GraphemeCategory::Other,
+    }
+}
+
+/// The version of the Unicode Character Database the tables come from.
+pub fn unicode_version() -> (u8, u8, u8) {
+    tables::UNICODE_VERSION
+}
+
+// What the rules need to know about the chars before a possible boundary
+struct BreakState {
+    previous: GraphemeCategory,
+    // The chars so far are Extended_Pictographic Extend*
+    in_emoji: bool,
+    // ... followed by a ZWJ
+    emoji_zwj: bool,
+    // How many Regional_Indicators are directly before the boundary
+    regional_indicators: usize,
+}
+
+impl BreakState {
+    fn new(first: GraphemeCategory) -> BreakState {
+        BreakState {
+            previous: first,
+            in_emoji: first == GraphemeCategory::ExtendedPictographic,
+            emoji_zwj: false,
+            regional_indicators: (first == GraphemeCategory::RegionalIndicator) as usize,
+        }
+    }
+
+    // Whether there is a boundary between the chars seen so far and a char of category `next`
+    fn is_break(&self, next: GraphemeCategory) -> bool {
+        use GraphemeCategory::*;
+        match (self.previous, next) {
+            // GB3: keep CR LF together
+            (Cr, Lf) => false,
+            // GB4, GB5: break around controls
+            (Control | Cr | Lf, _) | (_, Control | Cr | Lf) => true,
+            // GB6 to GB8: keep Hangul syllable sequences together
+            (L, L | V | Lv | Lvt) | (Lv | V, V | T) | (Lvt | T, T) => false,
+            // GB9, GB9a: no break before extending chars and spacing marks
+            (_, Extend | Zwj | SpacingMark) => false,
+            // GB9b: no break after a prepended char
+            (Prepend, _) => false,
+            // GB11: keep emoji ZWJ sequences together
+            (Zwj, ExtendedPictographic) if self.emoji_zwj => false,
+            // GB12, GB13: flags are pairs of regional indicators
+            (RegionalIndicator, RegionalIndicator) => self.regional_indicators.is_multiple_of(2),
+            // GB999: break everywhere else
+            _ => true,
+        }
+    }
+
+    fn advance(&mut self, next: GraphemeCategory) {
+        use GraphemeCategory::*;
+        self.emoji_zwj = self.in_emoji && next == Zwj;
+        self.in_emoji = match next {
+            ExtendedPictographic => true,
+            Extend => self.in_emoji,
+            _ => false,
+        };
+        self.regional_indicators = if next == RegionalIndicator { self.regional_indicators + 1 } else { 0 };
+        self.previous = next;
+    }
+}
+
+// The byte length of the first grapheme cluster in `text`
+fn first_grapheme_len(text: &str) -> usize {
+    let mut chars = text.char_indices();
+    let Some((_, first)) = chars.next() else {
+        return 0;
+    };
+    let mut state = BreakState::new(category(first));
+    for (index, ch) in chars {
+        let next = category(ch);
+        if state.is_break(next) {
+            return index;
+        }
+        state.advance(next);
+    }
+    text.len()
+}
+
+/// The grapheme clusters of a string, in order.
+pub struct Graphemes<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Iterator for Graphemes<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let (grapheme, rest) = self.rest.split_at(first_grapheme_len(self.rest));
+        self.rest = rest;
+        Some(grapheme)
+    }
+
+    // At least one byte per cluster, at most four
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.rest.len().div_ceil(4), Some(self.rest.len()))
+    }
+}
+
+pub fn graphemes(text: &str) -> Graphemes<'_> {
+    Graphemes { rest: text }
+}
+
+// Each grapheme cluster with the byte offset it starts at
+pub fn grapheme_indices(text: &str) -> impl Iterator<Item = (usize, &str)> + '_ {
+    graphemes(text).map(move |grapheme| (grapheme.as_ptr() as usize - text.as_ptr() as usize, grapheme))
+}
+
+pub fn grapheme_len(text: &str) -> usize {
+    graphemes(text).count()
+}
+
+pub fn nth_grapheme(text: &str, n: usize) -> Option<&str> {
+    graphemes(text).nth(n)
+}
+
+// Keeps the first `count` grapheme clusters. Unlike `String::truncate` it can not cut a
+// character in half, neither a multi-byte char nor a cluster of several chars.
+pub fn truncate_graphemes(text: &mut String, count: usize) {
+    let end = grapheme_to_byte(text, count).unwrap_or(text.len());
+    text.truncate(end);
+}
+
+pub fn is_grapheme_boundary(text: &str, byte_index: usize) -> bool {
+    byte_index == text.len() || grapheme_indices(text).any(|(start, _)| start == byte_index)
+}
+
+// The conversions below return `None` for positions past the end of the text, and the length
+// of the text in the target unit for a position right at the end.
+
+pub fn char_to_byte(text: &str, char_index: usize) -> Option<usize> {
+    text.char_indices()
+        .map(|(byte, _)| byte)
+        .chain(Some(text.len()))
+        .nth(char_index)
+}
+
+// `None` if `byte_index` is in the middle of a char
+pub fn byte_to_char(text: &str, byte_index: usize) -> Option<usize> {
+    if byte_index > text.len() || !text.is_char_boundary(byte_index) {
+        return None;
+    }
+    Some(text[..byte_index].chars().count())
+}
+
+pub fn grapheme_to_byte(text: &str, grapheme_index: usize) -> Option<usize> {
+    grapheme_indices(text)
+        .map(|(byte, _)| byte)
+        .chain(Some(text.len()))
+        .nth(grapheme_index)
+}
+
+// The grapheme cluster that contains the byte at `byte_index`
+pub fn byte_to_grapheme(text: &str, byte_index: usize) -> Option<usize> {
+    if byte_index > text.len() {
+        return None;
+    }
+    if byte_index == text.len() {
+        return Some(grapheme_len(text));
+    }
+    grapheme_indices(text).position(|(start, grapheme)| byte_index < start + grapheme.len())
+}
+
+pub fn grapheme_to_char(text: &str, grapheme_index: usize) -> Option<usize> {
+    grapheme_to_byte(text, grapheme_index).and_then(|byte| byte_to_char(text, byte))
+}
+
+// The grapheme cluster that contains the char at `char_index`
+pub fn char_to_grapheme(text: &str, char_index: usize) -> Option<usize> {
+    char_to_byte(text, char_index).and_then(|byte| byte_to_grapheme(text, byte))
+}
@@ -0,0 +1,244 @@
+// Unicode case conversion and case-insensitive comparison.
+//
+// `make_ascii_lowercase` and `make_ascii_uppercase` only touch 'A'..'Z' and 'a'..'z', so "CAFÉ"
+// becomes "cafÉ". The full mappings can change the length of a string ("ß" is "SS" in upper case)
+// and depend on context (a Greek capital sigma at the end of a word is "ς", not "σ"). Upper and
+// lower case come from the standard library, which implements both. Title case and case folding
+// are missing there and use the tables in `case_tables.rs`.
+//
+// Case folding is the mapping meant for comparisons: two strings are equal ignoring case when
+// their folded forms are equal. It is not the same as lower case, e.g. "ß" folds to "ss".
+
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+
+use super::case_tables::{CASE_FOLDING, TITLE_CASE};
+
+fn lookup(table: &'static [(char, &'static str)], ch: char) -> Option<&'static str> {
+    table
+        .binary_search_by_key(&ch, |&(from, _)| from)
+        .ok()
+        .map(|index| table[index].1)
+}
+
+// One char can fold to up to three
+pub fn fold_char(ch: char) -> impl Iterator<Item = char> {
+    let folded = lookup(CASE_FOLDING, ch);
+    let unchanged = if folded.is_none() { Some(ch) } else { None };
+    folded.map(str::chars).into_iter().flatten().chain(unchanged)
+}
+
+pub fn fold_chars(text: &str) -> impl Iterator<Item = char> + '_ {
+    text.chars().flat_map(fold_char)
+}
+
+pub fn fold_case(text: &str) -> String {
+    fold_chars(text).collect()
+}
+
+pub fn to_lowercase(text: &str) -> String {
+    text.to_lowercase()
+}
+
+pub fn to_uppercase(text: &str) -> String {
+    text.to_uppercase()
+}
+
+// Most letters look the same in title case and in upper case, the exceptions are digraphs like
+// "ǆ" (title case "ǅ") and ligatures like "ﬀ" (title case "Ff")
+pub fn titlecase_char(ch: char) -> impl Iterator<Item = char> {
+    let title = lookup(TITLE_CASE, ch);
+    let upper = if title.is_none() { Some(ch.to_uppercase()) } else { None };
+    title.map(str::chars).into_iter().flatten().chain(upper.into_iter().flatten())
+}
+
+// The first letter of every word in title case, the rest in lower case. Words are separated by
+// white space.
+pub fn to_titlecase(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while !rest.is_empty() {
+        let word_start = rest.find(|ch: char| !ch.is_whitespace()).unwrap_or(rest.len());
+        result.push_str(&rest[..word_start]);
+        rest = &rest[word_start..];
+
+        let word_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let word = &rest[..word_end];
+        // The title case letter is the first one that has case, so "'s" or "¿qué" keep their
+        // leading punctuation
+        match word.char_indices().find(|&(_, ch)| ch.is_lowercase() || ch.is_uppercase() || lookup(TITLE_CASE, ch).is_some()) {
+            Some((index, first)) => {
+                result.push_str(&word[..index]);
+                result.extend(titlecase_char(first));
+                // `to_lowercase` on the whole rest, so a final sigma is still recognized
+                result.push_str(&word[index + first.len_utf8()..].to_lowercase());
+            }
+            None => result.push_str(word),
+        }
+        rest = &rest[word_end..];
+    }
+    result
+}
+
+pub fn eq_ignore_case(a: &str, b: &str) -> bool {
+    fold_chars(a).eq(fold_chars(b))
+}
+
+pub fn cmp_ignore_case(a: &str, b: &str) -> Ordering {
+    fold_chars(a).cmp(fold_chars(b))
+}
+
+/// A string slice that compares, hashes and orders by its case folded form.
+#[derive(Debug)]
+#[repr(transparent)]
+pub struct CaseInsensitiveStr(str);
+
+impl CaseInsensitiveStr {
+    pub fn new(text: &str) -> &CaseInsensitiveStr {
+        // `repr(transparent)` makes both the same type in memory
+        unsafe { &*(text as *const str as *const CaseInsensitiveStr) }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for CaseInsensitiveStr {
+    fn eq(&self, other: &Self) -> bool {
+        eq_ignore_case(&self.0, &other.0)
+    }
+}
+
+impl Eq for CaseInsensitiveStr {}
+
+impl PartialOrd for CaseInsensitiveStr {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CaseInsensitiveStr {
+    fn cmp(&self, other: &Self) -> Ordering {
+        cmp_ignore_case(&self.0, &other.0)
+    }
+}
+
+// Hashes the folded chars, so that strings that are equal ignoring case hash the same
+impl Hash for CaseInsensitiveStr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for ch in fold_chars(&self.0) {
+            state.write_u32(ch as u32);
+        }
+        // End with a marker like `str` does, so ("ab", "c") and ("a", "bc") hash differently as
+        // parts of a tuple
+        state.write_u8(0xff);
+    }
+}
+
+impl fmt::Display for CaseInsensitiveStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+/// An owned string that compares, hashes and orders ignoring case, for use as a map key.
+///
+/// It keeps the text as it was given, only comparisons ignore the case. Maps keyed by it can be
+/// searched with a `&CaseInsensitiveStr` without allocating.
+#[derive(Clone, Default)]
+pub struct CaseInsensitiveString(String);
+
+impl CaseInsensitiveString {
+    pub fn new(text: impl Into<String>) -> CaseInsensitiveString {
+        CaseInsensitiveString(text.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl Deref for CaseInsensitiveString {
+    type Target = CaseInsensitiveStr;
+
+    fn deref(&self) -> &CaseInsensitiveStr {
+        CaseInsensitiveStr::new(&self.0)
+    }
+}
+
+impl Borrow<CaseInsensitiveStr> for CaseInsensitiveString {
+    fn borrow(&self) -> &CaseInsensitiveStr {
+        self
+    }
+}
+
+impl From<&str> for CaseInsensitiveString {
+    fn from(text: &str) -> Self {
+        CaseInsensitiveString(String::from(text))
+    }
+}
+
+impl From<String> for CaseInsensitiveString {
+    fn from(text: String) -> Self {
+        CaseInsensitiveString(text)
+    }
+}
+
+impl PartialEq for CaseInsensitiveString {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for CaseInsensitiveString {}
+
+impl PartialEq<str> for CaseInsensitiveString {
+    fn eq(&self, other: &str) -> bool {
+        eq_ignore_case(&self.0, other)
+    }
+}
+
+impl PartialEq<&str> for CaseInsensitiveString {
+    fn eq(&self, other: &&str) -> bool {
+        eq_ignore_case(&self.0, other)
+    }
+}
+
+impl PartialOrd for CaseInsensitiveString {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CaseInsensitiveString {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+// Must hash like `CaseInsensitiveStr` for `Borrow` lookups to work
+impl Hash for CaseInsensitiveString {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state);
+    }
+}
+
+impl fmt::Debug for CaseInsensitiveString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for CaseInsensitiveString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
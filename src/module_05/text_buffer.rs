@@ -0,0 +1,93 @@
+// The editing operations the string lessons use, as a trait.
+//
+// `demo_strings` edits a `String` with `push_str`, `remove`, `pop`, `insert`, `insert_str` and
+// `replace_range`. `TextBuffer` describes all of them in terms of a few primitives, so the same
+// sequence of edits can run against a `String` and against other text containers such as
+// `GapBuffer`. Like on a `String`, positions are byte offsets and have to be on char boundaries.
+
+use std::fmt;
+use std::ops::Range;
+
+pub trait TextBuffer: fmt::Display {
+    // Length in bytes
+    fn len(&self) -> usize;
+
+    // The char starting at byte `index`
+    fn char_at(&self, index: usize) -> Option<char>;
+
+    // The char ending at byte `index`
+    fn char_before(&self, index: usize) -> Option<char>;
+
+    fn insert_str(&mut self, index: usize, text: &str);
+
+    fn delete(&mut self, range: Range<usize>);
+
+    // The byte offset of the first match of `pattern`
+    fn find(&self, pattern: &str) -> Option<usize>;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn push_str(&mut self, text: &str) {
+        self.insert_str(self.len(), text);
+    }
+
+    fn push(&mut self, ch: char) {
+        self.push_str(ch.encode_utf8(&mut [0; 4]));
+    }
+
+    fn insert(&mut self, index: usize, ch: char) {
+        self.insert_str(index, ch.encode_utf8(&mut [0; 4]));
+    }
+
+    fn remove(&mut self, index: usize) -> char {
+        let ch = self.char_at(index).expect("cannot remove a char from the end of a buffer");
+        self.delete(index..index + ch.len_utf8());
+        ch
+    }
+
+    fn pop(&mut self) -> Option<char> {
+        let ch = self.char_before(self.len())?;
+        let end = self.len();
+        self.delete(end - ch.len_utf8()..end);
+        Some(ch)
+    }
+
+    fn replace_range(&mut self, range: Range<usize>, text: &str) {
+        let start = range.start;
+        self.delete(range);
+        self.insert_str(start, text);
+    }
+}
+
+// Everything is forwarded to the inherent `String` methods
+impl TextBuffer for String {
+    fn len(&self) -> usize {
+        String::len(self)
+    }
+
+    fn char_at(&self, index: usize) -> Option<char> {
+        self[index..].chars().next()
+    }
+
+    fn char_before(&self, index: usize) -> Option<char> {
+        self[..index].chars().next_back()
+    }
+
+    fn insert_str(&mut self, index: usize, text: &str) {
+        String::insert_str(self, index, text);
+    }
+
+    fn delete(&mut self, range: Range<usize>) {
+        self.drain(range);
+    }
+
+    fn find(&self, pattern: &str) -> Option<usize> {
+        self.as_str().find(pattern)
+    }
+
+    fn replace_range(&mut self, range: Range<usize>, text: &str) {
+        String::replace_range(self, range, text);
+    }
+}
@@ -1,10 +1,45 @@
+pub mod alloc_tracking;
+pub mod arena;
+pub mod clone_profiler;
+pub mod growth_trace;
+pub mod heap_size;
+pub mod inline_string;
+pub mod interner;
+pub mod persistent;
+pub mod small_vec;
+
 pub mod managing_collections_in_memory {
-    pub fn collections_and_memory_management() {
-        let mut vector = Vec::from([1, 2, 3]);
-        println!("Capacity: {}", vector.capacity());
+    use std::collections::{HashMap, VecDeque};
+
+    use super::growth_trace::GrowthTrace;
 
+    pub fn collections_and_memory_management() {
+        // The trace records the (length, capacity) of the vector every time the capacity changes
+        let mut vector = GrowthTrace::new(Vec::from([1, 2, 3]));
         vector.push(4);
-        println!("Capacity: {}", vector.capacity());
+        println!("{}", vector);
+    }
+
+    pub fn demo_growth_curves() {
+        // Pushing one item at a time shows the growth policy of each collection
+        let mut vector = GrowthTrace::new(Vec::new());
+        vector.extend(1..=1000);
+        println!("Vec<i32>:\n{}", vector);
+
+        let mut deque = GrowthTrace::new(VecDeque::new());
+        deque.extend(1..=1000);
+        println!("VecDeque<i32>:\n{}", deque);
+
+        let mut string = GrowthTrace::new(String::new());
+        string.extend("Coffee".chars().cycle().take(1000));
+        println!("String:\n{}", string);
+
+        let mut map = GrowthTrace::new(HashMap::new());
+        map.extend((1..=1000).map(|n| (n, n * 10)));
+        println!("HashMap<i32, i32>:\n{}", map);
+
+        // The same data as CSV, e.g. to plot the growth curve in a spreadsheet
+        println!("{}", vector.to_csv());
     }
 
     pub fn moving_copying_and_cloning_collections() {
@@ -25,7 +60,12 @@ pub mod managing_collections_in_memory {
         println!("movd: {movd:?}\n");
 
         let other = vec![9, 2, 8];
-        // `clone()` makes a deep copy of the vector
+        // `clone()` makes a deep copy of the vector. For three numbers that is cheap, but the cost
+        // grows with the collection, and every element that owns heap data is allocated again,
+        // while a move or a borrow costs the same at any size. For the numbers, see
+        // `managing_collections_in_memory_demo::demo_clone_costs()`, which times moving,
+        // borrowing, cloning, sharing through an `Rc` and borrowing through a `Cow` for
+        // collections of several sizes.
         let cloned = other.clone();
 
         println!("other: {other:?}");
@@ -35,6 +75,15 @@ pub mod managing_collections_in_memory {
 
 pub mod managing_collections_in_memory_demo {
     use std::collections::HashMap;
+    use std::time::Instant;
+
+    use crate::coffee;
+
+    use super::alloc_tracking::{self, track};
+    use super::clone_profiler::CloneProfiler;
+    use super::growth_trace::GrowthTrace;
+    use super::heap_size::HeapSize;
+    use super::interner::{Interner, Symbol};
 
     #[derive(Debug, PartialEq, Clone)]
     struct Coffee {
@@ -42,6 +91,17 @@ pub mod managing_collections_in_memory_demo {
         count: i32,
     }
 
+    // A coffee is two integers, it owns nothing on the heap
+    impl HeapSize for Coffee {
+        fn heap_used(&self) -> usize {
+            0
+        }
+
+        fn heap_reserved(&self) -> usize {
+            0
+        }
+    }
+
     pub fn demo_cloning_and_copying_collections() {
         // Vec moving and cloning
         let coffees = Vec::from([
@@ -127,80 +187,654 @@ pub mod managing_collections_in_memory_demo {
 
     pub fn demo_capacity_management() {
         // Creating collections with capacity
+        // The trace keeps the (length, capacity) history, `track()` reports exactly what each
+        // step asked from the allocator. Use `{:#}` to see every single allocation.
         println!("Vec capacity management:");
-        let mut coffees: Vec<Coffee> = Vec::with_capacity(100);
-        println!("Capacity is {} | Length is {}", coffees.capacity(), coffees.len());
+        let mut coffees: GrowthTrace<Vec<Coffee>> = GrowthTrace::new(Vec::new());
+        let report = track(|| coffees.apply("with_capacity(100)", |c| *c = Vec::with_capacity(100)));
+        println!("with_capacity(100): {:#}", report);
 
-        for n in 1..76 {
-            coffees.push(Coffee { id: n, count: n * 10 });
-        };
-        println!("Capacity is {} | Length is {}", coffees.capacity(), coffees.len());
+        let report = track(|| coffees.extend((1..76).map(|n| Coffee { id: n, count: n * 10 })));
+        println!("push 75 coffees: {:#}", report);
 
-        for n in 1..26 {
-            coffees.push(Coffee { id: n, count: n * 10 });
-        };
-        println!("Capacity is {} | Length is {}", coffees.capacity(), coffees.len());
+        let report = track(|| coffees.extend((1..26).map(|n| Coffee { id: n, count: n * 10 })));
+        println!("push 25 coffees: {:#}", report);
 
-        for n in 1..26 {
-            coffees.push(Coffee { id: n, count: n * 10 });
-        };
-
-        // On my machine, a new allocation occurs here - it over-allocates
-        println!("Capacity is {} | Length is {}", coffees.capacity(), coffees.len());
+        // A new allocation occurs here - it over-allocates, and the report shows the realloc
+        let report = track(|| coffees.extend((1..26).map(|n| Coffee { id: n, count: n * 10 })));
+        println!("push 25 coffees: {:#}", report);
 
         // Let's shrink it!
-        coffees.shrink_to_fit();
+        let report = track(|| coffees.apply("shrink_to_fit()", Vec::shrink_to_fit));
+        println!("shrink_to_fit(): {:#}", report);
 
         // This code is commented because the capacity does not change when we shrink it if we
         // call 'reserve()' just afterwards!
 
         // We know more coffees are coming in - let's make a new allocation happen beforehand!
-        // coffees.reserve(100);
-        // println!("Capacity is {} | Length is {}", coffees.capacity(), coffees.len());
+        // coffees.apply("reserve(100)", |c| c.reserve(100));
 
-        println!("Capacity is {} | Length is {}", coffees.capacity(), coffees.len());
+        println!("\n{}", coffees);
 
 
         // HashMap capacity management!
         println!("\nHashMap capacity management:");
-        let mut coffee_map: HashMap<String, Coffee> = HashMap::with_capacity(100);
-
+        let mut coffee_map: GrowthTrace<HashMap<String, Coffee>> = GrowthTrace::new(HashMap::new());
         // Note that the capacity is more than we asked for!
-        println!("Capacity is {} | Length is {}", coffee_map.capacity(), coffee_map.len());
+        let report = track(|| coffee_map.apply("with_capacity(100)", |c| *c = HashMap::with_capacity(100)));
+        println!("with_capacity(100): {:#}", report);
 
-        for n in 1..76 {
-            coffee_map.insert(
-                "Coffee".to_owned() + n.to_string().as_str(),
-                Coffee { id: n, count: n * 10 },
-            );
-        };
-        println!("Capacity is {} | Length is {}", coffee_map.capacity(), coffee_map.len());
+        // Every key is built from temporary `String`s, so these reports also count the key allocations
+        let new_coffee = |n: i32| ("Coffee".to_owned() + n.to_string().as_str(), Coffee { id: n, count: n * 10 });
 
-        for n in 76..101 {
-            coffee_map.insert(
-                "Coffee".to_owned() + n.to_string().as_str(),
-                Coffee { id: n, count: n * 10 },
-            );
-        };
-        println!("Capacity is {} | Length is {}", coffee_map.capacity(), coffee_map.len());
+        let report = track(|| coffee_map.extend((1..76).map(new_coffee)));
+        println!("insert 75 coffees: {}", report);
 
-        for n in 101..151 {
-            coffee_map.insert(
-                "Coffee".to_owned() + n.to_string().as_str(),
-                Coffee { id: n, count: n * 10 },
-            );
-        };
+        let report = track(|| coffee_map.extend((76..101).map(new_coffee)));
+        println!("insert 25 coffees: {}", report);
 
-        // On my machine, a new allocation occurs here!
-        println!("Capacity is {} | Length is {}", coffee_map.capacity(), coffee_map.len());
+        // A new allocation occurs here: the table is rebuilt in a bigger allocation
+        // (a HashMap never reallocs in place, so look for the big alloc/dealloc pair)
+        let report = track(|| coffee_map.extend((101..151).map(new_coffee)));
+        println!("insert 50 coffees: {}", report);
 
         // Let's shrink it!
-        coffee_map.shrink_to_fit();
-        println!("Capacity is {} | Length is {}", coffee_map.capacity(), coffee_map.len());
+        let report = track(|| coffee_map.apply("shrink_to_fit()", HashMap::shrink_to_fit));
+        println!("shrink_to_fit(): {:#}", report);
 
         // We know more coffee entries are coming in - let's make a new allocation happen beforehand!
-        coffee_map.reserve(100);
-        println!("Capacity is {} | Length is {}", coffee_map.capacity(), coffee_map.len());
+        let report = track(|| coffee_map.apply("reserve(100)", |c| c.reserve(100)));
+        println!("reserve(100): {:#}", report);
         // This call to reserve on the HashMap doubled its capacity on my system!
+
+        println!("\n{}", coffee_map);
+
+
+        // String capacity management!
+        println!("\nString capacity management:");
+        let mut coffee_names = GrowthTrace::new(String::new());
+        let report = track(|| coffee_names.apply("with_capacity(16)", |c| *c = String::with_capacity(16)));
+        println!("with_capacity(16): {:#}", report);
+
+        // Fits in the capacity we asked for, so nothing is allocated
+        let report = track(|| coffee_names.apply("push_str()", |c| c.push_str("Latte, Mocha")));
+        println!("push_str(): {:#}", report);
+
+        // Outgrows the buffer: the String grows by reallocating in place when it can
+        let report = track(|| coffee_names.apply("push_str()", |c| c.push_str(", Cappuccino, Espresso")));
+        println!("push_str(): {:#}", report);
+
+        let report = track(|| coffee_names.apply("shrink_to_fit()", String::shrink_to_fit));
+        println!("shrink_to_fit(): {:#}", report);
+
+        println!("\n{}", coffee_names);
+    }
+
+    pub fn demo_memory_footprint() {
+        // Vec: over-allocated on purpose, like the coffees in `demo_capacity_management`
+        let mut coffees: Vec<Coffee> = Vec::with_capacity(200);
+        coffees.extend((1..126).map(|n| Coffee { id: n, count: n * 10 }));
+        println!("Vec<Coffee> with 125 coffees and room for 200:");
+        println!("  {}", coffees.footprint());
+
+        coffees.shrink_to_fit();
+        println!("After shrink_to_fit():");
+        println!("  {}\n", coffees.footprint());
+
+        // HashMap: the keys are `String`s, so their heap memory is counted as well
+        let mut coffee_map: HashMap<String, Coffee> = HashMap::new();
+        let report = track(|| {
+            coffee_map = (1..151)
+                .map(|n| (format!("Coffee{n}"), Coffee { id: n, count: n * 10 }))
+                .collect();
+            coffee_map.reserve(100);
+        });
+        println!("HashMap<String, Coffee> with 150 coffees after reserve(100):");
+        println!("  {}", coffee_map.footprint());
+        // The estimate can be checked against what the allocator actually handed out
+        if alloc_tracking::is_installed() {
+            println!("  The allocator reports {} bytes still in use", report.net_bytes());
+        }
+
+        let report = track(|| coffee_map.shrink_to_fit());
+        println!("After shrink_to_fit():");
+        println!("  {}", coffee_map.footprint());
+        if alloc_tracking::is_installed() {
+            println!("  The allocator reports {:+} bytes", report.net_bytes());
+        }
+        println!();
+
+        // The shared coffee record has a name, and every name is heap memory of its own
+        let named_map: HashMap<String, coffee::Coffee> = (1..151)
+            .map(|n| (format!("Coffee{n}"), coffee::Coffee::new(n, format!("Coffee {n} with milk"), n * 10)))
+            .collect();
+        println!("HashMap<String, coffee::Coffee> with 150 named coffees:");
+        println!("  {}", named_map.footprint());
+        let names: usize = named_map.values().map(|coffee| coffee.name.heap_reserved()).sum();
+        println!("  of which the names take {} B\n", names);
+        assert!(named_map.heap_reserved() > names);
+
+        // String: the capacity of a String is bytes, not characters
+        let mut greeting = String::with_capacity(64);
+        greeting.push_str("Hello World");
+        println!("String {:?} with capacity 64:", greeting);
+        println!("  {}", greeting.footprint());
+
+        greeting.shrink_to_fit();
+        println!("After shrink_to_fit():");
+        println!("  {}", greeting.footprint());
+    }
+
+    pub fn demo_clone_costs() {
+        // Moving, borrowing, cloning and sharing the coffees from `demo_cloning_and_copying_collections`,
+        // measured instead of explained
+        let mut profiler = CloneProfiler::new().sizes(&[10, 1_000, 100_000]).iterations(100);
+
+        profiler.profile("Vec<i32>", |size| (0..size as i32).collect::<Vec<i32>>());
+        profiler.profile("Vec<Coffee>", |size| {
+            (0..size as i32).map(|n| Coffee { id: n, count: n * 10 }).collect::<Vec<Coffee>>()
+        });
+        // Every name is a heap allocation of its own, so a deep clone allocates once per coffee
+        profiler.profile("Vec<String>", |size| {
+            (0..size).map(|n| format!("Coffee{n}")).collect::<Vec<String>>()
+        });
+        profiler.profile("HashMap<String, Coffee>", |size| {
+            (0..size as i32)
+                .map(|n| (format!("Coffee{n}"), Coffee { id: n, count: n * 10 }))
+                .collect::<HashMap<String, Coffee>>()
+        });
+
+        println!("{}", profiler);
+    }
+
+    pub fn demo_interned_keys() {
+        // The 150 coffees from `demo_capacity_management`, plus their prices and open orders, all
+        // keyed by the coffee name. Every `String` key is an allocation of its own.
+        let name = |n: i32| format!("Coffee{n}");
+        let mut coffee_map: HashMap<String, Coffee> = HashMap::new();
+        let mut price_map: HashMap<String, f64> = HashMap::new();
+        let mut order_map: HashMap<String, i32> = HashMap::new();
+        let string_report = track(|| {
+            coffee_map = (1..151).map(|n| (name(n), Coffee { id: n, count: n * 10 })).collect();
+            price_map = (1..151).map(|n| (name(n), 2.5 + n as f64 / 100.0)).collect();
+            order_map = (1..151).map(|n| (name(n), n % 7)).collect();
+        });
+        let string_bytes = coffee_map.heap_reserved() + price_map.heap_reserved() + order_map.heap_reserved();
+
+        // The same maps keyed by `Symbol`. Each name is stored once, in a few large buffers.
+        let mut names = Interner::with_capacity(150);
+        let mut symbol_map: HashMap<Symbol, Coffee> = HashMap::new();
+        let mut symbol_prices: HashMap<Symbol, f64> = HashMap::new();
+        let mut symbol_orders: HashMap<Symbol, i32> = HashMap::new();
+        let symbol_report = track(|| {
+            let symbols: Vec<Symbol> = (1..151).map(|n| names.intern(&name(n))).collect();
+            symbol_map = symbols.iter().zip(1..151).map(|(&s, n)| (s, Coffee { id: n, count: n * 10 })).collect();
+            symbol_prices = symbols.iter().zip(1..151).map(|(&s, n)| (s, 2.5 + n as f64 / 100.0)).collect();
+            symbol_orders = symbols.iter().zip(1..151).map(|(&s, n)| (s, n % 7)).collect();
+        });
+        let symbol_bytes = names.heap_reserved()
+            + symbol_map.heap_reserved()
+            + symbol_prices.heap_reserved()
+            + symbol_orders.heap_reserved();
+
+        println!("Three maps with 150 String keys each:");
+        println!("  HashMap<String, Coffee>: {}", coffee_map.footprint());
+        println!("  HashMap<String, f64>:    {}", price_map.footprint());
+        println!("  HashMap<String, i32>:    {}", order_map.footprint());
+        println!("  {}", string_report);
+        println!("  {} B on the heap in total\n", string_bytes);
+
+        println!("The same maps with Symbol keys, plus the interner holding the names:");
+        println!("  Interner:                {}", names.footprint());
+        println!("  HashMap<Symbol, Coffee>: {}", symbol_map.footprint());
+        println!("  HashMap<Symbol, f64>:    {}", symbol_prices.footprint());
+        println!("  HashMap<Symbol, i32>:    {}", symbol_orders.footprint());
+        // Each `format!` above is a temporary, freed again right after interning it
+        println!("  {}", symbol_report);
+        println!("  {} B on the heap in total", symbol_bytes);
+        // The interner costs more than a single map of short keys saves, it pays off as soon as
+        // the same names show up in more than one place
+        println!("  (the interner alone: {} B for {} names)\n", names.heap_reserved(), names.len());
+
+        // Interning the same name again returns the same symbol and stores nothing
+        let latte = names.intern("Latte");
+        let report = track(|| assert_eq!(names.intern("Latte"), latte));
+        println!("intern(\"Latte\") twice: {} and {} ({})", latte, names.intern("Latte"), report);
+        println!("{} resolves back to {:?}", latte, names.resolve(latte));
+        let coffee42 = names.get("Coffee42").unwrap();
+        println!("Coffee42 is {} and holds {:?} at {}\n", coffee42, symbol_map[&coffee42], symbol_prices[&coffee42]);
+
+        // Looking up every coffee many times: the `String` keys are hashed and compared byte
+        // by byte, the symbols are hashed and compared as a single integer
+        let keys: Vec<String> = (1..151).map(|n| format!("Coffee{n}")).collect();
+        let symbols: Vec<Symbol> = keys.iter().map(|key| names.get(key).unwrap()).collect();
+
+        let start = Instant::now();
+        let mut total = 0;
+        for _ in 0..1000 {
+            for key in &keys {
+                total += coffee_map[key.as_str()].count;
+            }
+        }
+        let string_time = start.elapsed();
+
+        let start = Instant::now();
+        let mut symbol_total = 0;
+        for _ in 0..1000 {
+            for symbol in &symbols {
+                symbol_total += symbol_map[symbol].count;
+            }
+        }
+        let symbol_time = start.elapsed();
+
+        assert_eq!(total, symbol_total);
+        println!("150,000 lookups by String: {:?}", string_time);
+        println!("150,000 lookups by Symbol: {:?}", symbol_time);
+    }
+}
+
+pub mod allocation_strategies {
+    use std::collections::LinkedList;
+    use std::time::Instant;
+
+    use super::alloc_tracking::{self, track};
+    use super::arena::{Arena, ArenaList, ArenaTree, TypedArena};
+
+    // Everything a coffee needs lives in the arena, including its name
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    struct Coffee<'a> {
+        id: i32,
+        name: &'a str,
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct OwnedCoffee {
+        id: i32,
+        name: String,
+    }
+
+    pub fn demo_arenas() {
+        // A bump arena hands out memory from big chunks instead of one allocation per value
+        let mut arena = Arena::new();
+        let latte = arena.alloc(Coffee { id: 1000, name: "Latte" });
+        latte.id += 1;
+        let prices = arena.alloc_slice(&[320.0, 350.0, 280.0]);
+        let name = arena.alloc_str("Honey Latte");
+        println!("latte: {:?}, prices: {:?}, name: {}", latte, prices, name);
+        println!("Arena chunks: {} ({} bytes)\n", arena.chunk_count(), arena.allocated_bytes());
+
+        // `reset()` needs `&mut`, so the borrow checker makes sure nothing above is used anymore
+        arena.reset();
+
+        // A typed arena drops its values, so it can hold types that own heap memory
+        let coffee_arena = TypedArena::new();
+        let mocha = coffee_arena.alloc(OwnedCoffee { id: 2000, name: String::from("Mocha") });
+        let more = coffee_arena.alloc_extend((1..4).map(|n| OwnedCoffee { id: n, name: format!("Coffee{n}") }));
+        println!("mocha: {:?}", mocha);
+        println!("more: {:?}", more);
+        println!("Coffees in the typed arena: {}\n", coffee_arena.len());
+
+        // The same operations as `demo_linkedlist`, on a list whose nodes live in the arena
+        let mut coffee_list = ArenaList::new(&arena);
+        for (id, name) in [(1000, "Coffee1"), (2000, "Coffee2"), (3000, "Coffee3")] {
+            coffee_list.push_back(Coffee { id, name: arena.alloc_str(name) });
+        }
+
+        coffee_list.push_back(Coffee { id: 4000, name: "Coffee4" });
+        println!("End of linked list: {:?}", coffee_list.back());
+
+        coffee_list.pop_back();
+        println!("End of linked list: {:?}", coffee_list.back());
+
+        coffee_list.push_front(Coffee { id: 0, name: "Coffee0" });
+        println!("Front of linked list: {:?}", coffee_list.front());
+
+        coffee_list.pop_front();
+        println!("Front of linked list: {:?}\n", coffee_list.front());
+
+        let mut split_coffees = coffee_list.split_off(coffee_list.len() - 1);
+        println!("Original list after split: {:?}", coffee_list);
+        println!("Split off list: {:?}", split_coffees);
+
+        split_coffees.append(&mut coffee_list);
+        println!("Joined list: {:?}", split_coffees);
+        println!("Old list: {:?}\n", coffee_list);
+
+        // A search tree keyed by id, again without a heap allocation per node
+        let mut coffee_tree = ArenaTree::new(&arena);
+        for id in [3000, 1000, 5000, 2000, 4000] {
+            coffee_tree.insert(id, Coffee { id, name: arena.alloc_str(&format!("Coffee{}", id / 1000)) });
+        }
+        println!("Coffee tree: {:?}", coffee_tree);
+        println!("Coffee 2000: {:?}", coffee_tree.get(&2000));
+        println!("Tree height: {}\n", coffee_tree.height());
+    }
+
+    pub fn benchmark_arena_list_vs_linked_list() {
+        println!("{:>8} | {:>14} | {:>14} | {:>12} | {:>12}", "size", "LinkedList", "ArenaList", "LL allocs", "arena allocs");
+        for size in [1_000, 10_000, 100_000, 1_000_000] {
+            let mut linked_list = LinkedList::new();
+            let start = Instant::now();
+            let list_report = track(|| {
+                for id in 0..size {
+                    linked_list.push_back(Coffee { id, name: "Coffee" });
+                }
+            });
+            let list_sum: i64 = linked_list.iter().map(|coffee| coffee.id as i64).sum();
+            drop(linked_list);
+            let list_time = start.elapsed();
+
+            let start = Instant::now();
+            let arena = Arena::new();
+            let mut arena_list = ArenaList::new(&arena);
+            let arena_report = track(|| {
+                for id in 0..size {
+                    arena_list.push_back(Coffee { id, name: "Coffee" });
+                }
+            });
+            let arena_sum: i64 = arena_list.iter().map(|coffee| coffee.id as i64).sum();
+            drop(arena);
+            let arena_time = start.elapsed();
+
+            assert_eq!(list_sum, arena_sum);
+            // Without the counting allocator both counts are 0, which would look like no allocations
+            let allocations = |count: usize| if alloc_tracking::is_installed() { count.to_string() } else { "n/a".to_string() };
+            println!(
+                "{:>8} | {:>14?} | {:>14?} | {:>12} | {:>12}",
+                size, list_time, arena_time, allocations(list_report.allocations), allocations(arena_report.allocations)
+            );
+        }
+        println!("(push_back every coffee, sum the ids, then free the list)");
+        if !alloc_tracking::is_installed() {
+            println!("(allocations are only counted with `--features alloc-tracking`)");
+        }
+        println!();
+    }
+}
+
+pub mod persistent_collections {
+    use std::collections::HashMap;
+    use std::time::Instant;
+
+    use super::alloc_tracking::{self, track};
+    use super::persistent::{PMap, PVec};
+
+    #[derive(Debug, PartialEq, Clone)]
+    struct Coffee {
+        id: i32,
+        count: i32,
+    }
+
+    pub fn demo_persistent_collections() {
+        // The same steps as `demo_cloning_and_copying_collections`, on persistent collections
+        let coffees = PVec::from([
+            Coffee { id: 1000, count: 10 },
+            Coffee { id: 2000, count: 20 },
+            Coffee { id: 3000, count: 30 }
+        ]);
+        println!("Vector of Coffees: {:?}\n", coffees);
+
+        // Cloning only bumps a reference count, both vectors share the same nodes
+        let mut cloned_coffees = coffees.clone();
+        println!("Vector of Cloned Coffees: (before)\t {:?}", cloned_coffees);
+        println!("Vector of Coffees: (before)\t\t {:?}\n", coffees);
+
+        // Changing the clone copies only the path to the changed coffee
+        if let Some(coffee) = cloned_coffees.get_mut(0) {
+            coffee.count = 10000;
+        }
+        println!("Vector of Cloned Coffees: (after)\t {:?}", cloned_coffees);
+        println!("Vector of Coffees: (after)\t\t {:?}\n", coffees);
+
+        let coffee_map = PMap::from([
+            ("Coffee1", Coffee { id: 1000, count: 10 }),
+            ("Coffee2", Coffee { id: 2000, count: 40 }),
+            ("Coffee3", Coffee { id: 3000, count: 500 })
+        ]);
+        println!("Coffee map:\t\t {:?}", coffee_map);
+
+        let mut cloned_coffee_map = coffee_map.clone();
+        cloned_coffee_map.insert("Coffee1", Coffee { id: 1000, count: 0 });
+        cloned_coffee_map.remove("Coffee3");
+        println!("Cloned coffee map:\t {:?}", cloned_coffee_map);
+        println!("Coffee map:\t\t {:?}\n", coffee_map);
+    }
+
+    pub fn compare_clone_costs() {
+        // Clone a collection and change a single coffee, which is all the demos above do
+        println!("{:>8} | {:>12} | {:>12} | {:>12} | {:>12}", "size", "Vec", "PVec", "HashMap", "PMap");
+        for size in [1_000, 10_000, 100_000, 1_000_000] {
+            let vec: Vec<Coffee> = (0..size).map(|n| Coffee { id: n, count: n }).collect();
+            let pvec: PVec<Coffee> = vec.iter().cloned().collect();
+            let map: HashMap<i32, Coffee> = vec.iter().map(|c| (c.id, c.clone())).collect();
+            let pmap: PMap<i32, Coffee> = vec.iter().map(|c| (c.id, c.clone())).collect();
+
+            let start = Instant::now();
+            let vec_report = track(|| {
+                let mut cloned = vec.clone();
+                cloned[0].count = 10000;
+            });
+            let vec_time = start.elapsed();
+
+            let start = Instant::now();
+            let pvec_report = track(|| {
+                let mut cloned = pvec.clone();
+                cloned.set(0, Coffee { id: 0, count: 10000 });
+            });
+            let pvec_time = start.elapsed();
+
+            let start = Instant::now();
+            let map_report = track(|| {
+                let mut cloned = map.clone();
+                cloned.insert(0, Coffee { id: 0, count: 10000 });
+            });
+            let map_time = start.elapsed();
+
+            let start = Instant::now();
+            let pmap_report = track(|| {
+                let mut cloned = pmap.clone();
+                cloned.insert(0, Coffee { id: 0, count: 10000 });
+            });
+            let pmap_time = start.elapsed();
+
+            // Untouched by the changes to the clones
+            assert_eq!(pvec[0].count, 0);
+            assert_eq!(pmap.get(&0).map(|c| c.count), Some(0));
+
+            println!(
+                "{:>8} | {:>12?} | {:>12?} | {:>12?} | {:>12?}",
+                size, vec_time, pvec_time, map_time, pmap_time
+            );
+            // Without the counting allocator every clone would show up as 0 B
+            if alloc_tracking::is_installed() {
+                println!(
+                    "{:>8} | {:>10} B | {:>10} B | {:>10} B | {:>10} B",
+                    "", vec_report.bytes_allocated, pvec_report.bytes_allocated,
+                    map_report.bytes_allocated, pmap_report.bytes_allocated
+                );
+            }
+        }
+        println!("(time and bytes allocated to clone and change one coffee)");
+        if !alloc_tracking::is_installed() {
+            println!("(bytes are only counted with `--features alloc-tracking`)");
+        }
+        println!();
     }
-}
\ No newline at end of file
+}
+
+pub mod inline_storage {
+    use std::collections::HashMap;
+
+    use super::alloc_tracking::track;
+    use super::heap_size::HeapSize;
+    use super::inline_string::{InlineString, INLINE_CAPACITY};
+    use super::small_vec::SmallVec;
+
+    pub fn demo_small_vec() {
+        // The short vectors from the earlier modules fit inline, creating them does not allocate
+        let mut prime_numbers: SmallVec<i32, 8> = SmallVec::new();
+        let report = track(|| prime_numbers = SmallVec::from([2, 3, 5, 7]));
+        println!("prime_numbers: {:?} (spilled: {})", prime_numbers, prime_numbers.spilled());
+        println!("  {}", report);
+
+        let mut prime_vec = Vec::new();
+        let report = track(|| prime_vec = vec![2, 3, 5, 7]);
+        println!("The same numbers in a Vec: {:?}", prime_vec);
+        println!("  {}\n", report);
+
+        // Going past the inline capacity moves everything to the heap once
+        let report = track(|| prime_numbers.extend([11, 13, 17, 19, 23]));
+        println!("prime_numbers (after extend()): {:?} (spilled: {})", prime_numbers, prime_numbers.spilled());
+        println!("  {}", report);
+        println!("  {}", prime_numbers.footprint());
+
+        // ... and only moves back inline when asked to
+        prime_numbers.truncate(4);
+        prime_numbers.shrink_to_fit();
+        println!("prime_numbers (after truncate(4) and shrink_to_fit()): {:?} (spilled: {})",
+                 prime_numbers, prime_numbers.spilled());
+        println!("  {}\n", prime_numbers.footprint());
+
+        // The Vec API from `useful_vec_functions`
+        let report = track(|| {
+            let mut my_vec: SmallVec<i32, 8> = SmallVec::from([2, 4, 6]);
+            my_vec[0] = 123;
+            if let Some(x) = my_vec.get_mut(0) {
+                *x = 1024;
+            }
+            println!("my_vec (mutated): {:?}", my_vec);
+
+            let mut stack: SmallVec<i32, 8> = SmallVec::from([2, 4, 6]);
+            stack.push(8000);
+            let num = stack.pop();
+            println!("Popped number: {:?} from the stack {:?}", num, stack);
+
+            let mut vec_utils: SmallVec<i32, 8> = SmallVec::from([2, 4, 6]);
+            vec_utils.append(&mut SmallVec::from([1, 2, 3]));
+            println!("vec_utils (after append): {:?}, length: {}", vec_utils, vec_utils.len());
+
+            vec_utils.clear();
+            vec_utils.append(&mut SmallVec::from([1, 2, 3, 4, 5, 6, 7]));
+            let drained: SmallVec<i32, 8> = vec_utils.drain(3..).collect();
+            println!("vec_utils (after draining): {:?}, drained: {:?}", vec_utils, drained);
+
+            vec_utils.insert(0, 500000);
+            println!("vec_utils (after insertion): {:?}", vec_utils);
+            vec_utils.remove(0);
+            println!("vec_utils (after removal): {:?}", vec_utils);
+
+            vec_utils.clear();
+            vec_utils.extend(1..=8);
+            vec_utils.retain(|&n| n % 2 == 0);
+            println!("vec_utils.retain(): {:?}", vec_utils);
+            vec_utils.retain_mut(|n| if *n > 4 {
+                *n += 100;
+                true
+            } else {
+                false
+            });
+            println!("vec_utils.retain_mut(): {:?}", vec_utils);
+
+            vec_utils.extend_from_slice(&[400, 500, 600]);
+            vec_utils.truncate(2);
+            println!("vec_utils.truncate(): {:?}", vec_utils);
+
+            assert_eq!(my_vec.as_slice(), &[1024, 4, 6]);
+            assert_eq!(stack.as_slice(), &[2, 4, 6]);
+            assert_eq!(drained.as_slice(), &[4, 5, 6, 7]);
+            assert_eq!(vec_utils.as_slice(), &[106, 108]);
+            assert!(!vec_utils.spilled());
+        });
+        // None of the vectors ever spilled, so none of this allocated
+        println!("  {}\n", report);
+
+        // Everything a `Vec<String>` owns is dropped correctly, inline or spilled
+        let mut names: SmallVec<String, 2> = ["Latte", "Mocha", "Espresso"].iter().map(|s| s.to_string()).collect();
+        names.retain(|name| name != "Mocha");
+        println!("names: {:?} (spilled: {}, capacity: {})", names, names.spilled(), names.capacity());
+        println!("  {}", names.footprint());
+    }
+
+    pub fn demo_inline_string() {
+        // The operations from `useful_string_functions`, none of them allocate
+        println!("useful_string_functions() with an InlineString:");
+        let report = track(|| {
+            let mut string = InlineString::from("Hello");
+            println!("string: {}", string);
+            println!("bytes: {:?}", string.as_bytes());
+
+            string.truncate(3);
+            println!("string (after `truncate()`): {}", string);
+
+            string.pop();
+            println!("string (after `pop()`): {}", string);
+
+            string = InlineString::from("Hello");
+            let lo = string.split_off(3);
+            println!("lo: {}", lo);
+
+            string = InlineString::from("Hello");
+            string.push_str(" World");
+            println!("string (after `push_str()`): {}", string);
+
+            string.insert_str(0, "Well, ");
+            println!("string (after `insert_str()`): {} (spilled: {})", string, string.spilled());
+
+            assert_eq!(lo, "lo");
+            assert_eq!(string, "Well, Hello World");
+            assert!(!string.spilled());
+        });
+        println!("  {}\n", report);
+
+        // Growing past 23 bytes moves the text to the heap
+        let mut name = InlineString::from("Caramel Macchiato");
+        println!("{:?}: {} of {} bytes inline", name, name.len(), INLINE_CAPACITY);
+        let report = track(|| name.push_str(" with Oat Milk"));
+        println!("{:?}: spilled: {}, capacity: {}", name, name.spilled(), name.capacity());
+        println!("  {}", report);
+        name.truncate("Caramel Macchiato".len());
+        name.shrink_to_fit();
+        println!("{:?} (after truncate() and shrink_to_fit()): spilled: {}\n", name, name.spilled());
+
+        // Memory used by 1000 coffee names, "Coffee1" to "Coffee1000"
+        let mut strings: Vec<String> = Vec::new();
+        let string_report = track(|| strings = (1..=1000).map(|n| format!("Coffee{n}")).collect());
+        let mut inline_strings: Vec<InlineString> = Vec::new();
+        let inline_report = track(|| {
+            inline_strings = (1..=1000)
+                .map(|n| {
+                    let mut name = InlineString::from("Coffee");
+                    name.push_str(itoa(n, &mut [0; 10]));
+                    name
+                })
+                .collect()
+        });
+        println!("Vec<String> with 1000 names:");
+        println!("  {}", strings.footprint());
+        println!("  {}", string_report);
+        println!("Vec<InlineString> with 1000 names:");
+        println!("  {}", inline_strings.footprint());
+        println!("  {}\n", inline_report);
+
+        // Works as a map key and can be looked up with a `&str`
+        let coffee_prices: HashMap<InlineString, f64> = HashMap::from([
+            (InlineString::from("Latte"), 3.50),
+            (InlineString::from("Mocha"), 4.25),
+        ]);
+        println!("Price of a Latte: {:?}", coffee_prices.get("Latte"));
+    }
+
+    // Formats a number without allocating, unlike `to_string()`
+    fn itoa(mut n: u32, buffer: &mut [u8; 10]) -> &str {
+        let mut start = buffer.len();
+        loop {
+            start -= 1;
+            buffer[start] = b'0' + (n % 10) as u8;
+            n /= 10;
+            if n == 0 {
+                break;
+            }
+        }
+        std::str::from_utf8(&buffer[start..]).unwrap()
+    }
+}
@@ -0,0 +1,272 @@
+// Counting global allocator.
+//
+// `CountingAllocator` forwards every request to the system allocator. It only starts counting
+// on a thread while that thread is inside `track()`, so installing it costs almost nothing
+// outside of the demos that ask for a report. It is opt-in: it only sees allocations when it is
+// installed with `#[global_allocator]`, which `main.rs` does with the `alloc-tracking` feature.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Events beyond this are still counted, but not recorded individually
+const MAX_EVENTS: usize = 64;
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AllocEvent {
+    Alloc { bytes: usize },
+    Realloc { from: usize, to: usize },
+    Dealloc { bytes: usize },
+}
+
+impl fmt::Display for AllocEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AllocEvent::Alloc { bytes } => write!(f, "alloc {bytes} bytes"),
+            AllocEvent::Realloc { from, to } => write!(f, "realloc {from} -> {to} bytes"),
+            AllocEvent::Dealloc { bytes } => write!(f, "dealloc {bytes} bytes"),
+        }
+    }
+}
+
+// Everything here is `Copy` and const-constructible, so the allocator can update it without
+// allocating itself
+#[derive(Clone, Copy)]
+struct Counters {
+    allocations: usize,
+    reallocations: usize,
+    deallocations: usize,
+    bytes_allocated: usize,
+    bytes_deallocated: usize,
+    events: [AllocEvent; MAX_EVENTS],
+    recorded: usize,
+    dropped: usize,
+}
+
+impl Counters {
+    const EMPTY: Counters = Counters {
+        allocations: 0,
+        reallocations: 0,
+        deallocations: 0,
+        bytes_allocated: 0,
+        bytes_deallocated: 0,
+        events: [AllocEvent::Alloc { bytes: 0 }; MAX_EVENTS],
+        recorded: 0,
+        dropped: 0,
+    };
+
+    fn record(&mut self, event: AllocEvent) {
+        match event {
+            AllocEvent::Alloc { bytes } => {
+                self.allocations += 1;
+                self.bytes_allocated += bytes;
+            }
+            AllocEvent::Realloc { from, to } => {
+                self.reallocations += 1;
+                self.bytes_allocated += to;
+                self.bytes_deallocated += from;
+            }
+            AllocEvent::Dealloc { bytes } => {
+                self.deallocations += 1;
+                self.bytes_deallocated += bytes;
+            }
+        }
+
+        if self.recorded < MAX_EVENTS {
+            self.events[self.recorded] = event;
+            self.recorded += 1;
+        } else {
+            self.dropped += 1;
+        }
+    }
+
+    // Folds the counters of a nested `track()` call into the enclosing one
+    fn merge(&mut self, inner: &Counters) {
+        self.allocations += inner.allocations;
+        self.reallocations += inner.reallocations;
+        self.deallocations += inner.deallocations;
+        self.bytes_allocated += inner.bytes_allocated;
+        self.bytes_deallocated += inner.bytes_deallocated;
+        self.dropped += inner.dropped;
+        for event in &inner.events[..inner.recorded] {
+            if self.recorded < MAX_EVENTS {
+                self.events[self.recorded] = *event;
+                self.recorded += 1;
+            } else {
+                self.dropped += 1;
+            }
+        }
+    }
+}
+
+thread_local! {
+    static ACTIVE: Cell<bool> = const { Cell::new(false) };
+    static COUNTERS: RefCell<Counters> = const { RefCell::new(Counters::EMPTY) };
+}
+
+fn record(event: AllocEvent) {
+    // `try_with` because the allocator is still called while thread-locals are being torn down
+    let _ = ACTIVE.try_with(|active| {
+        if active.get() {
+            let _ = COUNTERS.try_with(|counters| {
+                if let Ok(mut counters) = counters.try_borrow_mut() {
+                    counters.record(event);
+                }
+            });
+        }
+    });
+}
+
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        INSTALLED.store(true, Ordering::Relaxed);
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            record(AllocEvent::Alloc { bytes: layout.size() });
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        INSTALLED.store(true, Ordering::Relaxed);
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            record(AllocEvent::Alloc { bytes: layout.size() });
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        record(AllocEvent::Dealloc { bytes: layout.size() });
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            record(AllocEvent::Realloc { from: layout.size(), to: new_size });
+        }
+        new_ptr
+    }
+}
+
+/// What happened on the heap while a closure passed to `track()` was running.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AllocReport {
+    pub allocations: usize,
+    pub reallocations: usize,
+    pub deallocations: usize,
+    pub bytes_allocated: usize,
+    pub bytes_deallocated: usize,
+    pub events: Vec<AllocEvent>,
+    // Events that were counted, but not kept in `events`
+    pub dropped_events: usize,
+}
+
+impl AllocReport {
+    pub fn net_bytes(&self) -> isize {
+        self.bytes_allocated as isize - self.bytes_deallocated as isize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allocations == 0 && self.reallocations == 0 && self.deallocations == 0
+    }
+}
+
+impl From<&Counters> for AllocReport {
+    fn from(counters: &Counters) -> Self {
+        AllocReport {
+            allocations: counters.allocations,
+            reallocations: counters.reallocations,
+            deallocations: counters.deallocations,
+            bytes_allocated: counters.bytes_allocated,
+            bytes_deallocated: counters.bytes_deallocated,
+            events: counters.events[..counters.recorded].to_vec(),
+            dropped_events: counters.dropped,
+        }
+    }
+}
+
+// `{}` prints a one line summary, `{:#}` also lists every recorded event
+impl fmt::Display for AllocReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !is_installed() {
+            return write!(f, "(allocation tracking is off: run with `--features alloc-tracking`)");
+        }
+
+        write!(
+            f,
+            "{} allocs, {} reallocs, {} deallocs | {} bytes allocated, {} bytes freed, net {:+} bytes",
+            self.allocations,
+            self.reallocations,
+            self.deallocations,
+            self.bytes_allocated,
+            self.bytes_deallocated,
+            self.net_bytes()
+        )?;
+
+        if f.alternate() {
+            for event in &self.events {
+                write!(f, "\n    {event}")?;
+            }
+            if self.dropped_events > 0 {
+                write!(f, "\n    ... and {} more", self.dropped_events)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether `CountingAllocator` is the global allocator of this program.
+pub fn is_installed() -> bool {
+    INSTALLED.load(Ordering::Relaxed)
+}
+
+// Restores the enclosing scope's counters even if the tracked closure panics
+struct Scope {
+    was_active: bool,
+    outer: Counters,
+    finished: bool,
+}
+
+impl Scope {
+    fn finish(&mut self) -> Counters {
+        ACTIVE.with(|active| active.set(false));
+        let inner = COUNTERS.with(|counters| counters.replace(self.outer));
+        if self.was_active {
+            COUNTERS.with(|counters| counters.borrow_mut().merge(&inner));
+        }
+        self.finished = true;
+        inner
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.finish();
+        }
+        ACTIVE.with(|active| active.set(self.was_active));
+    }
+}
+
+/// Runs `f` and reports every allocation the current thread made while it ran.
+///
+/// Calls can be nested: the outer report then includes everything the inner one saw.
+pub fn track<F: FnOnce()>(f: F) -> AllocReport {
+    let was_active = ACTIVE.with(|active| active.replace(false));
+    let outer = COUNTERS.with(|counters| counters.replace(Counters::EMPTY));
+    let mut scope = Scope { was_active, outer, finished: false };
+
+    ACTIVE.with(|active| active.set(true));
+    f();
+
+    // Tracking is switched off again here, so building the report is not counted anywhere
+    let inner = scope.finish();
+    AllocReport::from(&inner)
+}
@@ -0,0 +1,593 @@
+// Arena allocation.
+//
+// Instead of asking the global allocator for memory once per value, an arena grabs big chunks
+// up front and hands out pieces of them. Everything allocated from an arena lives exactly as long
+// as the arena itself and is freed in one go when the arena is dropped or reset.
+//
+// - `Arena` is a bump allocator for values of any type. It never runs destructors, so it is best
+//   suited to plain data and to data that borrows from the arena itself (`&'a str`, nodes).
+// - `TypedArena<T>` stores values of a single type and drops them with the arena.
+// - `ArenaList` and `ArenaTree` keep all of their nodes in an `Arena`. Their values outlive the
+//   containers and are never dropped, so they only take types that need no dropping: a list of
+//   `String`s does not compile, a list of `&'a str` from `alloc_str` does.
+
+use std::alloc::{self, Layout};
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::fmt;
+use std::mem::{self, ManuallyDrop};
+use std::ptr::{self, NonNull};
+use std::slice;
+use std::str;
+
+const MIN_CHUNK_SIZE: usize = 4096;
+const CHUNK_ALIGN: usize = 16;
+const MIN_TYPED_CHUNK_LEN: usize = 64;
+
+struct RawChunk {
+    ptr: NonNull<u8>,
+    size: usize,
+}
+
+impl RawChunk {
+    fn new(size: usize) -> RawChunk {
+        let layout = Layout::from_size_align(size, CHUNK_ALIGN).unwrap();
+        let ptr = unsafe { alloc::alloc(layout) };
+        let ptr = NonNull::new(ptr).unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        RawChunk { ptr, size }
+    }
+
+    // Carves `layout` out of the chunk after the first `used` bytes, returning the new `used`
+    fn try_alloc(&self, used: usize, layout: Layout) -> Option<(NonNull<u8>, usize)> {
+        let base = self.ptr.as_ptr() as usize;
+        let start = (base + used).next_multiple_of(layout.align()) - base;
+        let end = start.checked_add(layout.size())?;
+        if end > self.size {
+            return None;
+        }
+        // Derived from the chunk's own pointer, so it never invalidates earlier allocations
+        let ptr = unsafe { NonNull::new_unchecked(self.ptr.as_ptr().add(start)) };
+        Some((ptr, end))
+    }
+}
+
+impl Drop for RawChunk {
+    fn drop(&mut self) {
+        let layout = Layout::from_size_align(self.size, CHUNK_ALIGN).unwrap();
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), layout) };
+    }
+}
+
+pub struct Arena {
+    chunks: RefCell<Vec<RawChunk>>,
+    // Bytes handed out from the last chunk
+    used: Cell<usize>,
+}
+
+impl Arena {
+    pub fn new() -> Arena {
+        Arena {
+            chunks: RefCell::new(Vec::new()),
+            used: Cell::new(0),
+        }
+    }
+
+    pub fn with_capacity(bytes: usize) -> Arena {
+        let arena = Arena::new();
+        if bytes > 0 {
+            arena.chunks.borrow_mut().push(RawChunk::new(bytes));
+        }
+        arena
+    }
+
+    fn alloc_layout(&self, layout: Layout) -> NonNull<u8> {
+        if layout.size() == 0 {
+            // Zero-sized values need a well-aligned address, but no memory
+            return unsafe { NonNull::new_unchecked(layout.align() as *mut u8) };
+        }
+
+        let mut chunks = self.chunks.borrow_mut();
+        if let Some((ptr, used)) = chunks.last().and_then(|chunk| chunk.try_alloc(self.used.get(), layout)) {
+            self.used.set(used);
+            return ptr;
+        }
+
+        // Whatever is left in the current chunk is wasted, the next one is twice as big
+        let last_size = chunks.last().map_or(0, |chunk| chunk.size);
+        let size = (last_size * 2)
+            .max(MIN_CHUNK_SIZE)
+            .max(layout.size() + layout.align());
+        let chunk = RawChunk::new(size);
+        let (ptr, used) = chunk.try_alloc(0, layout).unwrap();
+        chunks.push(chunk);
+        self.used.set(used);
+        ptr
+    }
+
+    // Handing out `&mut` from `&self` is the whole point of an arena: every call returns
+    // a different, non-overlapping piece of memory.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc<T>(&self, value: T) -> &mut T {
+        let ptr = self.alloc_layout(Layout::new::<T>()).cast::<T>();
+        unsafe {
+            ptr.as_ptr().write(value);
+            &mut *ptr.as_ptr()
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_slice<T: Copy>(&self, values: &[T]) -> &mut [T] {
+        let ptr = self.alloc_layout(Layout::for_value(values)).cast::<T>();
+        unsafe {
+            ptr::copy_nonoverlapping(values.as_ptr(), ptr.as_ptr(), values.len());
+            slice::from_raw_parts_mut(ptr.as_ptr(), values.len())
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_str(&self, value: &str) -> &mut str {
+        let bytes = self.alloc_slice(value.as_bytes());
+        unsafe { str::from_utf8_unchecked_mut(bytes) }
+    }
+
+    // Makes all memory available again. Taking `&mut self` guarantees nothing allocated from
+    // the arena is still borrowed. The biggest chunk is kept so that refilling is cheap.
+    pub fn reset(&mut self) {
+        let chunks = self.chunks.get_mut();
+        if chunks.len() > 1 {
+            chunks.drain(..chunks.len() - 1);
+        }
+        self.used.set(0);
+    }
+
+    // Bytes reserved from the global allocator
+    pub fn allocated_bytes(&self) -> usize {
+        self.chunks.borrow().iter().map(|chunk| chunk.size).sum()
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.borrow().len()
+    }
+}
+
+impl Default for Arena {
+    fn default() -> Self {
+        Arena::new()
+    }
+}
+
+// A `Vec` that is never allowed to grow, taken apart so that its elements can be handed out
+struct TypedChunk<T> {
+    ptr: *mut T,
+    len: usize,
+    capacity: usize,
+}
+
+impl<T> TypedChunk<T> {
+    fn new(capacity: usize) -> TypedChunk<T> {
+        let mut storage = ManuallyDrop::new(Vec::with_capacity(capacity));
+        TypedChunk {
+            ptr: storage.as_mut_ptr(),
+            len: 0,
+            capacity: storage.capacity(),
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.capacity - self.len
+    }
+}
+
+impl<T> Drop for TypedChunk<T> {
+    fn drop(&mut self) {
+        // Rebuilding the `Vec` drops the elements and frees the memory
+        unsafe { drop(Vec::from_raw_parts(self.ptr, self.len, self.capacity)) };
+    }
+}
+
+pub struct TypedArena<T> {
+    chunks: RefCell<Vec<TypedChunk<T>>>,
+}
+
+impl<T> TypedArena<T> {
+    pub fn new() -> TypedArena<T> {
+        TypedArena {
+            chunks: RefCell::new(Vec::new()),
+        }
+    }
+
+    // Returns a chunk with room for at least `additional` more values
+    fn reserve(chunks: &mut Vec<TypedChunk<T>>, additional: usize) -> &mut TypedChunk<T> {
+        let needs_chunk = chunks.last().is_none_or(|chunk| chunk.remaining() < additional);
+        if needs_chunk {
+            let last_capacity = chunks.last().map_or(0, |chunk| chunk.capacity);
+            let capacity = (last_capacity * 2).max(MIN_TYPED_CHUNK_LEN).max(additional);
+            chunks.push(TypedChunk::new(capacity));
+        }
+        chunks.last_mut().unwrap()
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc(&self, value: T) -> &mut T {
+        let mut chunks = self.chunks.borrow_mut();
+        let chunk = Self::reserve(&mut chunks, 1);
+        unsafe {
+            let slot = chunk.ptr.add(chunk.len);
+            slot.write(value);
+            chunk.len += 1;
+            &mut *slot
+        }
+    }
+
+    // The values are collected first, so the iterator is free to use the arena itself
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_extend<I: IntoIterator<Item = T>>(&self, values: I) -> &mut [T] {
+        let mut values: Vec<T> = values.into_iter().collect();
+        let count = values.len();
+
+        let mut chunks = self.chunks.borrow_mut();
+        let chunk = Self::reserve(&mut chunks, count);
+        unsafe {
+            let start = chunk.ptr.add(chunk.len);
+            ptr::copy_nonoverlapping(values.as_ptr(), start, count);
+            // The values have been moved into the arena
+            values.set_len(0);
+            chunk.len += count;
+            slice::from_raw_parts_mut(start, count)
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_slice(&self, values: &[T]) -> &mut [T]
+    where
+        T: Clone,
+    {
+        self.alloc_extend(values.iter().cloned())
+    }
+
+    // Drops every value, but keeps the biggest chunk around for reuse
+    pub fn reset(&mut self) {
+        let chunks = self.chunks.get_mut();
+        if chunks.len() > 1 {
+            chunks.drain(..chunks.len() - 1);
+        }
+        if let Some(chunk) = chunks.last_mut() {
+            let len = chunk.len;
+            // Forget the values before dropping them, in case a destructor panics
+            chunk.len = 0;
+            unsafe { ptr::drop_in_place(ptr::slice_from_raw_parts_mut(chunk.ptr, len)) };
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.borrow().iter().map(|chunk| chunk.len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Default for TypedArena<T> {
+    fn default() -> Self {
+        TypedArena::new()
+    }
+}
+
+struct ListNode<'a, T> {
+    value: T,
+    prev: Cell<Option<&'a ListNode<'a, T>>>,
+    next: Cell<Option<&'a ListNode<'a, T>>>,
+}
+
+// Doubly linked list with the same interface as `std::collections::LinkedList`, except that
+// popped values stay in the arena and are returned by reference. `T` must not need dropping.
+pub struct ArenaList<'a, T> {
+    arena: &'a Arena,
+    head: Option<&'a ListNode<'a, T>>,
+    tail: Option<&'a ListNode<'a, T>>,
+    len: usize,
+}
+
+impl<'a, T> ArenaList<'a, T> {
+    pub fn new(arena: &'a Arena) -> ArenaList<'a, T> {
+        // Values handed out by `pop_front` and the like outlive the list, so the list can not
+        // drop them either. Refuse at compile time rather than leak them.
+        const { assert!(!mem::needs_drop::<T>(), "the arena never drops values, use a type that needs no dropping") };
+        ArenaList {
+            arena,
+            head: None,
+            tail: None,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn front(&self) -> Option<&'a T> {
+        self.head.map(|node| &node.value)
+    }
+
+    pub fn back(&self) -> Option<&'a T> {
+        self.tail.map(|node| &node.value)
+    }
+
+    pub fn push_back(&mut self, value: T) {
+        let node: &'a ListNode<'a, T> = self.arena.alloc(ListNode {
+            value,
+            prev: Cell::new(self.tail),
+            next: Cell::new(None),
+        });
+        match self.tail {
+            Some(tail) => tail.next.set(Some(node)),
+            None => self.head = Some(node),
+        }
+        self.tail = Some(node);
+        self.len += 1;
+    }
+
+    pub fn push_front(&mut self, value: T) {
+        let node: &'a ListNode<'a, T> = self.arena.alloc(ListNode {
+            value,
+            prev: Cell::new(None),
+            next: Cell::new(self.head),
+        });
+        match self.head {
+            Some(head) => head.prev.set(Some(node)),
+            None => self.tail = Some(node),
+        }
+        self.head = Some(node);
+        self.len += 1;
+    }
+
+    pub fn pop_front(&mut self) -> Option<&'a T> {
+        let node = self.head?;
+        self.head = node.next.get();
+        match self.head {
+            Some(head) => head.prev.set(None),
+            None => self.tail = None,
+        }
+        node.next.set(None);
+        self.len -= 1;
+        Some(&node.value)
+    }
+
+    pub fn pop_back(&mut self) -> Option<&'a T> {
+        let node = self.tail?;
+        self.tail = node.prev.get();
+        match self.tail {
+            Some(tail) => tail.next.set(None),
+            None => self.head = None,
+        }
+        node.prev.set(None);
+        self.len -= 1;
+        Some(&node.value)
+    }
+
+    // Moves all nodes of `other` to the end of this list without copying anything
+    pub fn append(&mut self, other: &mut ArenaList<'a, T>) {
+        let Some(other_head) = other.head.take() else {
+            return;
+        };
+        match self.tail {
+            Some(tail) => {
+                tail.next.set(Some(other_head));
+                other_head.prev.set(Some(tail));
+            }
+            None => self.head = Some(other_head),
+        }
+        self.tail = other.tail.take();
+        self.len += other.len;
+        other.len = 0;
+    }
+
+    // Splits the list in two at the given index, like `LinkedList::split_off`
+    pub fn split_off(&mut self, at: usize) -> ArenaList<'a, T> {
+        assert!(at <= self.len, "Cannot split off at a nonexistent index");
+
+        let mut split = ArenaList::new(self.arena);
+        if at == self.len {
+            return split;
+        }
+        if at == 0 {
+            // Both lists use the same arena, so the whole list can simply change hands
+            mem::swap(self, &mut split);
+            return split;
+        }
+
+        let mut node = self.head.unwrap();
+        for _ in 1..at {
+            node = node.next.get().unwrap();
+        }
+        let split_head = node.next.take().unwrap();
+        split_head.prev.set(None);
+
+        split.head = Some(split_head);
+        split.tail = self.tail;
+        split.len = self.len - at;
+        self.tail = Some(node);
+        self.len = at;
+        split
+    }
+
+    pub fn iter(&self) -> Iter<'a, T> {
+        Iter {
+            next: self.head,
+            remaining: self.len,
+        }
+    }
+}
+
+pub struct Iter<'a, T> {
+    next: Option<&'a ListNode<'a, T>>,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.next?;
+        self.next = node.next.get();
+        self.remaining -= 1;
+        Some(&node.value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> IntoIterator for &ArenaList<'a, T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ArenaList<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+struct TreeNode<'a, K, V> {
+    key: K,
+    value: V,
+    left: Cell<Option<&'a TreeNode<'a, K, V>>>,
+    right: Cell<Option<&'a TreeNode<'a, K, V>>>,
+}
+
+// Unbalanced binary search tree whose nodes live in an `Arena`. Neither `K` nor `V` may need
+// dropping.
+pub struct ArenaTree<'a, K, V> {
+    arena: &'a Arena,
+    root: Option<&'a TreeNode<'a, K, V>>,
+    len: usize,
+}
+
+impl<'a, K: Ord, V> ArenaTree<'a, K, V> {
+    pub fn new(arena: &'a Arena) -> ArenaTree<'a, K, V> {
+        // Like in `ArenaList::new`: the values `insert` returns outlive the tree
+        const { assert!(!mem::needs_drop::<K>() && !mem::needs_drop::<V>(), "the arena never drops values, use types that need no dropping") };
+        ArenaTree {
+            arena,
+            root: None,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Nodes are shared, so an existing value is never replaced: like `entry().or_insert()`,
+    // this returns the value that ends up stored under `key`
+    pub fn insert(&mut self, key: K, value: V) -> &'a V {
+        let mut link = match self.root {
+            Some(root) => root,
+            None => {
+                let node = self.new_node(key, value);
+                self.root = Some(node);
+                return &node.value;
+            }
+        };
+
+        loop {
+            let child = match key.cmp(&link.key) {
+                Ordering::Equal => return &link.value,
+                Ordering::Less => &link.left,
+                Ordering::Greater => &link.right,
+            };
+            match child.get() {
+                Some(node) => link = node,
+                None => {
+                    let node = self.new_node(key, value);
+                    child.set(Some(node));
+                    return &node.value;
+                }
+            }
+        }
+    }
+
+    fn new_node(&mut self, key: K, value: V) -> &'a TreeNode<'a, K, V> {
+        self.len += 1;
+        self.arena.alloc(TreeNode {
+            key,
+            value,
+            left: Cell::new(None),
+            right: Cell::new(None),
+        })
+    }
+
+    pub fn get(&self, key: &K) -> Option<&'a V> {
+        let mut current = self.root;
+        while let Some(node) = current {
+            current = match key.cmp(&node.key) {
+                Ordering::Equal => return Some(&node.value),
+                Ordering::Less => node.left.get(),
+                Ordering::Greater => node.right.get(),
+            };
+        }
+        None
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn height(&self) -> usize {
+        fn height<K, V>(node: Option<&TreeNode<'_, K, V>>) -> usize {
+            node.map_or(0, |node| 1 + height(node.left.get()).max(height(node.right.get())))
+        }
+        height(self.root)
+    }
+
+    // In-order, so the keys come out sorted like in a `BTreeMap`
+    pub fn iter(&self) -> TreeIter<'a, K, V> {
+        let mut iter = TreeIter { stack: Vec::new() };
+        iter.push_left(self.root);
+        iter
+    }
+}
+
+pub struct TreeIter<'a, K, V> {
+    stack: Vec<&'a TreeNode<'a, K, V>>,
+}
+
+impl<'a, K, V> TreeIter<'a, K, V> {
+    fn push_left(&mut self, mut node: Option<&'a TreeNode<'a, K, V>>) {
+        while let Some(current) = node {
+            self.stack.push(current);
+            node = current.left.get();
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for TreeIter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.push_left(node.right.get());
+        Some((&node.key, &node.value))
+    }
+}
+
+impl<K: Ord + fmt::Debug, V: fmt::Debug> fmt::Debug for ArenaTree<'_, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
@@ -0,0 +1,233 @@
+// Measures what it costs to hand a collection to someone else.
+//
+// Moving a collection only copies its three words on the stack, borrowing copies a pointer and
+// cloning copies every element, including everything the elements own on the heap. Sharing it
+// through an `Rc` costs one reference count update, and a `Cow` borrows until somebody writes to
+// it. `CloneProfiler` runs each of these strategies many times on collections of different sizes
+// and reports the time and the allocations per operation as a table.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::hint::black_box;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use super::alloc_tracking::{self, track, AllocReport};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    Move,
+    Borrow,
+    Clone,
+    RcShare,
+    CowBorrow,
+}
+
+impl Strategy {
+    pub const ALL: [Strategy; 5] = [
+        Strategy::Move,
+        Strategy::Borrow,
+        Strategy::Clone,
+        Strategy::RcShare,
+        Strategy::CowBorrow,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Strategy::Move => "move",
+            Strategy::Borrow => "borrow",
+            Strategy::Clone => "clone",
+            Strategy::RcShare => "Rc share",
+            Strategy::CowBorrow => "Cow borrow",
+        }
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+/// The cost of one strategy, averaged over all iterations.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Measurement {
+    pub collection: &'static str,
+    pub size: usize,
+    pub strategy: Strategy,
+    pub time_per_op: Duration,
+    pub allocations_per_op: f64,
+    pub bytes_per_op: f64,
+}
+
+pub struct CloneProfiler {
+    sizes: Vec<usize>,
+    iterations: usize,
+    measurements: Vec<Measurement>,
+}
+
+impl CloneProfiler {
+    pub fn new() -> CloneProfiler {
+        CloneProfiler {
+            sizes: vec![10, 1_000, 100_000],
+            iterations: 100,
+            measurements: Vec::new(),
+        }
+    }
+
+    pub fn sizes(mut self, sizes: &[usize]) -> CloneProfiler {
+        self.sizes = sizes.to_vec();
+        self
+    }
+
+    pub fn iterations(mut self, iterations: usize) -> CloneProfiler {
+        self.iterations = iterations.max(1);
+        self
+    }
+
+    pub fn measurements(&self) -> &[Measurement] {
+        &self.measurements
+    }
+
+    // Builds a collection with `make(size)` for every size and measures every strategy on it
+    pub fn profile<C: Clone>(&mut self, collection: &'static str, make: impl Fn(usize) -> C) {
+        for &size in &self.sizes {
+            let value = make(size);
+            for strategy in Strategy::ALL {
+                let (value_back, elapsed, report) = self.run(strategy, value.clone());
+                drop(value_back);
+
+                let iterations = self.iterations as f64;
+                self.measurements.push(Measurement {
+                    collection,
+                    size,
+                    strategy,
+                    time_per_op: elapsed / self.iterations as u32,
+                    allocations_per_op: report.allocations as f64 / iterations,
+                    bytes_per_op: report.bytes_allocated as f64 / iterations,
+                });
+            }
+        }
+    }
+
+    // Every strategy hands the collection back, so that dropping it is never part of the timing.
+    // `black_box` keeps the compiler from optimizing the handed out value away.
+    fn run<C: Clone>(&self, strategy: Strategy, mut value: C) -> (C, Duration, AllocReport) {
+        let iterations = self.iterations;
+        let mut elapsed = Duration::ZERO;
+        let report = match strategy {
+            Strategy::Move => {
+                let mut moved = None;
+                let report = track(|| {
+                    let mut owner = value;
+                    let start = Instant::now();
+                    for _ in 0..iterations {
+                        owner = black_box(owner);
+                    }
+                    elapsed = start.elapsed();
+                    moved = Some(owner);
+                });
+                value = moved.expect("the value is handed back after moving it around");
+                report
+            }
+            Strategy::Borrow => track(|| {
+                let start = Instant::now();
+                for _ in 0..iterations {
+                    black_box(&value);
+                }
+                elapsed = start.elapsed();
+            }),
+            // The clone is dropped again right away, which is part of what a clone costs
+            Strategy::Clone => track(|| {
+                let start = Instant::now();
+                for _ in 0..iterations {
+                    black_box(value.clone());
+                }
+                elapsed = start.elapsed();
+            }),
+            // Putting the collection into the `Rc` happens once, only the sharing is measured
+            Strategy::RcShare => {
+                let shared = Rc::new(value);
+                let report = track(|| {
+                    let start = Instant::now();
+                    for _ in 0..iterations {
+                        black_box(Rc::clone(&shared));
+                    }
+                    elapsed = start.elapsed();
+                });
+                value = Rc::try_unwrap(shared).unwrap_or_else(|shared| (*shared).clone());
+                report
+            }
+            Strategy::CowBorrow => track(|| {
+                let start = Instant::now();
+                for _ in 0..iterations {
+                    let cow: Cow<'_, C> = Cow::Borrowed(&value);
+                    black_box(&cow);
+                }
+                elapsed = start.elapsed();
+            }),
+        };
+        (value, elapsed, report)
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("collection,size,strategy,nanos_per_op,allocations_per_op,bytes_per_op\n");
+        for m in &self.measurements {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{}\n",
+                m.collection,
+                m.size,
+                m.strategy,
+                m.time_per_op.as_nanos(),
+                m.allocations_per_op,
+                m.bytes_per_op
+            ));
+        }
+        csv
+    }
+}
+
+impl Default for CloneProfiler {
+    fn default() -> Self {
+        CloneProfiler::new()
+    }
+}
+
+impl fmt::Display for CloneProfiler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let collection_width = self
+            .measurements
+            .iter()
+            .map(|m| m.collection.len())
+            .chain(Some("collection".len()))
+            .max()
+            .unwrap_or(0);
+
+        writeln!(
+            f,
+            "{:<collection_width$} | {:>8} | {:<10} | {:>12} | {:>10} | {:>12}",
+            "collection", "size", "strategy", "time/op", "allocs/op", "bytes/op"
+        )?;
+        writeln!(
+            f,
+            "{:-<collection_width$}-+-{:-<8}-+-{:-<10}-+-{:-<12}-+-{:-<10}-+-{:-<12}",
+            "", "", "", "", "", ""
+        )?;
+        for m in &self.measurements {
+            writeln!(
+                f,
+                "{:<collection_width$} | {:>8} | {:<10} | {:>12} | {:>10.1} | {:>12.0}",
+                m.collection,
+                m.size,
+                m.strategy,
+                format!("{:?}", m.time_per_op),
+                m.allocations_per_op,
+                m.bytes_per_op
+            )?;
+        }
+        if !alloc_tracking::is_installed() {
+            writeln!(f, "(allocations are only counted with `--features alloc-tracking`)")?;
+        }
+        Ok(())
+    }
+}
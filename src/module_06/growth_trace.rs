@@ -0,0 +1,203 @@
+// Records how a collection's capacity grows as items are added to it.
+//
+// `GrowthTrace` wraps a `Vec`, `String`, `VecDeque` or `HashMap` and adds a step every time the
+// capacity changes, so the growth policy of each collection can be printed as a table or
+// exported as CSV instead of printing `capacity()` by hand after every few pushes.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+
+// Reserved up front so that recording a step does not itself allocate in the middle of a
+// `track()` report
+const INITIAL_STEPS: usize = 64;
+
+pub trait Capacity {
+    fn len(&self) -> usize;
+    fn capacity(&self) -> usize;
+}
+
+// How a single item is added to each collection
+pub trait TracePush: Capacity {
+    type Item;
+    fn trace_push(&mut self, item: Self::Item);
+}
+
+impl<T> Capacity for Vec<T> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn capacity(&self) -> usize {
+        Vec::capacity(self)
+    }
+}
+
+impl<T> TracePush for Vec<T> {
+    type Item = T;
+
+    fn trace_push(&mut self, item: T) {
+        self.push(item);
+    }
+}
+
+impl<T> Capacity for VecDeque<T> {
+    fn len(&self) -> usize {
+        VecDeque::len(self)
+    }
+
+    fn capacity(&self) -> usize {
+        VecDeque::capacity(self)
+    }
+}
+
+impl<T> TracePush for VecDeque<T> {
+    type Item = T;
+
+    fn trace_push(&mut self, item: T) {
+        self.push_back(item);
+    }
+}
+
+impl Capacity for String {
+    fn len(&self) -> usize {
+        String::len(self)
+    }
+
+    fn capacity(&self) -> usize {
+        String::capacity(self)
+    }
+}
+
+impl TracePush for String {
+    type Item = char;
+
+    fn trace_push(&mut self, item: char) {
+        self.push(item);
+    }
+}
+
+impl<K, V, S> Capacity for HashMap<K, V, S> {
+    fn len(&self) -> usize {
+        HashMap::len(self)
+    }
+
+    fn capacity(&self) -> usize {
+        HashMap::capacity(self)
+    }
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher> TracePush for HashMap<K, V, S> {
+    type Item = (K, V);
+
+    fn trace_push(&mut self, (key, value): (K, V)) {
+        self.insert(key, value);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GrowthStep {
+    pub event: &'static str,
+    pub len: usize,
+    pub capacity: usize,
+}
+
+pub struct GrowthTrace<C> {
+    collection: C,
+    steps: Vec<GrowthStep>,
+}
+
+impl<C: Capacity> GrowthTrace<C> {
+    pub fn new(collection: C) -> GrowthTrace<C> {
+        let mut trace = GrowthTrace {
+            collection,
+            steps: Vec::with_capacity(INITIAL_STEPS),
+        };
+        trace.checkpoint("new");
+        trace
+    }
+
+    pub fn get(&self) -> &C {
+        &self.collection
+    }
+
+    pub fn into_inner(self) -> C {
+        self.collection
+    }
+
+    pub fn steps(&self) -> &[GrowthStep] {
+        &self.steps
+    }
+
+    // Records the current state under `event`, even if nothing changed
+    pub fn checkpoint(&mut self, event: &'static str) {
+        self.steps.push(GrowthStep {
+            event,
+            len: self.collection.len(),
+            capacity: self.collection.capacity(),
+        });
+    }
+
+    // Runs any operation on the collection (`reserve`, `shrink_to_fit`, `clear`, ...) and
+    // records the state afterwards under `event`
+    pub fn apply<R>(&mut self, event: &'static str, f: impl FnOnce(&mut C) -> R) -> R {
+        let result = f(&mut self.collection);
+        self.checkpoint(event);
+        result
+    }
+
+    fn record_growth(&mut self) {
+        let capacity = self.collection.capacity();
+        let last = self.steps.last().map_or(0, |step| step.capacity);
+        if capacity != last {
+            let event = if capacity > last { "grow" } else { "shrink" };
+            self.checkpoint(event);
+        }
+    }
+}
+
+impl<C: TracePush> GrowthTrace<C> {
+    pub fn push(&mut self, item: C::Item) {
+        self.collection.trace_push(item);
+        self.record_growth();
+    }
+
+    pub fn extend<I: IntoIterator<Item = C::Item>>(&mut self, items: I) {
+        for item in items {
+            self.push(item);
+        }
+    }
+}
+
+impl<C> GrowthTrace<C> {
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("step,event,len,capacity\n");
+        for (index, step) in self.steps.iter().enumerate() {
+            csv.push_str(&format!("{},{},{},{}\n", index, step.event, step.len, step.capacity));
+        }
+        csv
+    }
+}
+
+impl<C> fmt::Display for GrowthTrace<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let event_width = self
+            .steps
+            .iter()
+            .map(|step| step.event.len())
+            .chain(Some("event".len()))
+            .max()
+            .unwrap_or(0);
+
+        writeln!(f, "{:>4} | {:<event_width$} | {:>8} | {:>8}", "step", "event", "len", "capacity")?;
+        writeln!(f, "{:-<4}-+-{:-<event_width$}-+-{:-<8}-+-{:-<8}", "", "", "", "")?;
+        for (index, step) in self.steps.iter().enumerate() {
+            writeln!(
+                f,
+                "{:>4} | {:<event_width$} | {:>8} | {:>8}",
+                index, step.event, step.len, step.capacity
+            )?;
+        }
+        Ok(())
+    }
+}
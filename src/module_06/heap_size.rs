@@ -0,0 +1,270 @@
+// Estimates how much memory a value really takes up.
+//
+// `size_of` only tells how big a collection is on the stack (a `Vec` is always three words),
+// not how much heap memory it owns. `HeapSize` walks the value and reports both the heap bytes
+// holding live elements and the bytes that have been reserved for them, so the difference is the
+// memory lost to over-allocation.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, LinkedList, VecDeque};
+use std::fmt;
+use std::mem;
+
+use crate::coffee::Coffee;
+
+pub trait HeapSize {
+    // Heap bytes occupied by live elements, including everything they own in turn
+    fn heap_used(&self) -> usize;
+
+    // Heap bytes allocated on behalf of this value, including spare capacity
+    fn heap_reserved(&self) -> usize;
+
+    fn footprint(&self) -> Footprint
+    where
+        Self: Sized,
+    {
+        Footprint {
+            stack: mem::size_of::<Self>(),
+            heap_used: self.heap_used(),
+            heap_reserved: self.heap_reserved(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Footprint {
+    pub stack: usize,
+    pub heap_used: usize,
+    pub heap_reserved: usize,
+}
+
+impl Footprint {
+    // Reserved, but not holding any element
+    pub fn heap_unused(&self) -> usize {
+        self.heap_reserved - self.heap_used
+    }
+
+    pub fn total(&self) -> usize {
+        self.stack + self.heap_reserved
+    }
+}
+
+impl fmt::Display for Footprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "stack {} B | heap used {} B | heap reserved {} B ({} B unused)",
+            self.stack,
+            self.heap_used,
+            self.heap_reserved,
+            self.heap_unused()
+        )
+    }
+}
+
+macro_rules! impl_heap_size_for_plain_types {
+    ($($ty:ty),*) => {
+        $(
+            impl HeapSize for $ty {
+                fn heap_used(&self) -> usize {
+                    0
+                }
+
+                fn heap_reserved(&self) -> usize {
+                    0
+                }
+            }
+        )*
+    };
+}
+
+impl_heap_size_for_plain_types!(
+    (), bool, char, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64
+);
+
+// Borrowed data is owned by someone else
+impl<T: ?Sized> HeapSize for &T {
+    fn heap_used(&self) -> usize {
+        0
+    }
+
+    fn heap_reserved(&self) -> usize {
+        0
+    }
+}
+
+impl<A: HeapSize, B: HeapSize> HeapSize for (A, B) {
+    fn heap_used(&self) -> usize {
+        self.0.heap_used() + self.1.heap_used()
+    }
+
+    fn heap_reserved(&self) -> usize {
+        self.0.heap_reserved() + self.1.heap_reserved()
+    }
+}
+
+impl<T: HeapSize, const N: usize> HeapSize for [T; N] {
+    fn heap_used(&self) -> usize {
+        self.iter().map(HeapSize::heap_used).sum()
+    }
+
+    fn heap_reserved(&self) -> usize {
+        self.iter().map(HeapSize::heap_reserved).sum()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Option<T> {
+    fn heap_used(&self) -> usize {
+        self.as_ref().map_or(0, HeapSize::heap_used)
+    }
+
+    fn heap_reserved(&self) -> usize {
+        self.as_ref().map_or(0, HeapSize::heap_reserved)
+    }
+}
+
+impl<T: HeapSize> HeapSize for Box<T> {
+    fn heap_used(&self) -> usize {
+        mem::size_of::<T>() + (**self).heap_used()
+    }
+
+    fn heap_reserved(&self) -> usize {
+        mem::size_of::<T>() + (**self).heap_reserved()
+    }
+}
+
+impl HeapSize for String {
+    fn heap_used(&self) -> usize {
+        self.len()
+    }
+
+    fn heap_reserved(&self) -> usize {
+        self.capacity()
+    }
+}
+
+// The shared coffee record owns its name
+impl HeapSize for Coffee {
+    fn heap_used(&self) -> usize {
+        self.name.heap_used()
+    }
+
+    fn heap_reserved(&self) -> usize {
+        self.name.heap_reserved()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_used(&self) -> usize {
+        self.len() * mem::size_of::<T>() + self.iter().map(HeapSize::heap_used).sum::<usize>()
+    }
+
+    fn heap_reserved(&self) -> usize {
+        self.capacity() * mem::size_of::<T>() + self.iter().map(HeapSize::heap_reserved).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize> HeapSize for VecDeque<T> {
+    fn heap_used(&self) -> usize {
+        self.len() * mem::size_of::<T>() + self.iter().map(HeapSize::heap_used).sum::<usize>()
+    }
+
+    fn heap_reserved(&self) -> usize {
+        self.capacity() * mem::size_of::<T>() + self.iter().map(HeapSize::heap_reserved).sum::<usize>()
+    }
+}
+
+// Every element gets its own node with a pointer to the next and the previous node
+const LINKED_LIST_NODE_OVERHEAD: usize = 2 * mem::size_of::<usize>();
+
+impl<T: HeapSize> HeapSize for LinkedList<T> {
+    fn heap_used(&self) -> usize {
+        self.len() * mem::size_of::<T>() + self.iter().map(HeapSize::heap_used).sum::<usize>()
+    }
+
+    fn heap_reserved(&self) -> usize {
+        self.len() * (mem::size_of::<T>() + LINKED_LIST_NODE_OVERHEAD)
+            + self.iter().map(HeapSize::heap_reserved).sum::<usize>()
+    }
+}
+
+// `HashMap` and `HashSet` store their entries in a single table with a power of two number of
+// buckets, followed by one control byte per bucket plus one group of extra control bytes. The
+// table is only ever filled to 7/8 of its buckets, which is what `capacity()` reports.
+const HASH_GROUP_WIDTH: usize = 16;
+
+fn hash_table_reserved(capacity: usize, entry_size: usize) -> usize {
+    if capacity == 0 {
+        return 0;
+    }
+    let buckets = if capacity < 8 { capacity + 1 } else { capacity / 7 * 8 };
+    let data = (buckets * entry_size).next_multiple_of(HASH_GROUP_WIDTH);
+    data + buckets + HASH_GROUP_WIDTH
+}
+
+impl<K: HeapSize, V: HeapSize, S> HeapSize for HashMap<K, V, S> {
+    fn heap_used(&self) -> usize {
+        self.len() * (mem::size_of::<(K, V)>() + 1)
+            + self.iter().map(|(k, v)| k.heap_used() + v.heap_used()).sum::<usize>()
+    }
+
+    fn heap_reserved(&self) -> usize {
+        hash_table_reserved(self.capacity(), mem::size_of::<(K, V)>())
+            + self.iter().map(|(k, v)| k.heap_reserved() + v.heap_reserved()).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize, S> HeapSize for HashSet<T, S> {
+    fn heap_used(&self) -> usize {
+        self.len() * (mem::size_of::<T>() + 1) + self.iter().map(HeapSize::heap_used).sum::<usize>()
+    }
+
+    fn heap_reserved(&self) -> usize {
+        hash_table_reserved(self.capacity(), mem::size_of::<T>())
+            + self.iter().map(HeapSize::heap_reserved).sum::<usize>()
+    }
+}
+
+// A B-tree node holds up to 11 entries. Leaves also store a parent pointer and two lengths,
+// internal nodes additionally store 12 child pointers. The real number of nodes depends on the
+// insertion order, so this assumes nodes that are full, which makes it a lower bound.
+const BTREE_NODE_CAPACITY: usize = 11;
+const BTREE_LEAF_OVERHEAD: usize = mem::size_of::<usize>() + 2 * mem::size_of::<u16>();
+
+fn btree_reserved(len: usize, entry_size: usize) -> usize {
+    if len == 0 {
+        return 0;
+    }
+    let leaf_size = BTREE_NODE_CAPACITY * entry_size + BTREE_LEAF_OVERHEAD;
+    let internal_size = leaf_size + (BTREE_NODE_CAPACITY + 1) * mem::size_of::<usize>();
+
+    let leaves = len.div_ceil(BTREE_NODE_CAPACITY);
+    let mut internal = 0;
+    let mut level = leaves;
+    while level > 1 {
+        level = level.div_ceil(BTREE_NODE_CAPACITY + 1);
+        internal += level;
+    }
+    leaves * leaf_size + internal * internal_size
+}
+
+impl<K: HeapSize, V: HeapSize> HeapSize for BTreeMap<K, V> {
+    fn heap_used(&self) -> usize {
+        self.len() * (mem::size_of::<K>() + mem::size_of::<V>())
+            + self.iter().map(|(k, v)| k.heap_used() + v.heap_used()).sum::<usize>()
+    }
+
+    fn heap_reserved(&self) -> usize {
+        btree_reserved(self.len(), mem::size_of::<K>() + mem::size_of::<V>())
+            + self.iter().map(|(k, v)| k.heap_reserved() + v.heap_reserved()).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize> HeapSize for BTreeSet<T> {
+    fn heap_used(&self) -> usize {
+        self.len() * mem::size_of::<T>() + self.iter().map(HeapSize::heap_used).sum::<usize>()
+    }
+
+    fn heap_reserved(&self) -> usize {
+        btree_reserved(self.len(), mem::size_of::<T>()) + self.iter().map(HeapSize::heap_reserved).sum::<usize>()
+    }
+}
@@ -0,0 +1,433 @@
+// A string that keeps short text inline.
+//
+// Coffee names like "Latte" or "Coffee1" are a few bytes long, yet every `String` holding one
+// allocates on the heap. `InlineString` is exactly as big as a `String` (24 bytes on 64-bit
+// targets) and uses those bytes differently depending on the length of the text:
+//
+// - up to 23 bytes: the text itself, followed by one byte holding a tag bit and the length
+// - longer: the pointer, length and capacity of a heap buffer, just like a `String`
+//
+// The capacity is stored in little-endian byte order and can never exceed `isize::MAX`, so the
+// last byte of a heap string never has its top bit set, which is how the two are told apart.
+
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::mem::{self, ManuallyDrop};
+use std::ops::{Deref, DerefMut};
+use std::slice;
+use std::str;
+
+use super::heap_size::HeapSize;
+
+/// How many bytes fit without allocating.
+pub const INLINE_CAPACITY: usize = mem::size_of::<String>() - 1;
+
+const INLINE_TAG: u8 = 0x80;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Inline {
+    bytes: [u8; INLINE_CAPACITY],
+    // `INLINE_TAG | len`
+    tag: u8,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Heap {
+    ptr: *mut u8,
+    len: usize,
+    capacity_le: usize,
+}
+
+#[repr(C)]
+union Repr {
+    inline: Inline,
+    heap: Heap,
+}
+
+pub struct InlineString {
+    repr: Repr,
+}
+
+// Owns its heap buffer exclusively, just like a `String`
+unsafe impl Send for InlineString {}
+unsafe impl Sync for InlineString {}
+
+const _: () = assert!(mem::size_of::<InlineString>() == mem::size_of::<String>());
+
+impl InlineString {
+    pub const fn new() -> InlineString {
+        InlineString {
+            repr: Repr { inline: Inline { bytes: [0; INLINE_CAPACITY], tag: INLINE_TAG } },
+        }
+    }
+
+    // Starts out on the heap right away if `capacity` bytes do not fit inline
+    pub fn with_capacity(capacity: usize) -> InlineString {
+        if capacity <= INLINE_CAPACITY {
+            InlineString::new()
+        } else {
+            InlineString::from_heap(String::with_capacity(capacity))
+        }
+    }
+
+    fn from_heap(string: String) -> InlineString {
+        let mut string = ManuallyDrop::new(string);
+        InlineString {
+            repr: Repr {
+                heap: Heap {
+                    ptr: string.as_mut_ptr(),
+                    len: string.len(),
+                    capacity_le: string.capacity().to_le(),
+                },
+            },
+        }
+    }
+
+    // `text` must fit inline
+    fn from_inline(text: &str) -> InlineString {
+        let mut inline = Inline { bytes: [0; INLINE_CAPACITY], tag: INLINE_TAG | text.len() as u8 };
+        inline.bytes[..text.len()].copy_from_slice(text.as_bytes());
+        InlineString { repr: Repr { inline } }
+    }
+
+    /// Whether the text has been moved to the heap.
+    pub fn spilled(&self) -> bool {
+        // Both variants are fully initialized and the tag is the last byte of either
+        unsafe { self.repr.inline.tag & INLINE_TAG == 0 }
+    }
+
+    pub fn capacity(&self) -> usize {
+        if self.spilled() {
+            unsafe { usize::from_le(self.repr.heap.capacity_le) }
+        } else {
+            INLINE_CAPACITY
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        unsafe {
+            let bytes = if self.spilled() {
+                slice::from_raw_parts(self.repr.heap.ptr, self.repr.heap.len)
+            } else {
+                let len = (self.repr.inline.tag & !INLINE_TAG) as usize;
+                &self.repr.inline.bytes[..len]
+            };
+            str::from_utf8_unchecked(bytes)
+        }
+    }
+
+    pub fn as_mut_str(&mut self) -> &mut str {
+        unsafe {
+            let bytes = if self.spilled() {
+                slice::from_raw_parts_mut(self.repr.heap.ptr, self.repr.heap.len)
+            } else {
+                let len = (self.repr.inline.tag & !INLINE_TAG) as usize;
+                &mut self.repr.inline.bytes[..len]
+            };
+            str::from_utf8_unchecked_mut(bytes)
+        }
+    }
+
+    // The new length must be on a char boundary and fit inline
+    unsafe fn set_inline_len(&mut self, len: usize) {
+        self.repr.inline.tag = INLINE_TAG | len as u8;
+    }
+
+    // Hands the heap buffer to `f` as a `String`. In the meantime this string is empty, so a
+    // panic in `f` can not free the buffer twice.
+    fn with_heap<R>(&mut self, f: impl FnOnce(&mut String) -> R) -> R {
+        let mut string = match mem::take(self).into_string_if_spilled() {
+            Ok(string) => string,
+            Err(_) => unreachable!("with_heap is only called on spilled strings"),
+        };
+        let result = f(&mut string);
+        *self = InlineString::from_heap(string);
+        result
+    }
+
+    fn into_string_if_spilled(self) -> Result<String, InlineString> {
+        if !self.spilled() {
+            return Err(self);
+        }
+        let this = ManuallyDrop::new(self);
+        unsafe {
+            let heap = this.repr.heap;
+            Ok(String::from_raw_parts(heap.ptr, heap.len, usize::from_le(heap.capacity_le)))
+        }
+    }
+
+    // Moves the text to the heap, with room for `capacity` bytes
+    fn spill(&mut self, capacity: usize) {
+        if !self.spilled() {
+            let mut string = String::with_capacity(capacity.max(self.len()));
+            string.push_str(self.as_str());
+            *self = InlineString::from_heap(string);
+        }
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = self.len().checked_add(additional).expect("capacity overflow");
+        if self.spilled() {
+            self.with_heap(|string| string.reserve(additional));
+        } else if needed > INLINE_CAPACITY {
+            self.spill(needed.max(INLINE_CAPACITY * 2));
+        }
+    }
+
+    // Moves the text back inline if it fits, otherwise shrinks the heap buffer
+    pub fn shrink_to_fit(&mut self) {
+        if !self.spilled() {
+            return;
+        }
+        if self.len() <= INLINE_CAPACITY {
+            *self = InlineString::from_inline(self.as_str());
+        } else {
+            self.with_heap(|string| string.shrink_to_fit());
+        }
+    }
+
+    pub fn push(&mut self, ch: char) {
+        self.push_str(ch.encode_utf8(&mut [0; 4]));
+    }
+
+    pub fn push_str(&mut self, text: &str) {
+        let len = self.len();
+        if !self.spilled() && len + text.len() <= INLINE_CAPACITY {
+            unsafe {
+                self.repr.inline.bytes[len..len + text.len()].copy_from_slice(text.as_bytes());
+                self.set_inline_len(len + text.len());
+            }
+            return;
+        }
+        self.reserve(text.len());
+        self.with_heap(|string| string.push_str(text));
+    }
+
+    pub fn insert(&mut self, index: usize, ch: char) {
+        self.insert_str(index, ch.encode_utf8(&mut [0; 4]));
+    }
+
+    pub fn insert_str(&mut self, index: usize, text: &str) {
+        assert!(self.is_char_boundary(index), "index {index} is not a char boundary");
+        let len = self.len();
+        if !self.spilled() && len + text.len() <= INLINE_CAPACITY {
+            unsafe {
+                let bytes = &mut self.repr.inline.bytes;
+                bytes.copy_within(index..len, index + text.len());
+                bytes[index..index + text.len()].copy_from_slice(text.as_bytes());
+                self.set_inline_len(len + text.len());
+            }
+            return;
+        }
+        self.reserve(text.len());
+        self.with_heap(|string| string.insert_str(index, text));
+    }
+
+    pub fn truncate(&mut self, new_len: usize) {
+        if new_len >= self.len() {
+            return;
+        }
+        assert!(self.is_char_boundary(new_len), "new_len {new_len} is not a char boundary");
+        if self.spilled() {
+            self.with_heap(|string| string.truncate(new_len));
+        } else {
+            unsafe { self.set_inline_len(new_len) };
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<char> {
+        let ch = self.chars().next_back()?;
+        self.truncate(self.len() - ch.len_utf8());
+        Some(ch)
+    }
+
+    // Moves the text at `at..` into a new string, which is inline if it is short enough
+    pub fn split_off(&mut self, at: usize) -> InlineString {
+        assert!(self.is_char_boundary(at), "`at` {at} is not a char boundary");
+        let other = InlineString::from(&self[at..]);
+        self.truncate(at);
+        other
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    pub fn into_string(self) -> String {
+        match self.into_string_if_spilled() {
+            Ok(string) => string,
+            Err(inline) => String::from(inline.as_str()),
+        }
+    }
+}
+
+impl Drop for InlineString {
+    fn drop(&mut self) {
+        if self.spilled() {
+            let heap = unsafe { self.repr.heap };
+            unsafe { drop(String::from_raw_parts(heap.ptr, heap.len, usize::from_le(heap.capacity_le))) };
+        }
+    }
+}
+
+impl Deref for InlineString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl DerefMut for InlineString {
+    fn deref_mut(&mut self) -> &mut str {
+        self.as_mut_str()
+    }
+}
+
+impl Default for InlineString {
+    fn default() -> Self {
+        InlineString::new()
+    }
+}
+
+impl Clone for InlineString {
+    fn clone(&self) -> Self {
+        InlineString::from(self.as_str())
+    }
+}
+
+impl From<&str> for InlineString {
+    fn from(text: &str) -> Self {
+        if text.len() <= INLINE_CAPACITY {
+            InlineString::from_inline(text)
+        } else {
+            InlineString::from_heap(String::from(text))
+        }
+    }
+}
+
+// Short strings are moved inline and their heap buffer is freed, long ones keep their buffer
+impl From<String> for InlineString {
+    fn from(string: String) -> Self {
+        if string.len() <= INLINE_CAPACITY {
+            InlineString::from_inline(&string)
+        } else {
+            InlineString::from_heap(string)
+        }
+    }
+}
+
+impl From<InlineString> for String {
+    fn from(string: InlineString) -> Self {
+        string.into_string()
+    }
+}
+
+impl AsRef<str> for InlineString {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+// Lets a `HashMap<InlineString, _>` be searched with a `&str`
+impl Borrow<str> for InlineString {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Debug for InlineString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for InlineString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Write for InlineString {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        self.push_str(text);
+        Ok(())
+    }
+}
+
+impl PartialEq for InlineString {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for InlineString {}
+
+impl PartialEq<str> for InlineString {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for InlineString {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialOrd for InlineString {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for InlineString {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+// Must hash like a `str` for `Borrow<str>` lookups to work
+impl Hash for InlineString {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state);
+    }
+}
+
+impl Extend<char> for InlineString {
+    fn extend<I: IntoIterator<Item = char>>(&mut self, chars: I) {
+        for ch in chars {
+            self.push(ch);
+        }
+    }
+}
+
+impl<'a> Extend<&'a str> for InlineString {
+    fn extend<I: IntoIterator<Item = &'a str>>(&mut self, texts: I) {
+        for text in texts {
+            self.push_str(text);
+        }
+    }
+}
+
+impl FromIterator<char> for InlineString {
+    fn from_iter<I: IntoIterator<Item = char>>(chars: I) -> Self {
+        let mut string = InlineString::new();
+        string.extend(chars);
+        string
+    }
+}
+
+impl HeapSize for InlineString {
+    fn heap_used(&self) -> usize {
+        if self.spilled() { self.len() } else { 0 }
+    }
+
+    fn heap_reserved(&self) -> usize {
+        if self.spilled() { self.capacity() } else { 0 }
+    }
+}
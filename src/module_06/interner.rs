@@ -0,0 +1,151 @@
+// String interning.
+//
+// The maps in the demos use keys like "Coffee1".."Coffee150": every key is its own heap
+// allocation, and every lookup hashes and compares the whole string. An `Interner` stores each
+// distinct string once and hands out a `Symbol`, a 4 byte handle. Two symbols from the same
+// interner are equal exactly when their strings are, so comparing and hashing them costs the
+// same as for a `u32`, and the string can always be looked up again with `resolve()`.
+//
+// The strings are copied into large buffers that are never reallocated, so they stay where they
+// are while the interner lives and can be referenced by the lookup table without copying them a
+// second time.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+
+use super::heap_size::HeapSize;
+
+const MIN_BUFFER_CAPACITY: usize = 1024;
+
+/// A handle to a string stored in an `Interner`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    pub fn as_u32(self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+impl HeapSize for Symbol {
+    fn heap_used(&self) -> usize {
+        0
+    }
+
+    fn heap_reserved(&self) -> usize {
+        0
+    }
+}
+
+pub struct Interner {
+    // The `&'static str`s point into `buffers`. They never escape with that lifetime: every
+    // method hands them out borrowed from `&self`.
+    symbols: HashMap<&'static str, Symbol>,
+    strings: Vec<&'static str>,
+    // Only the last buffer is written to. A full buffer is kept and a bigger one is started,
+    // pushing to a `String` within its capacity never moves the bytes already in it.
+    current: String,
+    full: Vec<String>,
+}
+
+impl Interner {
+    pub fn new() -> Interner {
+        Interner::with_capacity(0)
+    }
+
+    // Room for `capacity` strings before the lookup table has to grow
+    pub fn with_capacity(capacity: usize) -> Interner {
+        Interner {
+            symbols: HashMap::with_capacity(capacity),
+            strings: Vec::with_capacity(capacity),
+            current: String::new(),
+            full: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    /// Returns the symbol of `name`, storing it first if it has not been seen before.
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(name) {
+            return symbol;
+        }
+        let symbol = Symbol(u32::try_from(self.strings.len()).expect("too many interned strings"));
+        let stored = self.store(name);
+        self.symbols.insert(stored, symbol);
+        self.strings.push(stored);
+        symbol
+    }
+
+    /// The symbol of `name`, if it has been interned, without storing it.
+    pub fn get(&self, name: &str) -> Option<Symbol> {
+        self.symbols.get(name).copied()
+    }
+
+    /// The string behind `symbol`.
+    ///
+    /// Panics if `symbol` comes from a different interner that has more strings than this one.
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        self.strings[symbol.0 as usize]
+    }
+
+    // Symbols are handed out in order, so this is also the order the strings were interned in
+    pub fn iter(&self) -> impl Iterator<Item = (Symbol, &str)> + '_ {
+        self.strings.iter().enumerate().map(|(index, &name)| (Symbol(index as u32), name))
+    }
+
+    fn store(&mut self, name: &str) -> &'static str {
+        let available = self.current.capacity() - self.current.len();
+        if available < name.len() {
+            let capacity = (self.current.capacity() * 2).max(name.len()).max(MIN_BUFFER_CAPACITY);
+            let full = mem::replace(&mut self.current, String::with_capacity(capacity));
+            if !full.is_empty() {
+                self.full.push(full);
+            }
+        }
+
+        let start = self.current.len();
+        self.current.push_str(name);
+        let stored = &self.current[start..];
+        // The buffer is neither reallocated nor dropped before the interner is, see above
+        unsafe { &*(stored as *const str) }
+    }
+}
+
+impl Default for Interner {
+    fn default() -> Self {
+        Interner::new()
+    }
+}
+
+impl fmt::Debug for Interner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl HeapSize for Interner {
+    fn heap_used(&self) -> usize {
+        self.symbols.heap_used() + self.strings.heap_used() + self.current.heap_used() + self.full.heap_used()
+    }
+
+    fn heap_reserved(&self) -> usize {
+        self.symbols.heap_reserved()
+            + self.strings.heap_reserved()
+            + self.current.heap_reserved()
+            + self.full.heap_reserved()
+    }
+}
@@ -0,0 +1,677 @@
+// Persistent (immutable) collections with structural sharing.
+//
+// Cloning a `Vec` or a `HashMap` copies every element. `PVec` and `PMap` instead store their
+// elements in trees of reference-counted nodes, so a clone only bumps one reference count.
+// Changing a clone copies just the nodes on the path to the changed element (`Arc::make_mut`)
+// and leaves everything else shared with the original, which keeps its old contents.
+//
+// - `PVec<T>` is a bit-partitioned trie with 32-way branching and a separate tail buffer, so
+//   pushing is amortized O(1) and everything else is O(log32 n).
+// - `PMap<K, V>` is a hash array mapped trie (HAMT): every level uses 5 bits of the hash to pick
+//   a child, and a bitmap records which of the 32 children actually exist.
+
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::ops::Index;
+use std::sync::Arc;
+
+const BITS: usize = 5;
+const WIDTH: usize = 1 << BITS;
+const MASK: usize = WIDTH - 1;
+
+#[derive(Clone)]
+enum VecNode<T> {
+    Branch(Vec<Arc<VecNode<T>>>),
+    Leaf(Vec<T>),
+}
+
+impl<T> VecNode<T> {
+    fn children_mut(&mut self) -> &mut Vec<Arc<VecNode<T>>> {
+        match self {
+            VecNode::Branch(children) => children,
+            VecNode::Leaf(_) => unreachable!("expected a branch node"),
+        }
+    }
+
+    fn children(&self) -> &Vec<Arc<VecNode<T>>> {
+        match self {
+            VecNode::Branch(children) => children,
+            VecNode::Leaf(_) => unreachable!("expected a branch node"),
+        }
+    }
+
+    fn values(&self) -> &Vec<T> {
+        match self {
+            VecNode::Leaf(values) => values,
+            VecNode::Branch(_) => unreachable!("expected a leaf node"),
+        }
+    }
+
+    fn values_mut(&mut self) -> &mut Vec<T> {
+        match self {
+            VecNode::Leaf(values) => values,
+            VecNode::Branch(_) => unreachable!("expected a leaf node"),
+        }
+    }
+}
+
+pub struct PVec<T> {
+    len: usize,
+    // Number of index bits consumed above the leaves
+    shift: usize,
+    root: Arc<VecNode<T>>,
+    // The last (up to 32) elements, kept out of the tree so that `push()` rarely touches it
+    tail: Arc<Vec<T>>,
+}
+
+impl<T: Clone> PVec<T> {
+    pub fn new() -> PVec<T> {
+        PVec {
+            len: 0,
+            shift: BITS,
+            root: Arc::new(VecNode::Branch(Vec::new())),
+            tail: Arc::new(Vec::new()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Index of the first element stored in the tail
+    fn tail_offset(&self) -> usize {
+        if self.len < WIDTH {
+            0
+        } else {
+            ((self.len - 1) >> BITS) << BITS
+        }
+    }
+
+    fn leaf_for(&self, index: usize) -> &Vec<T> {
+        if index >= self.tail_offset() {
+            return &self.tail;
+        }
+        let mut node = &self.root;
+        let mut level = self.shift;
+        while level > 0 {
+            node = &node.children()[(index >> level) & MASK];
+            level -= BITS;
+        }
+        node.values()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len {
+            return None;
+        }
+        Some(&self.leaf_for(index)[index & MASK])
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.len.checked_sub(1).and_then(|index| self.get(index))
+    }
+
+    // Copies the nodes on the path to `index` if they are shared with another vector
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.len {
+            return None;
+        }
+        if index >= self.tail_offset() {
+            return Some(&mut Arc::make_mut(&mut self.tail)[index & MASK]);
+        }
+
+        let mut node = Arc::make_mut(&mut self.root);
+        let mut level = self.shift;
+        while level > 0 {
+            node = Arc::make_mut(&mut node.children_mut()[(index >> level) & MASK]);
+            level -= BITS;
+        }
+        Some(&mut node.values_mut()[index & MASK])
+    }
+
+    pub fn set(&mut self, index: usize, value: T) -> T {
+        let len = self.len;
+        let slot = self
+            .get_mut(index)
+            .unwrap_or_else(|| panic!("index out of bounds: the len is {len} but the index is {index}"));
+        std::mem::replace(slot, value)
+    }
+
+    pub fn push(&mut self, value: T) {
+        if self.len - self.tail_offset() < WIDTH {
+            Arc::make_mut(&mut self.tail).push(value);
+            self.len += 1;
+            return;
+        }
+
+        // The tail is full: move it into the tree and start a new one
+        let full_tail = std::mem::replace(&mut self.tail, Arc::new(vec![value]));
+        let leaf = Arc::new(VecNode::Leaf(Arc::unwrap_or_clone(full_tail)));
+
+        if (self.len >> BITS) > (1 << self.shift) {
+            // The tree is full as well, so it grows a new level on top
+            let old_root = std::mem::replace(&mut self.root, Arc::new(VecNode::Branch(Vec::new())));
+            let path = new_path(self.shift, leaf);
+            self.root = Arc::new(VecNode::Branch(vec![old_root, path]));
+            self.shift += BITS;
+        } else {
+            push_tail(self.len, self.shift, &mut self.root, leaf);
+        }
+        self.len += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        if self.len - self.tail_offset() > 1 || self.len == 1 {
+            self.len -= 1;
+            let value = Arc::make_mut(&mut self.tail).pop();
+            if self.len == 0 {
+                *self = PVec::new();
+            }
+            return value;
+        }
+
+        // The tail is about to become empty: the last leaf of the tree becomes the new tail
+        let value = Arc::make_mut(&mut self.tail).pop();
+        let new_tail = self.leaf_for(self.len - 2).clone();
+        self.tail = Arc::new(new_tail);
+
+        if !pop_tail(self.len, self.shift, &mut self.root) {
+            self.root = Arc::new(VecNode::Branch(Vec::new()));
+        }
+        if self.shift > BITS && self.root.children().len() == 1 {
+            let only_child = Arc::clone(&self.root.children()[0]);
+            self.root = only_child;
+            self.shift -= BITS;
+        }
+        self.len -= 1;
+        value
+    }
+
+    pub fn iter(&self) -> PVecIter<'_, T> {
+        PVecIter {
+            vec: self,
+            index: 0,
+        }
+    }
+}
+
+fn new_path<T>(level: usize, node: Arc<VecNode<T>>) -> Arc<VecNode<T>> {
+    if level == 0 {
+        node
+    } else {
+        Arc::new(VecNode::Branch(vec![new_path(level - BITS, node)]))
+    }
+}
+
+// `len` is the length before the leaf is added, so `len - 1` is the last index in the leaf
+fn push_tail<T: Clone>(len: usize, level: usize, parent: &mut Arc<VecNode<T>>, leaf: Arc<VecNode<T>>) {
+    let sub_index = ((len - 1) >> level) & MASK;
+    let children = Arc::make_mut(parent).children_mut();
+    if level == BITS {
+        children.push(leaf);
+    } else if sub_index < children.len() {
+        push_tail(len, level - BITS, &mut children[sub_index], leaf);
+    } else {
+        children.push(new_path(level - BITS, leaf));
+    }
+}
+
+// Removes the last leaf of the tree. Returns false if `node` has become empty.
+fn pop_tail<T: Clone>(len: usize, level: usize, node: &mut Arc<VecNode<T>>) -> bool {
+    let sub_index = ((len - 2) >> level) & MASK;
+    let children = Arc::make_mut(node).children_mut();
+    if level > BITS {
+        if !pop_tail(len, level - BITS, &mut children[sub_index]) {
+            children.pop();
+        }
+    } else {
+        children.pop();
+    }
+    !children.is_empty()
+}
+
+impl<T: Clone> Default for PVec<T> {
+    fn default() -> Self {
+        PVec::new()
+    }
+}
+
+// O(1): only the reference counts of the root and the tail change
+impl<T> Clone for PVec<T> {
+    fn clone(&self) -> Self {
+        PVec {
+            len: self.len,
+            shift: self.shift,
+            root: Arc::clone(&self.root),
+            tail: Arc::clone(&self.tail),
+        }
+    }
+}
+
+impl<T: Clone> Index<usize> for PVec<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index)
+            .unwrap_or_else(|| panic!("index out of bounds: the len is {} but the index is {}", self.len, index))
+    }
+}
+
+impl<T: Clone> FromIterator<T> for PVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut vec = PVec::new();
+        vec.extend(iter);
+        vec
+    }
+}
+
+impl<T: Clone> Extend<T> for PVec<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+impl<T: Clone, const N: usize> From<[T; N]> for PVec<T> {
+    fn from(values: [T; N]) -> Self {
+        values.into_iter().collect()
+    }
+}
+
+impl<T: Clone + PartialEq> PartialEq for PVec<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Clone + fmt::Debug> fmt::Debug for PVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+pub struct PVecIter<'a, T> {
+    vec: &'a PVec<T>,
+    index: usize,
+}
+
+impl<'a, T: Clone> Iterator for PVecIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let value = self.vec.get(self.index)?;
+        self.index += 1;
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.vec.len - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, T: Clone> IntoIterator for &'a PVec<T> {
+    type Item = &'a T;
+    type IntoIter = PVecIter<'a, T>;
+
+    fn into_iter(self) -> PVecIter<'a, T> {
+        self.iter()
+    }
+}
+
+#[derive(Clone)]
+enum MapNode<K, V> {
+    Branch {
+        bitmap: u32,
+        children: Vec<Arc<MapNode<K, V>>>,
+    },
+    // All entries share the same full hash, there is more than one only on hash collisions
+    Leaf {
+        hash: u64,
+        entries: Vec<(K, V)>,
+    },
+}
+
+fn hash_index(hash: u64, shift: usize) -> u32 {
+    ((hash >> shift) & MASK as u64) as u32
+}
+
+// Position of the child for `bit` in the compressed `children` vector
+fn child_position(bitmap: u32, bit: u32) -> usize {
+    (bitmap & (bit - 1)).count_ones() as usize
+}
+
+pub struct PMap<K, V, S = RandomState> {
+    root: Option<Arc<MapNode<K, V>>>,
+    len: usize,
+    // Shared by all clones, so that they agree on where every key lives
+    hasher: S,
+}
+
+impl<K, V> PMap<K, V, RandomState> {
+    pub fn new() -> PMap<K, V, RandomState> {
+        PMap {
+            root: None,
+            len: 0,
+            hasher: RandomState::new(),
+        }
+    }
+}
+
+impl<K, V, S> PMap<K, V, S> {
+    pub fn with_hasher(hasher: S) -> PMap<K, V, S> {
+        PMap { root: None, len: 0, hasher }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> PMapIter<'_, K, V> {
+        PMapIter {
+            stack: self.root.iter().map(|root| (&**root, 0)).collect(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone, S: BuildHasher> PMap<K, V, S> {
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hasher.hash_one(key);
+        let mut node = self.root.as_deref()?;
+        let mut shift = 0;
+        loop {
+            match node {
+                MapNode::Leaf { hash: leaf_hash, entries } => {
+                    if *leaf_hash != hash {
+                        return None;
+                    }
+                    return entries.iter().find(|(k, _)| k.borrow() == key).map(|(_, v)| v);
+                }
+                MapNode::Branch { bitmap, children } => {
+                    let bit = 1 << hash_index(hash, shift);
+                    if bitmap & bit == 0 {
+                        return None;
+                    }
+                    node = &children[child_position(*bitmap, bit)];
+                    shift += BITS;
+                }
+            }
+        }
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let hash = self.hasher.hash_one(&key);
+        let old = match &mut self.root {
+            Some(root) => insert_node(root, 0, hash, key, value),
+            None => {
+                self.root = Some(Arc::new(MapNode::Leaf {
+                    hash,
+                    entries: vec![(key, value)],
+                }));
+                None
+            }
+        };
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        // Check first, so that removing a missing key never copies any shared node
+        if !self.contains_key(key) {
+            return None;
+        }
+
+        let hash = self.hasher.hash_one(key);
+        let root = self.root.as_mut()?;
+        let (removed, now_empty) = remove_node(root, 0, hash, key);
+        if now_empty {
+            self.root = None;
+        }
+        self.len -= 1;
+        removed
+    }
+}
+
+fn insert_node<K: Eq + Clone, V: Clone>(
+    node: &mut Arc<MapNode<K, V>>,
+    shift: usize,
+    hash: u64,
+    key: K,
+    value: V,
+) -> Option<V> {
+    if let MapNode::Leaf { hash: leaf_hash, .. } = **node {
+        if leaf_hash != hash {
+            // Two different hashes end up here: replace the leaf by a branch holding both
+            let existing = Arc::clone(node);
+            let new_leaf = Arc::new(MapNode::Leaf {
+                hash,
+                entries: vec![(key, value)],
+            });
+            *node = merge_leaves(shift, existing, leaf_hash, new_leaf, hash);
+            return None;
+        }
+    }
+
+    match Arc::make_mut(node) {
+        MapNode::Leaf { entries, .. } => match entries.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, existing)) => Some(std::mem::replace(existing, value)),
+            None => {
+                entries.push((key, value));
+                None
+            }
+        },
+        MapNode::Branch { bitmap, children } => {
+            let bit = 1 << hash_index(hash, shift);
+            let position = child_position(*bitmap, bit);
+            if *bitmap & bit != 0 {
+                insert_node(&mut children[position], shift + BITS, hash, key, value)
+            } else {
+                children.insert(
+                    position,
+                    Arc::new(MapNode::Leaf {
+                        hash,
+                        entries: vec![(key, value)],
+                    }),
+                );
+                *bitmap |= bit;
+                None
+            }
+        }
+    }
+}
+
+fn merge_leaves<K, V>(
+    shift: usize,
+    first: Arc<MapNode<K, V>>,
+    first_hash: u64,
+    second: Arc<MapNode<K, V>>,
+    second_hash: u64,
+) -> Arc<MapNode<K, V>> {
+    let first_index = hash_index(first_hash, shift);
+    let second_index = hash_index(second_hash, shift);
+
+    if first_index == second_index {
+        let child = merge_leaves(shift + BITS, first, first_hash, second, second_hash);
+        return Arc::new(MapNode::Branch {
+            bitmap: 1 << first_index,
+            children: vec![child],
+        });
+    }
+
+    let children = if first_index < second_index {
+        vec![first, second]
+    } else {
+        vec![second, first]
+    };
+    Arc::new(MapNode::Branch {
+        bitmap: (1 << first_index) | (1 << second_index),
+        children,
+    })
+}
+
+// Returns the removed value and whether `node` is now empty
+fn remove_node<K, V, Q>(node: &mut Arc<MapNode<K, V>>, shift: usize, hash: u64, key: &Q) -> (Option<V>, bool)
+where
+    K: Borrow<Q> + Clone,
+    V: Clone,
+    Q: Eq + ?Sized,
+{
+    let (removed, collapsed) = match Arc::make_mut(node) {
+        MapNode::Leaf { entries, .. } => {
+            let removed = entries
+                .iter()
+                .position(|(k, _)| k.borrow() == key)
+                .map(|position| entries.swap_remove(position).1);
+            return (removed, entries.is_empty());
+        }
+        MapNode::Branch { bitmap, children } => {
+            let bit = 1 << hash_index(hash, shift);
+            let position = child_position(*bitmap, bit);
+            let (removed, child_empty) = remove_node(&mut children[position], shift + BITS, hash, key);
+            if child_empty {
+                children.remove(position);
+                *bitmap &= !bit;
+            }
+            if children.is_empty() {
+                return (removed, true);
+            }
+
+            // A branch with a single leaf is replaced by the leaf, so lookups stay short
+            let collapsed = (children.len() == 1 && matches!(*children[0], MapNode::Leaf { .. }))
+                .then(|| children.pop().unwrap());
+            (removed, collapsed)
+        }
+    };
+
+    if let Some(leaf) = collapsed {
+        *node = leaf;
+    }
+    (removed, false)
+}
+
+impl<K, V> Default for PMap<K, V, RandomState> {
+    fn default() -> Self {
+        PMap::new()
+    }
+}
+
+// O(1): the clone shares every node with the original
+impl<K, V, S: Clone> Clone for PMap<K, V, S> {
+    fn clone(&self) -> Self {
+        PMap {
+            root: self.root.clone(),
+            len: self.len,
+            hasher: self.hasher.clone(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> FromIterator<(K, V)> for PMap<K, V, RandomState> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = PMap::new();
+        for (key, value) in iter {
+            map.insert(key, value);
+        }
+        map
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone, const N: usize> From<[(K, V); N]> for PMap<K, V, RandomState> {
+    fn from(entries: [(K, V); N]) -> Self {
+        entries.into_iter().collect()
+    }
+}
+
+impl<K, V, S> PartialEq for PMap<K, V, S>
+where
+    K: Hash + Eq + Clone,
+    V: Clone + PartialEq,
+    S: BuildHasher,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, S> fmt::Debug for PMap<K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+// Depth-first walk over the trie, in hash order like a `HashMap`
+pub struct PMapIter<'a, K, V> {
+    stack: Vec<(&'a MapNode<K, V>, usize)>,
+}
+
+impl<'a, K, V> Iterator for PMapIter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, index) = self.stack.last_mut()?;
+            let node: &'a MapNode<K, V> = node;
+            match node {
+                MapNode::Leaf { entries, .. } => {
+                    if let Some((k, v)) = entries.get(*index) {
+                        *index += 1;
+                        return Some((k, v));
+                    }
+                }
+                MapNode::Branch { children, .. } => {
+                    if let Some(child) = children.get(*index) {
+                        *index += 1;
+                        self.stack.push((child, 0));
+                        continue;
+                    }
+                }
+            }
+            self.stack.pop();
+        }
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a PMap<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = PMapIter<'a, K, V>;
+
+    fn into_iter(self) -> PMapIter<'a, K, V> {
+        self.iter()
+    }
+}
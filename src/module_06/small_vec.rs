@@ -0,0 +1,475 @@
+// A vector that keeps its first few items inline.
+//
+// `vec![2, 4, 6]` allocates on the heap even though three numbers would fit in the 24 bytes the
+// `Vec` itself takes up on the stack. `SmallVec<T, N>` stores up to `N` items inside the struct
+// and only moves them to a heap allocated `Vec` (it "spills") once more are pushed, so short
+// vectors never touch the allocator. Once spilled it stays on the heap until `shrink_to_fit()`
+// finds that the items fit inline again.
+
+use std::fmt;
+use std::mem::{self, MaybeUninit};
+use std::ops::{Bound, Deref, DerefMut, RangeBounds};
+use std::ptr;
+use std::slice;
+
+use super::heap_size::HeapSize;
+
+enum Storage<T, const N: usize> {
+    // Only the first `len` items are initialized
+    Inline { items: [MaybeUninit<T>; N], len: usize },
+    Heap(Vec<T>),
+}
+
+pub struct SmallVec<T, const N: usize> {
+    storage: Storage<T, N>,
+}
+
+impl<T, const N: usize> SmallVec<T, N> {
+    pub const fn new() -> SmallVec<T, N> {
+        SmallVec {
+            storage: Storage::Inline { items: [const { MaybeUninit::uninit() }; N], len: 0 },
+        }
+    }
+
+    // Starts out on the heap right away if `capacity` items do not fit inline
+    pub fn with_capacity(capacity: usize) -> SmallVec<T, N> {
+        if capacity <= N {
+            SmallVec::new()
+        } else {
+            SmallVec { storage: Storage::Heap(Vec::with_capacity(capacity)) }
+        }
+    }
+
+    /// How many items fit before the vector has to spill to the heap.
+    pub const fn inline_capacity() -> usize {
+        N
+    }
+
+    /// Whether the items have been moved to the heap.
+    pub fn spilled(&self) -> bool {
+        matches!(self.storage, Storage::Heap(_))
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.storage {
+            Storage::Inline { len, .. } => *len,
+            Storage::Heap(vec) => vec.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        match &self.storage {
+            Storage::Inline { .. } => N,
+            Storage::Heap(vec) => vec.capacity(),
+        }
+    }
+
+    pub fn as_ptr(&self) -> *const T {
+        match &self.storage {
+            Storage::Inline { items, .. } => items.as_ptr() as *const T,
+            Storage::Heap(vec) => vec.as_ptr(),
+        }
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        match &mut self.storage {
+            Storage::Inline { items, .. } => items.as_mut_ptr() as *mut T,
+            Storage::Heap(vec) => vec.as_mut_ptr(),
+        }
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.as_ptr(), self.len()) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        let len = self.len();
+        unsafe { slice::from_raw_parts_mut(self.as_mut_ptr(), len) }
+    }
+
+    // The items in `..new_len` must be initialized and `new_len` must not exceed the capacity
+    unsafe fn set_len(&mut self, new_len: usize) {
+        match &mut self.storage {
+            Storage::Inline { len, .. } => *len = new_len,
+            Storage::Heap(vec) => vec.set_len(new_len),
+        }
+    }
+
+    // Moves the inline items into a `Vec` with room for `capacity` items
+    fn spill(&mut self, capacity: usize) {
+        let vec = match &mut self.storage {
+            Storage::Inline { items, len } => {
+                let mut vec = Vec::with_capacity(capacity.max(*len));
+                unsafe {
+                    ptr::copy_nonoverlapping(items.as_ptr() as *const T, vec.as_mut_ptr(), *len);
+                    vec.set_len(*len);
+                }
+                *len = 0;
+                vec
+            }
+            Storage::Heap(_) => return,
+        };
+        self.storage = Storage::Heap(vec);
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = self.len().checked_add(additional).expect("capacity overflow");
+        match &mut self.storage {
+            Storage::Inline { .. } => {
+                if needed > N {
+                    // Grow like a `Vec` would, instead of spilling into a full allocation
+                    self.spill(needed.max(N * 2).max(4));
+                }
+            }
+            Storage::Heap(vec) => vec.reserve(additional),
+        }
+    }
+
+    // Moves the items back inline if they fit, otherwise shrinks the heap allocation
+    pub fn shrink_to_fit(&mut self) {
+        if let Storage::Heap(vec) = &mut self.storage {
+            if vec.len() > N {
+                vec.shrink_to_fit();
+                return;
+            }
+            let mut inline = SmallVec::new();
+            unsafe {
+                ptr::copy_nonoverlapping(vec.as_ptr(), inline.as_mut_ptr(), vec.len());
+                inline.set_len(vec.len());
+                vec.set_len(0);
+            }
+            *self = inline;
+        }
+    }
+
+    pub fn push(&mut self, item: T) {
+        if self.len() == self.capacity() {
+            self.reserve(1);
+        }
+        let len = self.len();
+        unsafe {
+            self.as_mut_ptr().add(len).write(item);
+            self.set_len(len + 1);
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let len = self.len();
+        if len == 0 {
+            return None;
+        }
+        unsafe {
+            self.set_len(len - 1);
+            Some(self.as_ptr().add(len - 1).read())
+        }
+    }
+
+    pub fn insert(&mut self, index: usize, item: T) {
+        let len = self.len();
+        assert!(index <= len, "insertion index (is {index}) should be <= len (is {len})");
+        if len == self.capacity() {
+            self.reserve(1);
+        }
+        unsafe {
+            let at = self.as_mut_ptr().add(index);
+            ptr::copy(at, at.add(1), len - index);
+            at.write(item);
+            self.set_len(len + 1);
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> T {
+        let len = self.len();
+        assert!(index < len, "removal index (is {index}) should be < len (is {len})");
+        unsafe {
+            let at = self.as_mut_ptr().add(index);
+            let item = at.read();
+            ptr::copy(at.add(1), at, len - index - 1);
+            self.set_len(len - 1);
+            item
+        }
+    }
+
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        let last = self.len() - 1;
+        self.as_mut_slice().swap(index, last);
+        self.pop().unwrap()
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        let old_len = self.len();
+        if len >= old_len {
+            return;
+        }
+        unsafe {
+            // The length is updated first, so a panicking `drop` can not lead to a double drop
+            self.set_len(len);
+            let tail = slice::from_raw_parts_mut(self.as_mut_ptr().add(len), old_len - len);
+            ptr::drop_in_place(tail);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut keep: F) {
+        self.retain_mut(|item| keep(item));
+    }
+
+    pub fn retain_mut<F: FnMut(&mut T) -> bool>(&mut self, mut keep: F) {
+        // Kept items are swapped to the front, so every item is still valid if `keep` panics
+        let mut kept = 0;
+        let items = self.as_mut_slice();
+        for index in 0..items.len() {
+            if keep(&mut items[index]) {
+                items.swap(kept, index);
+                kept += 1;
+            }
+        }
+        self.truncate(kept);
+    }
+
+    // Moves the items at `at..` into a new vector
+    pub fn split_off(&mut self, at: usize) -> SmallVec<T, N> {
+        let len = self.len();
+        assert!(at <= len, "`at` split index (is {at}) should be <= len (is {len})");
+        let mut other = SmallVec::with_capacity(len - at);
+        unsafe {
+            ptr::copy_nonoverlapping(self.as_ptr().add(at), other.as_mut_ptr(), len - at);
+            self.set_len(at);
+            other.set_len(len - at);
+        }
+        other
+    }
+
+    // Moves all items of `other` to the end of this vector, leaving `other` empty
+    pub fn append(&mut self, other: &mut SmallVec<T, N>) {
+        let count = other.len();
+        self.reserve(count);
+        let len = self.len();
+        unsafe {
+            ptr::copy_nonoverlapping(other.as_ptr(), self.as_mut_ptr().add(len), count);
+            other.set_len(0);
+            self.set_len(len + count);
+        }
+    }
+
+    // Removes the items in `range` right away and returns them. Ranges of up to `N` items are
+    // returned without allocating.
+    pub fn drain<R: RangeBounds<usize>>(&mut self, range: R) -> IntoIter<T, N> {
+        let len = self.len();
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => len,
+        };
+        assert!(start <= end && end <= len, "drain range {start}..{end} is out of bounds for length {len}");
+
+        let mut tail = self.split_off(end);
+        let drained = self.split_off(start);
+        self.append(&mut tail);
+        drained.into_iter()
+    }
+
+    pub fn into_vec(mut self) -> Vec<T> {
+        match mem::replace(&mut self.storage, Storage::Heap(Vec::new())) {
+            Storage::Heap(vec) => vec,
+            Storage::Inline { items, len } => {
+                let mut vec = Vec::with_capacity(len);
+                unsafe {
+                    ptr::copy_nonoverlapping(items.as_ptr() as *const T, vec.as_mut_ptr(), len);
+                    vec.set_len(len);
+                }
+                vec
+            }
+        }
+    }
+}
+
+impl<T: Clone, const N: usize> SmallVec<T, N> {
+    pub fn extend_from_slice(&mut self, items: &[T]) {
+        self.reserve(items.len());
+        for item in items {
+            self.push(item.clone());
+        }
+    }
+}
+
+impl<T, const N: usize> Drop for SmallVec<T, N> {
+    fn drop(&mut self) {
+        // A spilled vector is dropped by its `Vec`
+        if let Storage::Inline { .. } = self.storage {
+            unsafe { ptr::drop_in_place(self.as_mut_slice()) };
+        }
+    }
+}
+
+impl<T, const N: usize> Deref for SmallVec<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T, const N: usize> DerefMut for SmallVec<T, N> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<T, const N: usize> Default for SmallVec<T, N> {
+    fn default() -> Self {
+        SmallVec::new()
+    }
+}
+
+impl<T: Clone, const N: usize> Clone for SmallVec<T, N> {
+    fn clone(&self) -> Self {
+        let mut clone = SmallVec::with_capacity(self.len());
+        clone.extend_from_slice(self);
+        clone
+    }
+}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for SmallVec<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq, const N: usize> PartialEq for SmallVec<T, N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<T: Eq, const N: usize> Eq for SmallVec<T, N> {}
+
+impl<T, const N: usize> Extend<T> for SmallVec<T, N> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, items: I) {
+        let items = items.into_iter();
+        self.reserve(items.size_hint().0);
+        for item in items {
+            self.push(item);
+        }
+    }
+}
+
+impl<T, const N: usize> FromIterator<T> for SmallVec<T, N> {
+    fn from_iter<I: IntoIterator<Item = T>>(items: I) -> Self {
+        let mut vec = SmallVec::new();
+        vec.extend(items);
+        vec
+    }
+}
+
+impl<T, const N: usize, const M: usize> From<[T; M]> for SmallVec<T, N> {
+    fn from(items: [T; M]) -> Self {
+        items.into_iter().collect()
+    }
+}
+
+// Keeps the heap allocation of the `Vec`, even if the items would fit inline
+impl<T, const N: usize> From<Vec<T>> for SmallVec<T, N> {
+    fn from(vec: Vec<T>) -> Self {
+        SmallVec { storage: Storage::Heap(vec) }
+    }
+}
+
+pub struct IntoIter<T, const N: usize> {
+    // Its length is 0, so it only frees the heap allocation (if any) when dropped
+    vec: SmallVec<T, N>,
+    start: usize,
+    end: usize,
+}
+
+impl<T, const N: usize> Iterator for IntoIter<T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.start == self.end {
+            return None;
+        }
+        let item = unsafe { self.vec.as_ptr().add(self.start).read() };
+        self.start += 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.end - self.start, Some(self.end - self.start))
+    }
+}
+
+impl<T, const N: usize> DoubleEndedIterator for IntoIter<T, N> {
+    fn next_back(&mut self) -> Option<T> {
+        if self.start == self.end {
+            return None;
+        }
+        self.end -= 1;
+        Some(unsafe { self.vec.as_ptr().add(self.end).read() })
+    }
+}
+
+impl<T, const N: usize> ExactSizeIterator for IntoIter<T, N> {}
+
+impl<T, const N: usize> Drop for IntoIter<T, N> {
+    fn drop(&mut self) {
+        unsafe {
+            let rest = slice::from_raw_parts_mut(self.vec.as_mut_ptr().add(self.start), self.end - self.start);
+            ptr::drop_in_place(rest);
+        }
+    }
+}
+
+impl<T, const N: usize> IntoIterator for SmallVec<T, N> {
+    type Item = T;
+    type IntoIter = IntoIter<T, N>;
+
+    fn into_iter(mut self) -> IntoIter<T, N> {
+        let len = self.len();
+        unsafe { self.set_len(0) };
+        IntoIter { vec: self, start: 0, end: len }
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a SmallVec<T, N> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> slice::Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a mut SmallVec<T, N> {
+    type Item = &'a mut T;
+    type IntoIter = slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> slice::IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+// Inline items are part of the struct itself, so only a spilled vector uses the heap
+impl<T: HeapSize, const N: usize> HeapSize for SmallVec<T, N> {
+    fn heap_used(&self) -> usize {
+        let items = if self.spilled() { self.len() * mem::size_of::<T>() } else { 0 };
+        items + self.iter().map(HeapSize::heap_used).sum::<usize>()
+    }
+
+    fn heap_reserved(&self) -> usize {
+        let items = if self.spilled() { self.capacity() * mem::size_of::<T>() } else { 0 };
+        items + self.iter().map(HeapSize::heap_reserved).sum::<usize>()
+    }
+}
@@ -0,0 +1,176 @@
+pub mod epoch;
+pub mod lock_free;
+pub mod work_stealing;
+
+pub mod using_collections_concurrently {
+    use std::sync::atomic::{AtomicI64, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::lock_free::{LockFreeQueue, LockFreeStack};
+    use super::work_stealing::{Steal, ThreadPool, Worker};
+
+    #[derive(Debug, PartialEq, Clone)]
+    struct Coffee {
+        id: i32,
+        count: i32,
+    }
+
+    pub fn demo_lock_free_collections() {
+        // A lock-free stack is used exactly like the `Vec` stack from `useful_vec_functions`,
+        // except that `push()` and `pop()` only need `&self`, so it can be shared between threads
+        let stack = LockFreeStack::new();
+        stack.push(2);
+        stack.push(4);
+        stack.push(6);
+        println!("stack length: {}", stack.len());
+
+        stack.push(8000);
+        match stack.pop() {
+            None => println!("Nothing to pop"),
+            Some(n) => println!("Popped number: {n} from the stack"),
+        }
+        println!("stack length (after the pop): {}\n", stack.len());
+
+        // The queue hands out coffees in the order they were pushed, like a `VecDeque`
+        let coffee_queue = LockFreeQueue::new();
+        coffee_queue.push(Coffee { id: 1000, count: 10 });
+        coffee_queue.push(Coffee { id: 2000, count: 20 });
+        coffee_queue.push(Coffee { id: 3000, count: 30 });
+        println!("Front of queue: {:?}", coffee_queue.pop());
+        println!("Front of queue: {:?}\n", coffee_queue.pop());
+
+        // Sharing between threads only needs an `Arc`, no `Mutex`
+        let shared_queue = Arc::new(LockFreeQueue::new());
+        let producers: Vec<_> = (0..4)
+            .map(|t| {
+                let queue = Arc::clone(&shared_queue);
+                thread::spawn(move || {
+                    for n in 0..250 {
+                        queue.push(Coffee { id: t * 1000 + n, count: n });
+                    }
+                })
+            })
+            .collect();
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        println!("Coffees pushed by 4 threads: {}", shared_queue.len());
+
+        let mut popped = 0;
+        while shared_queue.pop().is_some() {
+            popped += 1;
+        }
+        println!("Coffees popped: {popped}\n");
+    }
+
+    fn time_threads<F>(threads: usize, work: F) -> Duration
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        let work = Arc::new(work);
+        let start = Instant::now();
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                let work = Arc::clone(&work);
+                thread::spawn(move || work())
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        start.elapsed()
+    }
+
+    pub fn benchmark_lock_free_vs_mutex() {
+        const OPERATIONS: usize = 100_000;
+
+        println!("{:<8} | {:>16} | {:>16} | {:>16}", "threads", "Mutex<Vec>", "LockFreeStack", "LockFreeQueue");
+        for threads in [1, 2, 4, 8] {
+            // The `Vec`-as-stack pattern from `useful_vec_functions`, made thread safe with a lock
+            let mutex_stack = Arc::new(Mutex::new(Vec::new()));
+            let mutex_time = time_threads(threads, move || {
+                for n in 0..OPERATIONS {
+                    mutex_stack.lock().unwrap().push(n);
+                    mutex_stack.lock().unwrap().pop();
+                }
+            });
+
+            let stack = Arc::new(LockFreeStack::new());
+            let stack_time = time_threads(threads, move || {
+                for n in 0..OPERATIONS {
+                    stack.push(n);
+                    stack.pop();
+                }
+            });
+
+            let queue = Arc::new(LockFreeQueue::new());
+            let queue_time = time_threads(threads, move || {
+                for n in 0..OPERATIONS {
+                    queue.push(n);
+                    queue.pop();
+                }
+            });
+
+            println!(
+                "{:<8} | {:>16?} | {:>16?} | {:>16?}",
+                threads, mutex_time, stack_time, queue_time
+            );
+        }
+        println!("({OPERATIONS} push/pop pairs per thread)\n");
+    }
+
+    // Stands in for real per-coffee work, e.g. roasting or pricing
+    fn brew(coffee: &Coffee) -> i64 {
+        (0..20_000).fold(coffee.count as i64, |acc, n| (acc * 31 + n) % 1_000_003)
+    }
+
+    pub fn demo_work_stealing() {
+        // The owner pushes and pops at the bottom of its deque, just like the `Vec` stack...
+        let worker = Worker::new();
+        let stealer = worker.stealer();
+        for n in 1..6 {
+            worker.push(Coffee { id: n, count: n * 10 });
+        }
+        println!("Owner pops: {:?}", worker.pop());
+
+        // ...while other threads steal the oldest coffees from the top
+        let stolen = thread::spawn(move || match stealer.steal() {
+            Steal::Success(coffee) => Some(coffee),
+            Steal::Empty | Steal::Retry => None,
+        });
+        println!("Stolen by another thread: {:?}", stolen.join().unwrap());
+        println!("Left in the deque: {}\n", worker.len());
+
+        // The same 150 coffees as in `demo_capacity_management`
+        let mut coffees: Vec<Coffee> = (1..151).map(|n| Coffee { id: n, count: n * 10 }).collect();
+        let pool = ThreadPool::with_available_parallelism();
+        println!("Thread pool with {} threads", pool.threads());
+
+        let total_count = AtomicI64::new(0);
+        pool.par_for_each(&coffees, |coffee| {
+            total_count.fetch_add(coffee.count as i64, Ordering::Relaxed);
+        });
+        println!("Total count (par_for_each): {}", total_count.load(Ordering::Relaxed));
+
+        pool.par_for_each_mut(&mut coffees, |coffee| coffee.count += 1);
+        println!("First coffee after par_for_each_mut: {:?}", coffees[0]);
+
+        // Results come back in the same order as the input, no matter which thread did the work
+        let names: Vec<String> = pool.par_map(&coffees, |coffee| format!("Coffee{}", coffee.id));
+        println!("First names from par_map: {:?}\n", &names[..5]);
+
+        let start = Instant::now();
+        let sequential: Vec<i64> = coffees.iter().map(brew).collect();
+        let sequential_time = start.elapsed();
+
+        let start = Instant::now();
+        let parallel = pool.par_map(&coffees, brew);
+        let parallel_time = start.elapsed();
+
+        assert_eq!(sequential, parallel);
+        println!("Brewing sequentially: {:?}", sequential_time);
+        println!("Brewing with par_map: {:?}\n", parallel_time);
+    }
+}
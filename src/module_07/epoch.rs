@@ -0,0 +1,259 @@
+// Epoch-based memory reclamation.
+//
+// A lock-free collection cannot free a node as soon as it unlinks it, because another thread may
+// have loaded a pointer to that node just before it was unlinked. With epochs, every thread that
+// touches a collection first "pins" itself to the current global epoch. Unlinked nodes are
+// retired together with the epoch in which they were retired, and are only freed once the global
+// epoch has moved two steps past that, which guarantees every thread that could still see the
+// node has unpinned in the meantime.
+
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+// How many retirements/pins a thread performs before it tries to advance the epoch and collect
+const RETIRE_COLLECT_THRESHOLD: usize = 64;
+const PIN_COLLECT_THRESHOLD: usize = 128;
+
+static GLOBAL_EPOCH: AtomicUsize = AtomicUsize::new(0);
+
+// Append-only list of participants. Entries are never freed, a thread that exits marks its entry
+// as free so that the next new thread can reuse it.
+static PARTICIPANTS: AtomicPtr<Participant> = AtomicPtr::new(ptr::null_mut());
+
+// Garbage left behind by threads that exited before it could be freed
+static ORPHANS: Mutex<Vec<Deferred>> = Mutex::new(Vec::new());
+
+struct Participant {
+    // 0 when not pinned, otherwise `(epoch << 1) | 1`
+    state: AtomicUsize,
+    in_use: AtomicBool,
+    next: *mut Participant,
+}
+
+impl Participant {
+    fn acquire() -> &'static Participant {
+        // Try to reuse an entry released by a thread that has exited
+        let mut current = PARTICIPANTS.load(Ordering::Acquire);
+        while !current.is_null() {
+            // Participants are never deallocated, so the pointer is always valid
+            let participant = unsafe { &*current };
+            if participant
+                .in_use
+                .compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                return participant;
+            }
+            current = participant.next;
+        }
+
+        let new = Box::into_raw(Box::new(Participant {
+            state: AtomicUsize::new(0),
+            in_use: AtomicBool::new(true),
+            next: ptr::null_mut(),
+        }));
+        let mut head = PARTICIPANTS.load(Ordering::Acquire);
+        loop {
+            unsafe { (*new).next = head };
+            match PARTICIPANTS.compare_exchange(head, new, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => return unsafe { &*new },
+                Err(actual) => head = actual,
+            }
+        }
+    }
+}
+
+// Type-erased pointer and the function that knows how to free it
+struct Deferred {
+    epoch: usize,
+    ptr: *mut u8,
+    destroy: unsafe fn(*mut u8),
+}
+
+// Deferred values only ever free memory that no thread can reach anymore
+unsafe impl Send for Deferred {}
+
+impl Deferred {
+    fn is_expired(&self, global_epoch: usize) -> bool {
+        global_epoch.wrapping_sub(self.epoch) >= 2
+    }
+
+    fn run(self) {
+        unsafe { (self.destroy)(self.ptr) }
+    }
+}
+
+unsafe fn destroy_box<T>(ptr: *mut u8) {
+    drop(Box::from_raw(ptr as *mut T));
+}
+
+struct Local {
+    participant: &'static Participant,
+    guard_count: Cell<usize>,
+    pin_count: Cell<usize>,
+    garbage: RefCell<Vec<Deferred>>,
+}
+
+impl Local {
+    fn new() -> Local {
+        Local {
+            participant: Participant::acquire(),
+            guard_count: Cell::new(0),
+            pin_count: Cell::new(0),
+            garbage: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn pin(&self) {
+        let count = self.guard_count.get();
+        self.guard_count.set(count + 1);
+        if count > 0 {
+            return;
+        }
+
+        let epoch = GLOBAL_EPOCH.load(Ordering::SeqCst);
+        self.participant.state.store((epoch << 1) | 1, Ordering::SeqCst);
+        fence(Ordering::SeqCst);
+
+        let pins = self.pin_count.get().wrapping_add(1);
+        self.pin_count.set(pins);
+        if pins.is_multiple_of(PIN_COLLECT_THRESHOLD) {
+            self.collect();
+        }
+    }
+
+    fn unpin(&self) {
+        let count = self.guard_count.get();
+        self.guard_count.set(count - 1);
+        if count == 1 {
+            self.participant.state.store(0, Ordering::Release);
+        }
+    }
+
+    fn retire(&self, deferred: Deferred) {
+        let len = {
+            let mut garbage = self.garbage.borrow_mut();
+            garbage.push(deferred);
+            garbage.len()
+        };
+        if len.is_multiple_of(RETIRE_COLLECT_THRESHOLD) {
+            self.collect();
+        }
+    }
+
+    fn collect(&self) {
+        let global_epoch = try_advance();
+
+        // Split off the expired garbage first so that running destructors never touches the
+        // `RefCell` while it is borrowed
+        let expired: Vec<Deferred> = {
+            let mut garbage = self.garbage.borrow_mut();
+            let (expired, alive) = garbage.drain(..).partition(|d| d.is_expired(global_epoch));
+            *garbage = alive;
+            expired
+        };
+        expired.into_iter().for_each(Deferred::run);
+
+        if let Ok(mut orphans) = ORPHANS.try_lock() {
+            let (expired, alive): (Vec<Deferred>, Vec<Deferred>) =
+                orphans.drain(..).partition(|d| d.is_expired(global_epoch));
+            *orphans = alive;
+            drop(orphans);
+            expired.into_iter().for_each(Deferred::run);
+        }
+    }
+}
+
+impl Drop for Local {
+    fn drop(&mut self) {
+        self.participant.state.store(0, Ordering::SeqCst);
+        self.participant.in_use.store(false, Ordering::Release);
+
+        let garbage = std::mem::take(self.garbage.get_mut());
+        if !garbage.is_empty() {
+            ORPHANS
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .extend(garbage);
+        }
+    }
+}
+
+thread_local! {
+    static LOCAL: Local = Local::new();
+}
+
+// The global epoch can only move forward once every pinned thread has observed the current one
+fn try_advance() -> usize {
+    let global_epoch = GLOBAL_EPOCH.load(Ordering::SeqCst);
+    fence(Ordering::SeqCst);
+
+    let mut current = PARTICIPANTS.load(Ordering::Acquire);
+    while !current.is_null() {
+        let participant = unsafe { &*current };
+        let state = participant.state.load(Ordering::SeqCst);
+        if state & 1 == 1 && state >> 1 != global_epoch {
+            return global_epoch;
+        }
+        current = participant.next;
+    }
+
+    match GLOBAL_EPOCH.compare_exchange(
+        global_epoch,
+        global_epoch.wrapping_add(1),
+        Ordering::SeqCst,
+        Ordering::SeqCst,
+    ) {
+        Ok(_) => global_epoch.wrapping_add(1),
+        Err(actual) => actual,
+    }
+}
+
+/// Keeps the current thread pinned to an epoch for as long as it is alive.
+///
+/// Any pointer loaded from a lock-free collection while a guard is held stays valid until the
+/// guard is dropped, even if another thread unlinks and retires it in the meantime.
+pub struct Guard {
+    // A guard belongs to the thread that pinned it
+    _not_send: PhantomData<*mut ()>,
+}
+
+pub fn pin() -> Guard {
+    LOCAL.with(Local::pin);
+    Guard { _not_send: PhantomData }
+}
+
+impl Guard {
+    /// Schedules `ptr` (obtained from `Box::into_raw`) to be freed once no pinned thread can
+    /// still be holding it.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must already be unreachable for threads that pin after this call, and must not be
+    /// retired twice. Only the `Box` allocation is released: if `T` owns a value that has already
+    /// been moved out, it must be wrapped in `ManuallyDrop` or `MaybeUninit`.
+    pub unsafe fn defer_destroy<T>(&self, ptr: *mut T) {
+        let epoch = GLOBAL_EPOCH.load(Ordering::SeqCst);
+        let deferred = Deferred {
+            epoch,
+            ptr: ptr as *mut u8,
+            destroy: destroy_box::<T>,
+        };
+        LOCAL.with(|local| local.retire(deferred));
+    }
+
+    /// Tries to advance the global epoch and frees whatever garbage has become unreachable.
+    pub fn flush(&self) {
+        LOCAL.with(Local::collect);
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        // If the thread-local has already been torn down, the participant has been released too
+        let _ = LOCAL.try_with(Local::unpin);
+    }
+}
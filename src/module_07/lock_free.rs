@@ -0,0 +1,243 @@
+// Lock-free collections built directly on `std::sync::atomic`.
+//
+// `LockFreeStack` is a Treiber stack and `LockFreeQueue` is a Michael-Scott queue. Both retire
+// unlinked nodes through the epoch collector in `super::epoch`, which also protects them from
+// the ABA problem: a node cannot be freed and reused while another thread still holds it.
+
+use std::mem::{ManuallyDrop, MaybeUninit};
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use super::epoch;
+
+struct StackNode<T> {
+    data: ManuallyDrop<T>,
+    next: *mut StackNode<T>,
+}
+
+pub struct LockFreeStack<T> {
+    head: AtomicPtr<StackNode<T>>,
+    len: AtomicUsize,
+}
+
+unsafe impl<T: Send> Send for LockFreeStack<T> {}
+unsafe impl<T: Send> Sync for LockFreeStack<T> {}
+
+impl<T> LockFreeStack<T> {
+    pub fn new() -> LockFreeStack<T> {
+        LockFreeStack {
+            head: AtomicPtr::new(ptr::null_mut()),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn push(&self, value: T) {
+        let node = Box::into_raw(Box::new(StackNode {
+            data: ManuallyDrop::new(value),
+            next: ptr::null_mut(),
+        }));
+
+        // Counted before it is published, so a pop always takes away a count that is already
+        // there and `len` never drops below zero
+        self.len.fetch_add(1, Ordering::Relaxed);
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            // The node is not shared yet, so it is fine to write to it directly
+            unsafe { (*node).next = head };
+            match self
+                .head
+                .compare_exchange_weak(head, node, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let guard = epoch::pin();
+        let mut head = self.head.load(Ordering::Acquire);
+        loop {
+            if head.is_null() {
+                return None;
+            }
+
+            // Being pinned keeps `head` alive even if another thread pops it first
+            let next = unsafe { (*head).next };
+            match self
+                .head
+                .compare_exchange_weak(head, next, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => {
+                    self.len.fetch_sub(1, Ordering::Relaxed);
+                    unsafe {
+                        let value = ptr::read(&(*head).data);
+                        guard.defer_destroy(head);
+                        return Some(ManuallyDrop::into_inner(value));
+                    }
+                }
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire).is_null()
+    }
+
+    // Approximate while other threads push or pop: a value being pushed is counted a moment
+    // before it can be popped
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Default for LockFreeStack<T> {
+    fn default() -> Self {
+        LockFreeStack::new()
+    }
+}
+
+impl<T> Drop for LockFreeStack<T> {
+    fn drop(&mut self) {
+        // `&mut self` means no other thread can be looking at the nodes anymore
+        let mut current = *self.head.get_mut();
+        while !current.is_null() {
+            let mut node = unsafe { Box::from_raw(current) };
+            unsafe { ManuallyDrop::drop(&mut node.data) };
+            current = node.next;
+        }
+    }
+}
+
+struct QueueNode<T> {
+    // Uninitialized for the sentinel node and after the value has been dequeued
+    data: MaybeUninit<T>,
+    next: AtomicPtr<QueueNode<T>>,
+}
+
+impl<T> QueueNode<T> {
+    fn new(data: MaybeUninit<T>) -> *mut QueueNode<T> {
+        Box::into_raw(Box::new(QueueNode {
+            data,
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+}
+
+pub struct LockFreeQueue<T> {
+    // `head` always points at a sentinel node, the first value lives in `head.next`
+    head: AtomicPtr<QueueNode<T>>,
+    tail: AtomicPtr<QueueNode<T>>,
+    len: AtomicUsize,
+}
+
+unsafe impl<T: Send> Send for LockFreeQueue<T> {}
+unsafe impl<T: Send> Sync for LockFreeQueue<T> {}
+
+impl<T> LockFreeQueue<T> {
+    pub fn new() -> LockFreeQueue<T> {
+        let sentinel = QueueNode::new(MaybeUninit::uninit());
+        LockFreeQueue {
+            head: AtomicPtr::new(sentinel),
+            tail: AtomicPtr::new(sentinel),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn push(&self, value: T) {
+        let node = QueueNode::new(MaybeUninit::new(value));
+        let _guard = epoch::pin();
+        // Counted before it is linked in, like in `LockFreeStack::push`
+        self.len.fetch_add(1, Ordering::Relaxed);
+
+        loop {
+            let tail = self.tail.load(Ordering::Acquire);
+            let next = unsafe { (*tail).next.load(Ordering::Acquire) };
+
+            if next.is_null() {
+                let linked = unsafe {
+                    (*tail).next.compare_exchange(
+                        ptr::null_mut(),
+                        node,
+                        Ordering::Release,
+                        Ordering::Relaxed,
+                    )
+                };
+                if linked.is_ok() {
+                    // If this fails, another thread has already swung the tail for us
+                    let _ = self.tail.compare_exchange(tail, node, Ordering::Release, Ordering::Relaxed);
+                    return;
+                }
+            } else {
+                // The tail is lagging behind, help move it forward and try again
+                let _ = self.tail.compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let guard = epoch::pin();
+
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            let next = unsafe { (*head).next.load(Ordering::Acquire) };
+            if next.is_null() {
+                return None;
+            }
+
+            // Never let the tail point at a node that is about to be retired
+            let tail = self.tail.load(Ordering::Acquire);
+            if head == tail {
+                let _ = self.tail.compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+            }
+
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                self.len.fetch_sub(1, Ordering::Relaxed);
+                unsafe {
+                    // `next` becomes the new sentinel, so its value is moved out exactly once
+                    let value = ptr::read((*next).data.as_ptr());
+                    guard.defer_destroy(head);
+                    return Some(value);
+                }
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let _guard = epoch::pin();
+        let head = self.head.load(Ordering::Acquire);
+        unsafe { (*head).next.load(Ordering::Acquire).is_null() }
+    }
+
+    // Approximate while other threads push or pop: a value being pushed is counted a moment
+    // before it can be popped
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Default for LockFreeQueue<T> {
+    fn default() -> Self {
+        LockFreeQueue::new()
+    }
+}
+
+impl<T> Drop for LockFreeQueue<T> {
+    fn drop(&mut self) {
+        let sentinel = *self.head.get_mut();
+        let mut current = unsafe { Box::from_raw(sentinel) };
+        loop {
+            let next = *current.next.get_mut();
+            if next.is_null() {
+                break;
+            }
+            current = unsafe { Box::from_raw(next) };
+            unsafe { current.data.assume_init_drop() };
+        }
+    }
+}
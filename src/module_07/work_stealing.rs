@@ -0,0 +1,388 @@
+// Chase-Lev work-stealing deque and a small thread pool built on top of it.
+//
+// Each thread owns a `Worker` and pushes/pops tasks at the bottom of its own deque, which is
+// almost always uncontended. When it runs out of work, it takes tasks from the top of another
+// thread's deque through a `Stealer`. Only that last step needs a compare-and-swap.
+
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ops::Range;
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{fence, AtomicBool, AtomicIsize, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use super::epoch;
+
+const MIN_CAPACITY: usize = 16;
+
+// Fixed-size ring of slots. Growing the deque allocates a new, bigger buffer and retires the
+// old one through the epoch collector, since stealers may still be reading from it.
+struct Buffer<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+}
+
+impl<T> Buffer<T> {
+    fn new(capacity: usize) -> *mut Buffer<T> {
+        let slots = (0..capacity).map(|_| UnsafeCell::new(MaybeUninit::uninit())).collect();
+        Box::into_raw(Box::new(Buffer { slots }))
+    }
+
+    fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn slot(&self, index: isize) -> *mut MaybeUninit<T> {
+        // The capacity is always a power of two, so masking wraps the index around the ring
+        let index = index as usize & (self.capacity() - 1);
+        self.slots[index].get()
+    }
+
+    unsafe fn write(&self, index: isize, value: T) {
+        ptr::write_volatile(self.slot(index), MaybeUninit::new(value));
+    }
+
+    // A stealer may race with the owner overwriting this slot, so what it reads may be torn. It
+    // stays a `MaybeUninit` until the compare-and-swap on `top` says the slot was really ours.
+    unsafe fn read(&self, index: isize) -> MaybeUninit<T> {
+        ptr::read_volatile(self.slot(index))
+    }
+}
+
+struct Inner<T> {
+    top: AtomicIsize,
+    bottom: AtomicIsize,
+    buffer: AtomicPtr<Buffer<T>>,
+}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        let top = *self.top.get_mut();
+        let bottom = *self.bottom.get_mut();
+        let buffer = unsafe { Box::from_raw(*self.buffer.get_mut()) };
+        for index in top..bottom {
+            unsafe { drop(buffer.read(index).assume_init()) };
+        }
+    }
+}
+
+/// The owning end of a deque: only one thread may push and pop at the bottom.
+pub struct Worker<T> {
+    inner: Arc<Inner<T>>,
+    // `Worker` may be sent to another thread, but never shared between threads
+    _not_sync: PhantomData<*mut ()>,
+}
+
+unsafe impl<T: Send> Send for Worker<T> {}
+
+/// The stealing end of a deque: any number of threads may take tasks from the top.
+pub struct Stealer<T> {
+    inner: Arc<Inner<T>>,
+}
+
+unsafe impl<T: Send> Send for Stealer<T> {}
+unsafe impl<T: Send> Sync for Stealer<T> {}
+
+impl<T> Clone for Stealer<T> {
+    fn clone(&self) -> Self {
+        Stealer { inner: Arc::clone(&self.inner) }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Steal<T> {
+    Empty,
+    Success(T),
+    // Lost a race with another thread, the deque may still have tasks
+    Retry,
+}
+
+impl<T> Worker<T> {
+    pub fn new() -> Worker<T> {
+        Worker {
+            inner: Arc::new(Inner {
+                top: AtomicIsize::new(0),
+                bottom: AtomicIsize::new(0),
+                buffer: AtomicPtr::new(Buffer::new(MIN_CAPACITY)),
+            }),
+            _not_sync: PhantomData,
+        }
+    }
+
+    pub fn stealer(&self) -> Stealer<T> {
+        Stealer { inner: Arc::clone(&self.inner) }
+    }
+
+    pub fn len(&self) -> usize {
+        let bottom = self.inner.bottom.load(Ordering::Relaxed);
+        let top = self.inner.top.load(Ordering::Relaxed);
+        bottom.saturating_sub(top).max(0) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn push(&self, value: T) {
+        let bottom = self.inner.bottom.load(Ordering::Relaxed);
+        let top = self.inner.top.load(Ordering::Acquire);
+        let mut buffer = self.inner.buffer.load(Ordering::Relaxed);
+
+        if bottom - top >= unsafe { (*buffer).capacity() } as isize {
+            buffer = unsafe { self.grow(buffer, top, bottom) };
+        }
+
+        unsafe { (*buffer).write(bottom, value) };
+        fence(Ordering::Release);
+        self.inner.bottom.store(bottom + 1, Ordering::Relaxed);
+    }
+
+    unsafe fn grow(&self, old: *mut Buffer<T>, top: isize, bottom: isize) -> *mut Buffer<T> {
+        let new = Buffer::new((*old).capacity() * 2);
+        for index in top..bottom {
+            ptr::copy_nonoverlapping((*old).slot(index), (*new).slot(index), 1);
+        }
+
+        let guard = epoch::pin();
+        self.inner.buffer.store(new, Ordering::Release);
+        // Only the slots are freed, the values themselves have been copied into `new`
+        guard.defer_destroy(old);
+        new
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let bottom = self.inner.bottom.load(Ordering::Relaxed) - 1;
+        let buffer = self.inner.buffer.load(Ordering::Relaxed);
+        self.inner.bottom.store(bottom, Ordering::Relaxed);
+        fence(Ordering::SeqCst);
+        let top = self.inner.top.load(Ordering::Relaxed);
+
+        if top > bottom {
+            // The deque was already empty
+            self.inner.bottom.store(bottom + 1, Ordering::Relaxed);
+            return None;
+        }
+
+        let value = unsafe { (*buffer).read(bottom) };
+        if top == bottom {
+            // Last task: race the stealers for it
+            let won = self
+                .inner
+                .top
+                .compare_exchange(top, top + 1, Ordering::SeqCst, Ordering::Relaxed)
+                .is_ok();
+            self.inner.bottom.store(bottom + 1, Ordering::Relaxed);
+            if !won {
+                return None;
+            }
+        }
+        Some(unsafe { value.assume_init() })
+    }
+}
+
+impl<T> Default for Worker<T> {
+    fn default() -> Self {
+        Worker::new()
+    }
+}
+
+impl<T> Stealer<T> {
+    pub fn steal(&self) -> Steal<T> {
+        let top = self.inner.top.load(Ordering::Acquire);
+        fence(Ordering::SeqCst);
+        let bottom = self.inner.bottom.load(Ordering::Acquire);
+        if bottom - top <= 0 {
+            return Steal::Empty;
+        }
+
+        // Pinning keeps the buffer alive if the owner grows the deque while we read from it
+        let _guard = epoch::pin();
+        let buffer = self.inner.buffer.load(Ordering::Acquire);
+        let value = unsafe { (*buffer).read(top) };
+
+        match self
+            .inner
+            .top
+            .compare_exchange(top, top + 1, Ordering::SeqCst, Ordering::Relaxed)
+        {
+            Ok(_) => Steal::Success(unsafe { value.assume_init() }),
+            Err(_) => Steal::Retry,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let top = self.inner.top.load(Ordering::Acquire);
+        let bottom = self.inner.bottom.load(Ordering::Acquire);
+        bottom - top <= 0
+    }
+}
+
+// Raw pointer to the start of a slice that is being mutated from several threads at once
+struct SlicePtr<T>(*mut T);
+
+unsafe impl<T: Send> Send for SlicePtr<T> {}
+unsafe impl<T: Send> Sync for SlicePtr<T> {}
+
+impl<T> SlicePtr<T> {
+    fn get(&self) -> *mut T {
+        self.0
+    }
+}
+
+// Tells the other threads to stop waiting for work if a task panics
+struct PanicFlag<'a>(&'a AtomicBool);
+
+impl Drop for PanicFlag<'_> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.0.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Runs data-parallel loops on a fixed number of threads that balance work by stealing.
+///
+/// The threads are scoped to each call, which is what allows the closures to borrow from the
+/// caller's stack.
+pub struct ThreadPool {
+    threads: usize,
+    // Ranges shorter than this are not split any further. When it is not set, every thread gets
+    // split into roughly 16 tasks.
+    grain: Option<usize>,
+}
+
+impl ThreadPool {
+    pub fn new(threads: usize) -> ThreadPool {
+        ThreadPool {
+            threads: threads.max(1),
+            grain: None,
+        }
+    }
+
+    pub fn with_available_parallelism() -> ThreadPool {
+        let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        ThreadPool::new(threads)
+    }
+
+    pub fn grain(mut self, grain: usize) -> ThreadPool {
+        self.grain = Some(grain.max(1));
+        self
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    pub fn par_for_each<T, F>(&self, items: &[T], f: F)
+    where
+        T: Sync,
+        F: Fn(&T) + Sync,
+    {
+        self.run(items.len(), |range| items[range].iter().for_each(&f));
+    }
+
+    pub fn par_for_each_mut<T, F>(&self, items: &mut [T], f: F)
+    where
+        T: Send,
+        F: Fn(&mut T) + Sync,
+    {
+        let len = items.len();
+        let base = SlicePtr(items.as_mut_ptr());
+        self.run(len, |range| {
+            // Every index is handed out exactly once, so the ranges never overlap
+            let chunk = unsafe { slice::from_raw_parts_mut(base.get().add(range.start), range.len()) };
+            chunk.iter_mut().for_each(&f);
+        });
+    }
+
+    pub fn par_map<T, U, F>(&self, items: &[T], f: F) -> Vec<U>
+    where
+        T: Sync,
+        U: Send,
+        F: Fn(&T) -> U + Sync,
+    {
+        let results = Mutex::new(Vec::new());
+        self.run(items.len(), |range| {
+            let start = range.start;
+            let mapped: Vec<U> = items[range].iter().map(&f).collect();
+            results.lock().unwrap().push((start, mapped));
+        });
+
+        let mut results = results.into_inner().unwrap();
+        results.sort_unstable_by_key(|(start, _)| *start);
+        results.into_iter().flat_map(|(_, mapped)| mapped).collect()
+    }
+
+    // Splits `0..len` into one range per thread, then lets every thread keep halving its ranges
+    // so that idle threads always find something big enough to be worth stealing
+    fn run<F>(&self, len: usize, body: F)
+    where
+        F: Fn(Range<usize>) + Sync,
+    {
+        if len == 0 {
+            return;
+        }
+
+        let threads = self.threads.min(len);
+        let workers: Vec<Worker<Range<usize>>> = (0..threads).map(|_| Worker::new()).collect();
+        let stealers: Vec<Stealer<Range<usize>>> = workers.iter().map(Worker::stealer).collect();
+
+        let chunk = len.div_ceil(threads);
+        for (index, worker) in workers.iter().enumerate() {
+            let start = index * chunk;
+            let end = (start + chunk).min(len);
+            if start < end {
+                worker.push(start..end);
+            }
+        }
+
+        let remaining = AtomicUsize::new(len);
+        let panicked = AtomicBool::new(false);
+
+        thread::scope(|scope| {
+            for (index, worker) in workers.into_iter().enumerate() {
+                let (body, stealers, remaining, panicked) = (&body, &stealers, &remaining, &panicked);
+                let grain = self.grain.unwrap_or_else(|| (len / (threads * 16)).max(1));
+
+                scope.spawn(move || {
+                    let _flag = PanicFlag(panicked);
+                    while remaining.load(Ordering::Acquire) > 0 && !panicked.load(Ordering::Relaxed) {
+                        let Some(mut range) = worker.pop().or_else(|| steal_from(stealers, index)) else {
+                            thread::yield_now();
+                            continue;
+                        };
+
+                        while range.len() > grain {
+                            let middle = range.start + range.len() / 2;
+                            worker.push(middle..range.end);
+                            range.end = middle;
+                        }
+
+                        let done = range.len();
+                        body(range);
+                        remaining.fetch_sub(done, Ordering::Release);
+                    }
+                });
+            }
+        });
+    }
+}
+
+fn steal_from<T>(stealers: &[Stealer<T>], thief: usize) -> Option<T> {
+    loop {
+        let mut retry = false;
+        // Start with the next thread over so that thieves spread out across the deques
+        for offset in 1..stealers.len() {
+            match stealers[(thief + offset) % stealers.len()].steal() {
+                Steal::Success(value) => return Some(value),
+                Steal::Retry => retry = true,
+                Steal::Empty => {}
+            }
+        }
+        if !retry {
+            return None;
+        }
+    }
+}
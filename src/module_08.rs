@@ -0,0 +1,691 @@
+pub mod binary;
+pub mod csv;
+pub mod disk_map;
+pub mod json;
+pub mod sstable;
+
+use std::fs;
+use std::path::PathBuf;
+
+// A fresh, empty directory under the system's temporary directory, for the demos and checks that
+// work on files
+fn temp_directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("rust-2021-collections-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+pub mod reading_and_writing_files {
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, LinkedList, VecDeque};
+    use std::fs::File;
+    use std::io::{BufReader, BufWriter, Cursor};
+
+    use super::csv::{self, CsvError, Reader};
+    use super::json::{self, FromJson, Json, Parser, ToJson};
+    use crate::coffee::Coffee;
+
+    // The data files that come with the course, next to `Cargo.toml`
+    const DATA_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/data");
+
+    pub fn demo_csv() {
+        // The same coffees `demo_vec` builds inline, loaded from a file instead
+        let file = File::open(format!("{}/coffees.csv", DATA_DIR)).expect("data/coffees.csv is missing");
+        let coffees = csv::load_vec(BufReader::new(file)).expect("data/coffees.csv is not valid");
+        println!("Loaded {} coffees from data/coffees.csv:", coffees.len());
+        for coffee in &coffees {
+            println!("  {:?}", coffee);
+        }
+
+        // Writing them back quotes only the names that need it
+        let written = csv::save_vec(Vec::new(), &coffees).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        println!("\nWritten back:\n{}", written);
+        assert_eq!(csv::load_vec(written.as_bytes()).unwrap(), coffees);
+
+        // The same file as a map from name to coffee
+        let by_name: HashMap<String, Coffee> =
+            csv::load_map(BufReader::new(File::open(format!("{}/coffees.csv", DATA_DIR)).unwrap())).unwrap();
+        println!("by_name[\"Mocha\"]: {:?}", by_name["Mocha"]);
+
+        // The `from_btr` price list from module_04, sorted by name like any BTreeMap
+        let file = File::open(format!("{}/prices.csv", DATA_DIR)).expect("data/prices.csv is missing");
+        let prices: BTreeMap<String, f64> = csv::load_price_list(BufReader::new(file)).unwrap();
+        for (coffee, cost) in &prices {
+            println!("Coffee {coffee} costs {cost:.2} TK");
+        }
+        println!();
+
+        // Saving to a file and loading it again gives the same map
+        let path = std::env::temp_dir().join("rust-2021-collections-prices.csv");
+        let mut updated = prices.clone();
+        updated.insert(String::from("Espresso, double"), 200.0);
+        csv::save_price_list(BufWriter::new(File::create(&path).unwrap()), &updated).unwrap();
+        let reloaded = csv::load_price_list(BufReader::new(File::open(&path).unwrap())).unwrap();
+        println!("Saved and reloaded {} prices from {}", reloaded.len(), path.display());
+        assert_eq!(reloaded, updated);
+        std::fs::remove_file(&path).unwrap();
+
+        // Quoted fields can hold commas, quotes and line breaks, and columns can be in any order
+        let exported = "\u{feff}count,name,id,notes\r\n\
+                        10,\"Latte\",1000,\"hot, with milk\"\r\n\
+                        5,\"Seasonal\r\n\"\"Pumpkin\"\" Mocha\",1001,\r\n";
+        let raw: Vec<Vec<String>> = Reader::new(exported.as_bytes()).collect::<Result<_, _>>().unwrap();
+        println!("\nRaw records: {:?}", raw);
+        let coffees = csv::load_vec(exported.as_bytes()).unwrap();
+        println!("As coffees: {:?}\n", coffees);
+        assert_eq!(coffees[1].name, "Seasonal\r\n\"Pumpkin\" Mocha");
+
+        // The reader streams, so a file much bigger than memory could be summed up record by record
+        let big: String = (0..100_000).map(|n| format!("{},Coffee {},{}\n", n, n, n % 10)).collect();
+        let big = format!("id,name,count\n{}", big);
+        let total: i64 = csv::records::<Coffee, _>(Cursor::new(big))
+            .unwrap()
+            .map(|coffee| coffee.unwrap().count as i64)
+            .sum();
+        println!("Total count over 100,000 streamed records: {}\n", total);
+
+        // Errors say which line went wrong
+        for bad in [
+            "id,name,count\n1000,Latte,10\n1001,Mocha\n",
+            "id,name,count\n1000,Latte,ten\n",
+            "id,name\n1000,Latte\n",
+            "id,name,count\n1000,\"Latte,10\n",
+            "id,name,count\n1000,La\"tte,10\n",
+            "id,name,count\n1000,Latte,10\n1001,Latte,5\n",
+            "name,id,count,count\nLatte,1,10,11\n",
+        ] {
+            let error: CsvError = match csv::load_map(bad.as_bytes()) {
+                Ok(_) => unreachable!("{:?} should not load", bad),
+                Err(error) => error,
+            };
+            println!("{}", error);
+        }
+        // Extra columns are fine, but every record still needs a field for each of them
+        let extra = csv::load_vec("name,id,count,size\nLatte,1,10\n".as_bytes()).unwrap_err();
+        println!("{}", extra);
+        assert!(matches!(extra, CsvError::FieldCount { expected: 4, found: 3, .. }));
+    }
+
+    // Writes `value` as JSON, reads it back and checks that nothing changed
+    fn round_trip<T: ToJson + FromJson + PartialEq + std::fmt::Debug>(label: &str, value: T) {
+        let text = json::to_string(&value);
+        println!("{:>10}: {}", label, text);
+        assert_eq!(json::from_str::<T>(&text).unwrap(), value);
+    }
+
+    pub fn demo_json() {
+        // Every collection from the course, written as JSON and read back
+        round_trip("Vec", vec![2, 4, 6]);
+        round_trip("VecDeque", VecDeque::from([String::from("Latte"), String::from("Mocha")]));
+        round_trip("LinkedList", LinkedList::from([1.5, 2.25]));
+        round_trip("HashSet", HashSet::from([String::from("Latte")]));
+        round_trip("BTreeSet", BTreeSet::from(["Palm", "Mango", "Dates"].map(String::from)));
+        round_trip("HashMap", HashMap::from([(String::from("Latte"), 3.5)]));
+        round_trip("BTreeMap", BTreeMap::from([(1, String::from("January")), (2, String::from("February"))]));
+        round_trip("tuple", (1000, String::from("Latte"), true));
+        round_trip("array", [[1, 2], [3, 4]]);
+        round_trip("Option", vec![Some('☕'), None]);
+        round_trip("Coffee", Coffee::new(1000, "Latte \"large\"\n", 10));
+        println!();
+
+        // Pretty printed, for people to read
+        let coffees = vec![Coffee::new(1000, "Latte", 10), Coffee::new(1001, "Mocha", 5)];
+        println!("{}\n", json::to_string_pretty(&coffees));
+
+        // A fixture file, loaded as a generic `Json` first and then field by field
+        let file = File::open(format!("{}/coffees.json", DATA_DIR)).expect("data/coffees.json is missing");
+        let fixture = Parser::new(BufReader::new(file)).parse_document().expect("data/coffees.json is not valid");
+        let coffees: Vec<Coffee> = json::field(&fixture, "coffees").unwrap();
+        let prices: BTreeMap<String, f64> = json::field(&fixture, "prices").unwrap();
+        let months: BTreeMap<u32, String> = json::field(&fixture, "months").unwrap();
+        println!("Fixture coffees: {:?}", coffees);
+        println!("Fixture prices: {:?}", prices);
+        println!("Fixture months: {:?}\n", months);
+        assert_eq!(coffees[3].name, "Café Crème");
+
+        // A stream of documents, one per line, read one at a time
+        let log = "{\"id\":1,\"name\":\"Latte\",\"count\":1}\n{\"id\":2,\"name\":\"Mocha\",\"count\":2}\n[1, 2]\n";
+        let mut parser = Parser::new(log.as_bytes());
+        while let Some(value) = parser.next_value().unwrap() {
+            println!("Streamed: {}", value);
+        }
+        println!();
+
+        // Syntax errors say where, conversion errors say which value
+        for bad in [
+            "[1, 2,, 3]",
+            "{\n  \"id\": 1000,\n  \"name\": \"Latte\"\n  \"count\": 10\n}",
+            "{\"name\": \"Café\", \"id\": 01}",
+            "[\"Latte\", \"Mocha]",
+            "{\"id\": 1, \"id\": 2}",
+            "[true, nul]",
+            "[1] [2]",
+        ] {
+            let error = bad.parse::<Json>().unwrap_err();
+            println!("{:<32} -> {}", bad.replace('\n', "\\n"), error);
+        }
+        let missing_count = "[{\"id\": 1, \"name\": \"Latte\", \"count\": 1}, {\"id\": 2, \"name\": \"Mocha\"}]";
+        println!("{}", json::from_str::<Vec<Coffee>>(missing_count).unwrap_err());
+        let fractional_count = "[{\"id\": 1, \"name\": \"Latte\", \"count\": 1.5}]";
+        println!("{}", json::from_str::<Vec<Coffee>>(fractional_count).unwrap_err());
+        // 2^63 and 2^64, one past the end of i64 and u64. The smallest i64 is a power of two and fits.
+        println!("{}", json::from_str::<i64>("9223372036854775808").unwrap_err());
+        println!("{}", json::from_str::<u64>("18446744073709551616").unwrap_err());
+        assert_eq!(json::from_str::<i64>("-9223372036854775808").unwrap(), i64::MIN);
+        println!("{}", json::from_str::<BTreeMap<String, f64>>("{\"Latte\": \"cheap\"}").unwrap_err());
+        println!("{}", json::from_str::<BTreeMap<u32, String>>("{\"thirteen\": \"?\"}").unwrap_err());
+    }
+}
+
+pub mod binary_encoding {
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, LinkedList, VecDeque};
+    use std::fmt::Debug;
+
+    use super::binary::{self, Decode, DecodeError, Encode};
+    use super::json::{self, ToJson};
+    use crate::coffee::Coffee;
+    use crate::module_09::property::Random;
+
+    fn menu(size: usize) -> Vec<Coffee> {
+        let names = ["Latte", "Mocha", "Cappuccino", "Honey Latte", "Black Coffee"];
+        (0..size).map(|n| Coffee::new(1000 + n as i32, names[n % names.len()], (n % 20) as i32)).collect()
+    }
+
+    pub fn demo_binary_encoding() {
+        // Small numbers take one byte, whatever their type
+        for value in [0u64, 1, 127, 128, 300, 16_384, u64::MAX] {
+            println!("{:>20} -> {:02x?}", value, binary::encode(&value));
+        }
+        // Zigzag keeps small negative numbers small
+        for value in [0i32, -1, 1, -2, -64, 64, i32::MIN] {
+            println!("{:>20} -> {:02x?}", value, binary::encode(&value));
+        }
+        println!();
+
+        let latte = Coffee::new(1000, "Latte", 10);
+        println!("{:?} -> {:02x?}", latte, binary::encode(&latte));
+        println!("The same coffee as JSON: {}\n", json::to_string(&latte));
+
+        // With the envelope: magic, version, length, payload and checksum
+        let bytes = binary::to_bytes(&latte);
+        println!("Envelope: {:02x?}", bytes);
+        assert_eq!(binary::from_bytes::<Coffee>(&bytes), Ok(latte));
+
+        // How much smaller than JSON, for menus of different sizes
+        println!("\n{:>8} | {:>10} | {:>10} | {:>6}", "coffees", "JSON", "binary", "ratio");
+        for size in [1, 100, 10_000] {
+            let coffees = menu(size);
+            let json_size = json::to_string(&coffees).len();
+            let binary_size = binary::to_bytes(&coffees).len();
+            println!("{:>8} | {:>10} | {:>10} | {:>5.1}x", size, json_size, binary_size, json_size as f64 / binary_size as f64);
+        }
+        println!();
+
+        // A damaged file is rejected, not decoded into wrong coffees
+        let good = binary::to_bytes(&menu(3));
+        let mut flipped = good.clone();
+        flipped[10] ^= 0x04;
+        // A file written by a later version, with a checksum that matches
+        let mut newer = good.clone();
+        newer[4] = binary::FORMAT_VERSION + 1;
+        let checksum_start = newer.len() - 4;
+        let checksum = binary::crc32(&newer[..checksum_start]);
+        newer[checksum_start..].copy_from_slice(&checksum.to_le_bytes());
+        for (label, bytes) in [
+            ("one bit flipped", flipped),
+            ("truncated", good[..good.len() - 3].to_vec()),
+            ("not ours", b"PK\x03\x04 a zip file".to_vec()),
+            ("newer version", newer),
+        ] {
+            println!("{:>16}: {}", label, binary::from_bytes::<Vec<Coffee>>(&bytes).unwrap_err());
+        }
+        // Without the envelope the decoder still stops at bytes that can not be a coffee list
+        println!("{:>16}: {}", "bad UTF-8", binary::decode::<Vec<Coffee>>(&[1, 0, 2, 0xff, 0xfe, 0]).unwrap_err());
+        println!("{:>16}: {}", "huge length", binary::decode::<Vec<Coffee>>(&[0xff, 0xff, 0xff, 0xff, 0x0f]).unwrap_err());
+    }
+
+    // Short strings with the characters that are most likely to trip up an encoding
+    fn random_string(random: &mut Random) -> String {
+        let pieces = ["", "a", "Latte", "é", "☕", "🍩", "\0", "\n"];
+        (0..random.below(5)).map(|_| *random.pick(&pieces)).collect()
+    }
+
+    // Mostly small numbers, sometimes the extremes
+    fn random_integer(random: &mut Random) -> i64 {
+        match random.below(4) {
+            0 => random.below(300) as i64 - 150,
+            1 => i64::MIN + random.below(3) as i64,
+            2 => i64::MAX - random.below(3) as i64,
+            _ => random.next_u64() as i64,
+        }
+    }
+
+    fn random_coffee(random: &mut Random) -> Coffee {
+        Coffee::new(random_integer(random) as i32, random_string(random), random_integer(random) as i32)
+    }
+
+    fn round_trip<T: Encode + Decode + PartialEq + Debug>(value: &T) {
+        let bytes = binary::to_bytes(value);
+        assert_eq!(&binary::from_bytes::<T>(&bytes).unwrap(), value);
+        assert_eq!(&binary::decode::<T>(&binary::encode(value)).unwrap(), value);
+        // Every shorter prefix is an error, never a panic or a wrong value
+        for end in 0..bytes.len() {
+            assert!(binary::from_bytes::<T>(&bytes[..end]).is_err());
+        }
+    }
+
+    // Round trips random values of every supported type, then damages encoded menus in random
+    // ways and checks that none of them decodes
+    pub fn check_round_trips() {
+        let mut random = Random::new(0x2021_c0ff_ee00_0001);
+        for _ in 0..500 {
+            let length = random.below(20) as usize;
+            round_trip(&random_integer(&mut random));
+            round_trip(&(random_integer(&mut random) as i32, random_integer(&mut random) as u16, random_integer(&mut random) as u8, random.below(2) == 1));
+            round_trip(&(random_integer(&mut random) as f64 / 3.0, random_integer(&mut random) as f32));
+            round_trip(&random_string(&mut random));
+            round_trip(&char::from_u32(random.below(0x11_0000) as u32).unwrap_or('☕'));
+            round_trip(&[random_integer(&mut random), random_integer(&mut random), random_integer(&mut random)]);
+            round_trip(&(0..length).map(|_| random_coffee(&mut random)).collect::<Vec<_>>());
+            round_trip(&(0..length).map(|_| random_string(&mut random)).collect::<VecDeque<_>>());
+            round_trip(&(0..length).map(|_| random_integer(&mut random) as i32).collect::<LinkedList<_>>());
+            round_trip(&(0..length).map(|_| random_string(&mut random)).collect::<HashSet<_>>());
+            round_trip(&(0..length).map(|_| random_integer(&mut random)).collect::<BTreeSet<_>>());
+            round_trip(&(0..length).map(|_| (random_string(&mut random), random_coffee(&mut random))).collect::<HashMap<_, _>>());
+            round_trip(&(0..length).map(|_| (random_integer(&mut random) as u32, Some(random_string(&mut random)))).collect::<BTreeMap<_, _>>());
+            round_trip(&(0..length).map(|_| if random.below(2) == 0 { None } else { Some(random_coffee(&mut random)) }).collect::<Vec<_>>());
+        }
+
+        // Any single byte change anywhere in the envelope is caught
+        let mut damaged = 0;
+        for _ in 0..2000 {
+            let coffees: Vec<Coffee> = (0..random.below(10)).map(|_| random_coffee(&mut random)).collect();
+            let mut bytes = binary::to_bytes(&coffees);
+            let index = random.below(bytes.len() as u64) as usize;
+            bytes[index] ^= 1 + random.below(255) as u8;
+            let result = binary::from_bytes::<Vec<Coffee>>(&bytes);
+            assert!(result.is_err(), "a changed byte at {} was not detected", index);
+            damaged += 1;
+        }
+
+        // Random bytes without an envelope may decode to something, but never panic
+        let mut decoded = 0;
+        for _ in 0..20_000 {
+            let bytes: Vec<u8> = (0..random.below(40)).map(|_| random.below(256) as u8).collect();
+            if binary::decode::<Vec<Coffee>>(&bytes).is_ok() {
+                decoded += 1;
+            }
+            let _ = binary::decode::<BTreeMap<String, Vec<Option<char>>>>(&bytes);
+        }
+        println!("Round trips passed, {} damaged envelopes rejected, {} of 20000 random inputs were valid", damaged, decoded);
+    }
+}
+
+pub mod durable_storage {
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::path::Path;
+
+    use super::disk_map::{DiskMap, DiskMapOptions};
+    use super::temp_directory;
+    use crate::module_09::property::Random;
+
+    fn file_len(path: &Path) -> u64 {
+        fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0)
+    }
+
+    pub fn demo_disk_map() {
+        let directory = temp_directory("inventory");
+
+        // The `new_map` inventory from `useful_map_functions`, but on disk
+        {
+            let mut inventory: DiskMap<String, f64> = DiskMap::open(&directory).unwrap();
+            inventory.insert(String::from("Potato"), 35.0).unwrap();
+            inventory.insert(String::from("Rice"), 85.0).unwrap();
+            inventory.insert(String::from("Chicken"), 285.0).unwrap();
+            inventory.insert(String::from("Beef"), 1000.0).unwrap();
+            inventory.insert(String::from("Rice"), 90.0).unwrap();
+            inventory.remove(&String::from("Chicken")).unwrap();
+            println!("Before the restart: {:?}", inventory.as_map());
+            println!("Log: {} bytes\n", inventory.log_len());
+            // Dropped here without saving anything: every change is already in the log
+        }
+
+        let mut inventory: DiskMap<String, f64> = DiskMap::open(&directory).unwrap();
+        println!("After the restart: {:?}", inventory.as_map());
+        println!("{:?}\n", inventory.recovery());
+        for (item, price) in inventory.iter() {
+            println!("{item} costs {price:.2} tk");
+        }
+        println!();
+
+        // A snapshot moves the map into `snapshot.bin` and empties the log
+        inventory.snapshot().unwrap();
+        println!("After a snapshot: snapshot.bin {} bytes, wal.log {} bytes", file_len(&directory.join("snapshot.bin")), file_len(&directory.join("wal.log")));
+        inventory.insert(String::from("Latte"), 320.0).unwrap();
+        drop(inventory);
+
+        let inventory: DiskMap<String, f64> = DiskMap::open(&directory).unwrap();
+        println!("Reopened: {:?}", inventory.as_map());
+        println!("{:?}\n", inventory.recovery());
+        drop(inventory);
+
+        // A crash in the middle of writing a record: the half written record is dropped
+        let log_path = directory.join("wal.log");
+        let log = fs::read(&log_path).unwrap();
+        fs::write(&log_path, &log[..log.len() - 3]).unwrap();
+        let inventory: DiskMap<String, f64> = DiskMap::open(&directory).unwrap();
+        println!("After a torn write: {:?}", inventory.as_map());
+        println!("{:?}", inventory.recovery());
+        assert!(!inventory.contains_key(&String::from("Latte")));
+        drop(inventory);
+
+        // Snapshots happen on their own every so many changes
+        let mut counts: DiskMap<u32, u32> = DiskMapOptions::new().snapshot_every(Some(100)).open(directory.join("counts")).unwrap();
+        for n in 0..1050 {
+            counts.insert(n % 300, n).unwrap();
+        }
+        println!("\n1050 changes with a snapshot every 100: {} entries, {} bytes of log", counts.len(), counts.log_len());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    // Copies the map's files to `to`, with the log cut to `log_len` bytes
+    fn crash_copy(from: &Path, to: &Path, log_len: usize) {
+        let _ = fs::remove_dir_all(to);
+        fs::create_dir_all(to).unwrap();
+        if let Ok(snapshot) = fs::read(from.join("snapshot.bin")) {
+            fs::write(to.join("snapshot.bin"), snapshot).unwrap();
+        }
+        let log = fs::read(from.join("wal.log")).unwrap();
+        fs::write(to.join("wal.log"), &log[..log_len]).unwrap();
+    }
+
+    // Simulates crashes by cutting the log at random points, and checks that every reopened map is
+    // exactly the map after the last change that was completely written
+    pub fn check_crash_recovery() {
+        let directory = temp_directory("wal-check");
+        let crashed = temp_directory("wal-check-crashed");
+        let mut random = Random::new(0x2021_0000_dead_beef);
+        let options = DiskMapOptions::new().snapshot_every(None);
+
+        // Some history before a snapshot, so recovery starts from one
+        let mut map: DiskMap<u16, String> = options.open(&directory).unwrap();
+        for n in 0..50 {
+            map.insert(n, format!("before snapshot {}", n)).unwrap();
+        }
+        map.snapshot().unwrap();
+
+        // The expected map after every change, with the log length at that point
+        let mut states = vec![(map.log_len(), map.as_map().clone())];
+        for step in 0..400 {
+            let key = random.below(80) as u16;
+            if random.below(3) == 0 {
+                map.remove(&key).unwrap();
+            } else {
+                let value = "☕".repeat(random.below(4) as usize) + &step.to_string();
+                map.insert(key, value).unwrap();
+            }
+            states.push((map.log_len(), map.as_map().clone()));
+        }
+        let full_len = map.log_len() as usize;
+        drop(map);
+
+        // Every record boundary, one byte either side of it, and random points in between
+        let mut cuts: Vec<usize> = states.iter().flat_map(|&(len, _)| [len as usize - 1, len as usize, len as usize + 1]).collect();
+        cuts.extend((0..300).map(|_| random.below(full_len as u64 + 1) as usize));
+        cuts.retain(|&cut| cut <= full_len);
+        for &cut in &cuts {
+            crash_copy(&directory, &crashed, cut);
+            let mut recovered: DiskMap<u16, String> = options.open(&crashed).unwrap();
+            let (len, expected) = states.iter().rev().find(|&&(len, _)| len as usize <= cut).unwrap_or(&states[0]);
+            assert_eq!(recovered.as_map(), expected, "log cut at byte {}", cut);
+            assert_eq!(recovered.log_len(), *len, "log cut at byte {}", cut);
+
+            // The torn tail is gone, so a change after recovery is read back after another crash
+            recovered.insert(9999, String::from("after recovery")).unwrap();
+            drop(recovered);
+            let reopened: DiskMap<u16, String> = options.open(&crashed).unwrap();
+            assert_eq!(reopened.get(&9999).map(String::as_str), Some("after recovery"));
+            assert_eq!(reopened.len(), expected.len() + 1 - expected.contains_key(&9999) as usize);
+        }
+
+        // A damaged byte in the middle of the log loses that record and everything after it, but
+        // never produces a map that did not exist
+        for _ in 0..200 {
+            crash_copy(&directory, &crashed, full_len);
+            let log_path = crashed.join("wal.log");
+            let mut log = fs::read(&log_path).unwrap();
+            let index = 5 + random.below(full_len as u64 - 5) as usize;
+            log[index] ^= 1 + random.below(255) as u8;
+            fs::write(&log_path, &log).unwrap();
+            let recovered: DiskMap<u16, String> = options.open(&crashed).unwrap();
+            let expected = states.iter().rev().find(|&&(len, _)| len as usize <= index).unwrap();
+            assert_eq!(recovered.as_map(), &expected.1, "byte {} damaged", index);
+        }
+
+        // A crash after a snapshot replaced the old one but before the log was emptied replays
+        // the old log on top of the new snapshot, which gives the same map
+        let old_log = fs::read(directory.join("wal.log")).unwrap();
+        let mut map: DiskMap<u16, String> = options.open(&directory).unwrap();
+        map.snapshot().unwrap();
+        drop(map);
+        fs::write(directory.join("wal.log"), &old_log).unwrap();
+        let recovered: DiskMap<u16, String> = options.open(&directory).unwrap();
+        assert_eq!(recovered.as_map(), &states.last().unwrap().1);
+        drop(recovered);
+
+        println!("Recovered correctly from {} cut logs and 200 damaged ones", cuts.len());
+        fs::remove_dir_all(&directory).unwrap();
+        fs::remove_dir_all(&crashed).unwrap();
+    }
+}
+
+pub mod sorted_tables {
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::ops::Bound;
+
+    use super::binary;
+    use super::sstable::{self, SsTable, TableBuilder, TableError, TableOptions};
+    use super::temp_directory;
+    use crate::coffee::Coffee;
+    use crate::module_09::property::Random;
+
+    pub fn demo_sstable() {
+        let directory = temp_directory("sstable");
+        let path = directory.join("coffees.sst");
+
+        // A big catalog, keyed so that the keys sort in id order
+        let catalog: BTreeMap<String, Vec<u8>> = (0..100_000)
+            .map(|id| {
+                let coffee = Coffee::new(id, format!("Blend #{}", id), id % 97);
+                (format!("coffee:{:06}", id), binary::encode(&coffee))
+            })
+            .collect();
+        let stats = sstable::write_table(&path, &catalog).unwrap();
+        println!("Wrote {:?}", stats);
+        drop(catalog);
+
+        // Opening reads the footer, the index and the bloom filter, not the data
+        let table = SsTable::open(&path).unwrap();
+        println!("Opened: {} entries in {} blocks, {} blocks read so far\n", table.len(), table.data_blocks(), table.blocks_read());
+
+        // A point lookup reads one block
+        let bytes = table.get("coffee:031337").unwrap().unwrap();
+        let coffee: Coffee = binary::decode(&bytes).unwrap();
+        println!("coffee:031337 is {:?}, {} block read", coffee, table.blocks_read());
+
+        // Most lookups of missing keys are answered by the bloom filter alone
+        let before = table.blocks_read();
+        let missing = (0..10_000).filter(|n| table.get(&format!("coffee:{:06}x", n)).unwrap().is_none()).count();
+        println!("{} lookups of missing keys read {} blocks", missing, table.blocks_read() - before);
+
+        // A range scan reads only the blocks it covers
+        let before = table.blocks_read();
+        let names: Vec<String> = table
+            .range("coffee:050000".."coffee:050005")
+            .map(|entry| binary::decode::<Coffee>(&entry.unwrap().1).unwrap().name)
+            .collect();
+        println!("coffee:050000..coffee:050005 is {:?}, {} block read", names, table.blocks_read() - before);
+        let before = table.blocks_read();
+        let in_stock = table.iter().filter(|entry| binary::decode::<Coffee>(&entry.as_ref().unwrap().1).unwrap().count > 0).count();
+        println!("A full scan finds {} coffees in stock and reads all {} blocks\n", in_stock, table.blocks_read() - before);
+
+        // Without a bloom filter the file is smaller, but every lookup that reaches the key range
+        // reads a block
+        let no_bloom_path = directory.join("no-bloom.sst");
+        let small: BTreeMap<String, Vec<u8>> = (0..100_000).map(|id| (format!("coffee:{:06}", id), Vec::new())).collect();
+        let with_bloom = sstable::write_table(directory.join("bloom.sst"), &small).unwrap();
+        let without_bloom = TableOptions::new().bloom_bits_per_key(None).write(&no_bloom_path, &small).unwrap();
+        println!("Keys only: {} bytes with a bloom filter, {} bytes without", with_bloom.file_len, without_bloom.file_len);
+        let table = SsTable::open(&no_bloom_path).unwrap();
+        let missing = (0..10_000).filter(|n| table.get(&format!("coffee:{:06}x", n)).unwrap().is_none()).count();
+        println!("Without the filter {} lookups of missing keys read {} blocks\n", missing, table.blocks_read());
+
+        // A damaged block is reported, not read as wrong data
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[100] ^= 0x20;
+        fs::write(&path, &bytes).unwrap();
+        let table = SsTable::open(&path).unwrap();
+        match table.get("coffee:000001") {
+            Err(error) => println!("Damaged file: {}", error),
+            Ok(value) => panic!("read {:?} from a damaged block", value),
+        }
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    // Short keys from a small alphabet, so they share prefixes and collide with the lookups
+    fn random_key(random: &mut Random) -> String {
+        let len = random.below(6) as usize;
+        (0..len).map(|_| ['a', 'b', 'c', 'é', '0'][random.below(5) as usize]).collect()
+    }
+
+    fn random_bound(random: &mut Random) -> Bound<String> {
+        match random.below(3) {
+            0 => Bound::Included(random_key(random)),
+            1 => Bound::Excluded(random_key(random)),
+            _ => Bound::Unbounded,
+        }
+    }
+
+    fn as_str(bound: &Bound<String>) -> Bound<&str> {
+        bound.as_ref().map(String::as_str)
+    }
+
+    // Writes random maps with different block sizes, with and without a bloom filter, and checks
+    // every lookup and range scan of the files against the `BTreeMap` they were written from
+    pub fn check_sstable() {
+        let directory = temp_directory("sstable-check");
+        let path = directory.join("table.sst");
+        let mut random = Random::new(0x5eed_0000_2021_0048);
+        let mut lookups = 0;
+        let mut scans = 0;
+
+        for round in 0..120 {
+            let size = [0, 1, 2, 10, 100, 1000][round % 6];
+            let map: BTreeMap<String, Vec<u8>> = (0..size)
+                .map(|_| {
+                    let value = (0..random.below(40)).map(|_| random.below(256) as u8).collect();
+                    (random_key(&mut random), value)
+                })
+                .collect();
+            let block_size = [1, 16, 64, 4096][random.below(4) as usize];
+            let bloom = [None, Some(1), Some(10)][random.below(3) as usize];
+            let stats = TableOptions::new().block_size(block_size).bloom_bits_per_key(bloom).write(&path, &map).unwrap();
+            assert_eq!(stats.entries, map.len() as u64);
+            assert_eq!(stats.file_len, fs::metadata(&path).unwrap().len());
+
+            let table = SsTable::open(&path).unwrap();
+            assert_eq!(table.len(), map.len() as u64);
+            assert_eq!(table.data_blocks(), stats.data_blocks);
+            assert_eq!(table.blocks_read(), 0);
+
+            for _ in 0..200 {
+                let key = random_key(&mut random);
+                assert_eq!(table.get(&key).unwrap().as_ref(), map.get(&key), "get {:?}", key);
+                lookups += 1;
+            }
+            // Every key that was written is found, bloom filters have no false negatives
+            for (key, value) in &map {
+                assert_eq!(table.get(key).unwrap().as_ref(), Some(value));
+                lookups += 1;
+            }
+
+            let all: Vec<(String, Vec<u8>)> = table.iter().map(Result::unwrap).collect();
+            assert!(all.iter().eq(map.iter().map(|(key, value)| (key.clone(), value.clone())).collect::<Vec<_>>().iter()));
+            for _ in 0..50 {
+                let (start, end) = (random_bound(&mut random), random_bound(&mut random));
+                let (start, end) = (as_str(&start), as_str(&end));
+                // `BTreeMap::range` panics on a start after the end, a table just returns nothing
+                let inverted = match (start, end) {
+                    (Bound::Included(s) | Bound::Excluded(s), Bound::Included(e) | Bound::Excluded(e)) => {
+                        s > e || (s == e && matches!((start, end), (Bound::Excluded(_), Bound::Excluded(_))))
+                    }
+                    _ => false,
+                };
+                let expected: Vec<(&String, &Vec<u8>)> = if inverted { Vec::new() } else { map.range::<str, _>((start, end)).collect() };
+                let found: Vec<(String, Vec<u8>)> = table.range((start, end)).map(Result::unwrap).collect();
+                assert!(found.iter().map(|(key, value)| (key, value)).eq(expected.into_iter()), "range {:?}", (start, end));
+                scans += 1;
+            }
+        }
+
+        // Damage anywhere in the file is an error when the damaged part is read, never wrong data
+        let map: BTreeMap<String, Vec<u8>> = (0..500).map(|n| (format!("key {:04}", n), format!("value {}", n).into_bytes())).collect();
+        let written = TableOptions::new().block_size(256).write(&path, &map).unwrap();
+        let original = fs::read(&path).unwrap();
+        let mut errors = 0;
+        for _ in 0..300 {
+            let mut bytes = original.clone();
+            let index = random.below(bytes.len() as u64) as usize;
+            bytes[index] ^= 1 + random.below(255) as u8;
+            fs::write(&path, &bytes).unwrap();
+            let table = match SsTable::open(&path) {
+                Ok(table) => table,
+                Err(_) => {
+                    errors += 1;
+                    continue;
+                }
+            };
+            let mut failed = false;
+            for entry in table.iter() {
+                match entry {
+                    Ok((key, value)) => assert_eq!(map.get(&key), Some(&value), "byte {} damaged", index),
+                    Err(TableError::Corrupt { .. }) => failed = true,
+                    Err(error) => panic!("unexpected error {}", error),
+                }
+            }
+            for (key, value) in &map {
+                match table.get(key) {
+                    Ok(found) => assert_eq!(found.as_ref(), Some(value), "byte {} damaged", index),
+                    Err(_) => failed = true,
+                }
+            }
+            errors += failed as usize;
+        }
+        assert_eq!(errors, 300, "every damaged byte is covered by a checksum");
+
+        // Something that is not a table at all
+        fs::write(&path, b"id,name,count\n").unwrap();
+        assert!(matches!(SsTable::open(&path), Err(TableError::BadMagic)));
+
+        // Keys out of order are refused, and the table written so far is still good
+        let mut builder = TableBuilder::new(fs::File::create(&path).unwrap(), TableOptions::new());
+        builder.add("Latte", b"10").unwrap();
+        for key in ["Latte", "Espresso"] {
+            let error = builder.add(key, b"5").unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput, "{}", error);
+        }
+        builder.add("Mocha", b"20").unwrap();
+        builder.finish().unwrap();
+        let table = SsTable::open(&path).unwrap();
+        assert_eq!(table.iter().map(|entry| entry.unwrap().0).collect::<Vec<_>>(), ["Latte", "Mocha"]);
+
+        println!("{} lookups and {} range scans matched the BTreeMap, all 300 damaged files reported", lookups, scans);
+        println!("Last table: {:?}", written);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
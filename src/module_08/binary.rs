@@ -0,0 +1,548 @@
+// A compact binary format for collections, smaller and faster to read than JSON.
+//
+// Values are written one after the other with no field names and no separators:
+//
+// - unsigned integers are varints (LEB128): 7 bits per byte, the high bit set on every byte but
+//   the last, so 0..=127 takes one byte and a `u64` at most ten,
+// - signed integers are zigzag encoded first (0, -1, 1, -2, ... become 0, 1, 2, 3, ...) so small
+//   negative numbers stay short too,
+// - floats are their 4 or 8 bytes in little endian, `bool` and `Option` a single byte,
+// - strings and collections are their length as a varint followed by their contents,
+// - tuples, fixed size arrays and `Coffee` are their fields in order, without a length.
+//
+// Nothing in the encoding says which type was written, the reader has to know it. `to_bytes`
+// wraps a value in an envelope that makes a file or a message recognizable and safe to read:
+//
+//     "RCOL" | format version (1 byte) | payload length (varint) | payload | CRC-32 (4 bytes, LE)
+//
+// The checksum covers everything before it, so a truncated or corrupted file is rejected instead
+// of being decoded into wrong values. A reader refuses versions newer than it knows.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, LinkedList, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::hash::Hash;
+
+use crate::coffee::Coffee;
+
+pub const MAGIC: &[u8; 4] = b"RCOL";
+pub const FORMAT_VERSION: u8 = 1;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    // The input ended in the middle of a value
+    UnexpectedEnd { offset: usize },
+    // A varint longer than its type allows
+    VarintOverflow { offset: usize },
+    InvalidBool { offset: usize, byte: u8 },
+    InvalidOptionTag { offset: usize, byte: u8 },
+    InvalidChar { offset: usize, code: u32 },
+    InvalidUtf8 { offset: usize },
+    // The value ended before the input did
+    TrailingBytes { offset: usize },
+    BadMagic,
+    UnsupportedVersion { found: u8, supported: u8 },
+    ChecksumMismatch { expected: u32, found: u32 },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd { offset } => write!(f, "input ends in the middle of a value at byte {}", offset),
+            DecodeError::VarintOverflow { offset } => write!(f, "varint at byte {} is too large", offset),
+            DecodeError::InvalidBool { offset, byte } => write!(f, "byte {} is {}, not a bool", offset, byte),
+            DecodeError::InvalidOptionTag { offset, byte } => write!(f, "byte {} is {}, not an option tag", offset, byte),
+            DecodeError::InvalidChar { offset, code } => write!(f, "{:#x} at byte {} is not a char", code, offset),
+            DecodeError::InvalidUtf8 { offset } => write!(f, "string at byte {} is not valid UTF-8", offset),
+            DecodeError::TrailingBytes { offset } => write!(f, "unexpected bytes after the value at byte {}", offset),
+            DecodeError::BadMagic => write!(f, "not an encoded collection, the magic bytes are wrong"),
+            DecodeError::UnsupportedVersion { found, supported } => {
+                write!(f, "format version {} is not supported, the latest known version is {}", found, supported)
+            }
+            DecodeError::ChecksumMismatch { expected, found } => {
+                write!(f, "checksum mismatch: stored {:#010x}, computed {:#010x}", expected, found)
+            }
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+/// Reads values from a byte slice, keeping track of the offset for errors.
+pub struct Decoder<'a> {
+    input: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(input: &'a [u8]) -> Decoder<'a> {
+        Decoder { input, offset: 0 }
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.input.len() - self.offset
+    }
+
+    pub fn read_bytes(&mut self, count: usize) -> Result<&'a [u8], DecodeError> {
+        if count > self.remaining() {
+            return Err(DecodeError::UnexpectedEnd { offset: self.input.len() });
+        }
+        let bytes = &self.input[self.offset..self.offset + count];
+        self.offset += count;
+        Ok(bytes)
+    }
+
+    pub fn read_byte(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_varint(&mut self) -> Result<u64, DecodeError> {
+        let start = self.offset;
+        let mut value: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_byte()?;
+            let bits = (byte & 0x7f) as u64;
+            // The tenth byte may only hold the one bit that is left of a u64
+            if shift == 63 && bits > 1 {
+                return Err(DecodeError::VarintOverflow { offset: start });
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(DecodeError::VarintOverflow { offset: start })
+    }
+
+    // A length prefix. Each element takes at least one byte, so a length longer than the rest of
+    // the input is corrupt, and checking it here keeps a bad length from allocating gigabytes.
+    pub fn read_length(&mut self) -> Result<usize, DecodeError> {
+        let offset = self.offset;
+        let length = self.read_varint()?;
+        if length > self.remaining() as u64 {
+            return Err(DecodeError::UnexpectedEnd { offset });
+        }
+        Ok(length as usize)
+    }
+
+    // Checks that the whole input was used
+    pub fn finish(self) -> Result<(), DecodeError> {
+        if self.remaining() > 0 {
+            return Err(DecodeError::TrailingBytes { offset: self.offset });
+        }
+        Ok(())
+    }
+}
+
+pub fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+pub trait Encode {
+    fn encode(&self, out: &mut Vec<u8>);
+}
+
+pub trait Decode: Sized {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError>;
+}
+
+// The value alone, without the envelope
+pub fn encode<T: Encode + ?Sized>(value: &T) -> Vec<u8> {
+    let mut out = Vec::new();
+    value.encode(&mut out);
+    out
+}
+
+// A value that takes up all of `bytes`, without the envelope
+pub fn decode<T: Decode>(bytes: &[u8]) -> Result<T, DecodeError> {
+    let mut decoder = Decoder::new(bytes);
+    let value = T::decode(&mut decoder)?;
+    decoder.finish()?;
+    Ok(value)
+}
+
+pub fn to_bytes<T: Encode + ?Sized>(value: &T) -> Vec<u8> {
+    let payload = encode(value);
+    let mut out = Vec::with_capacity(payload.len() + 16);
+    out.extend_from_slice(MAGIC);
+    out.push(FORMAT_VERSION);
+    write_varint(&mut out, payload.len() as u64);
+    out.extend_from_slice(&payload);
+    let checksum = crc32(&out);
+    out.extend_from_slice(&checksum.to_le_bytes());
+    out
+}
+
+pub fn from_bytes<T: Decode>(bytes: &[u8]) -> Result<T, DecodeError> {
+    if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(DecodeError::BadMagic);
+    }
+    let mut header = Decoder::new(bytes);
+    header.read_bytes(MAGIC.len())?;
+    let version = header.read_byte()?;
+    let length = header.read_length()?;
+    let payload_start = header.offset();
+    header.read_bytes(length)?;
+    let stored = u32::from_le_bytes(header.read_bytes(4)?.try_into().unwrap());
+    header.finish()?;
+
+    // The checksum comes first, a corrupted version byte should not look like a newer format
+    let payload_end = payload_start + length;
+    let computed = crc32(&bytes[..payload_end]);
+    if stored != computed {
+        return Err(DecodeError::ChecksumMismatch { expected: stored, found: computed });
+    }
+    if version == 0 || version > FORMAT_VERSION {
+        return Err(DecodeError::UnsupportedVersion { found: version, supported: FORMAT_VERSION });
+    }
+
+    // Offsets in errors count from the start of the envelope
+    let mut decoder = Decoder { input: &bytes[..payload_end], offset: payload_start };
+    let value = T::decode(&mut decoder)?;
+    decoder.finish()?;
+    Ok(value)
+}
+
+// CRC-32 as used by zip and PNG (polynomial 0xEDB88320, reflected)
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+};
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc = CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+impl Encode for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+}
+
+impl Decode for bool {
+    fn decode(input: &mut Decoder<'_>) -> Result<bool, DecodeError> {
+        let offset = input.offset();
+        match input.read_byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            byte => Err(DecodeError::InvalidBool { offset, byte }),
+        }
+    }
+}
+
+// A `u8` is a byte as is, a varint would make half of the values two bytes long
+impl Encode for u8 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self);
+    }
+}
+
+impl Decode for u8 {
+    fn decode(input: &mut Decoder<'_>) -> Result<u8, DecodeError> {
+        input.read_byte()
+    }
+}
+
+macro_rules! impl_codec_for_unsigned {
+    ($($integer:ty),*) => {$(
+        impl Encode for $integer {
+            fn encode(&self, out: &mut Vec<u8>) {
+                write_varint(out, *self as u64);
+            }
+        }
+
+        impl Decode for $integer {
+            fn decode(input: &mut Decoder<'_>) -> Result<$integer, DecodeError> {
+                let offset = input.offset();
+                <$integer>::try_from(input.read_varint()?).map_err(|_| DecodeError::VarintOverflow { offset })
+            }
+        }
+    )*};
+}
+
+impl_codec_for_unsigned!(u16, u32, u64, usize);
+
+macro_rules! impl_codec_for_signed {
+    ($($integer:ty),*) => {$(
+        impl Encode for $integer {
+            fn encode(&self, out: &mut Vec<u8>) {
+                write_varint(out, zigzag(*self as i64));
+            }
+        }
+
+        impl Decode for $integer {
+            fn decode(input: &mut Decoder<'_>) -> Result<$integer, DecodeError> {
+                let offset = input.offset();
+                <$integer>::try_from(unzigzag(input.read_varint()?)).map_err(|_| DecodeError::VarintOverflow { offset })
+            }
+        }
+    )*};
+}
+
+impl_codec_for_signed!(i8, i16, i32, i64, isize);
+
+impl Encode for f32 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl Decode for f32 {
+    fn decode(input: &mut Decoder<'_>) -> Result<f32, DecodeError> {
+        Ok(f32::from_le_bytes(input.read_bytes(4)?.try_into().unwrap()))
+    }
+}
+
+impl Encode for f64 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl Decode for f64 {
+    fn decode(input: &mut Decoder<'_>) -> Result<f64, DecodeError> {
+        Ok(f64::from_le_bytes(input.read_bytes(8)?.try_into().unwrap()))
+    }
+}
+
+impl Encode for char {
+    fn encode(&self, out: &mut Vec<u8>) {
+        write_varint(out, *self as u64);
+    }
+}
+
+impl Decode for char {
+    fn decode(input: &mut Decoder<'_>) -> Result<char, DecodeError> {
+        let offset = input.offset();
+        let code = u32::decode(input)?;
+        char::from_u32(code).ok_or(DecodeError::InvalidChar { offset, code })
+    }
+}
+
+impl Encode for str {
+    fn encode(&self, out: &mut Vec<u8>) {
+        write_varint(out, self.len() as u64);
+        out.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.as_str().encode(out);
+    }
+}
+
+impl Decode for String {
+    fn decode(input: &mut Decoder<'_>) -> Result<String, DecodeError> {
+        let offset = input.offset();
+        let length = input.read_length()?;
+        let bytes = input.read_bytes(length)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidUtf8 { offset })
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (**self).encode(out);
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for Box<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (**self).encode(out);
+    }
+}
+
+impl<T: Decode> Decode for Box<T> {
+    fn decode(input: &mut Decoder<'_>) -> Result<Box<T>, DecodeError> {
+        T::decode(input).map(Box::new)
+    }
+}
+
+// 0 for `None`, 1 followed by the value for `Some`
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            None => out.push(0),
+            Some(value) => {
+                out.push(1);
+                value.encode(out);
+            }
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(input: &mut Decoder<'_>) -> Result<Option<T>, DecodeError> {
+        let offset = input.offset();
+        match input.read_byte()? {
+            0 => Ok(None),
+            1 => T::decode(input).map(Some),
+            byte => Err(DecodeError::InvalidOptionTag { offset, byte }),
+        }
+    }
+}
+
+fn encode_sequence<'a, T: Encode + 'a>(out: &mut Vec<u8>, length: usize, elements: impl Iterator<Item = &'a T>) {
+    write_varint(out, length as u64);
+    for element in elements {
+        element.encode(out);
+    }
+}
+
+fn decode_sequence<T: Decode, C: FromIterator<T>>(input: &mut Decoder<'_>) -> Result<C, DecodeError> {
+    let length = input.read_length()?;
+    (0..length).map(|_| T::decode(input)).collect()
+}
+
+impl<T: Encode> Encode for [T] {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_sequence(out, self.len(), self.iter());
+    }
+}
+
+// The length is part of the type, so it is not written
+impl<T: Encode, const N: usize> Encode for [T; N] {
+    fn encode(&self, out: &mut Vec<u8>) {
+        for element in self {
+            element.encode(out);
+        }
+    }
+}
+
+impl<T: Decode, const N: usize> Decode for [T; N] {
+    fn decode(input: &mut Decoder<'_>) -> Result<[T; N], DecodeError> {
+        let elements = (0..N).map(|_| T::decode(input)).collect::<Result<Vec<T>, _>>()?;
+        match elements.try_into() {
+            Ok(array) => Ok(array),
+            Err(_) => unreachable!("exactly {} elements were decoded", N),
+        }
+    }
+}
+
+macro_rules! impl_codec_for_sequences {
+    ($($collection:ident<T $(: $bound:ident $(+ $more:ident)*)?>),*) => {$(
+        impl<T: Encode> Encode for $collection<T> {
+            fn encode(&self, out: &mut Vec<u8>) {
+                encode_sequence(out, self.len(), self.iter());
+            }
+        }
+
+        impl<T: Decode $(+ $bound $(+ $more)*)?> Decode for $collection<T> {
+            fn decode(input: &mut Decoder<'_>) -> Result<$collection<T>, DecodeError> {
+                decode_sequence(input)
+            }
+        }
+    )*};
+}
+
+impl_codec_for_sequences!(Vec<T>, VecDeque<T>, LinkedList<T>, HashSet<T: Hash + Eq>, BTreeSet<T: Ord>);
+
+fn encode_map<'a, K: Encode + 'a, V: Encode + 'a>(
+    out: &mut Vec<u8>,
+    length: usize,
+    entries: impl Iterator<Item = (&'a K, &'a V)>,
+) {
+    write_varint(out, length as u64);
+    for (key, value) in entries {
+        key.encode(out);
+        value.encode(out);
+    }
+}
+
+fn decode_map<K: Decode, V: Decode, C: FromIterator<(K, V)>>(input: &mut Decoder<'_>) -> Result<C, DecodeError> {
+    let length = input.read_length()?;
+    (0..length).map(|_| Ok((K::decode(input)?, V::decode(input)?))).collect()
+}
+
+impl<K: Encode, V: Encode> Encode for HashMap<K, V> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_map(out, self.len(), self.iter());
+    }
+}
+
+impl<K: Decode + Hash + Eq, V: Decode> Decode for HashMap<K, V> {
+    fn decode(input: &mut Decoder<'_>) -> Result<HashMap<K, V>, DecodeError> {
+        decode_map(input)
+    }
+}
+
+impl<K: Encode, V: Encode> Encode for BTreeMap<K, V> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_map(out, self.len(), self.iter());
+    }
+}
+
+impl<K: Decode + Ord, V: Decode> Decode for BTreeMap<K, V> {
+    fn decode(input: &mut Decoder<'_>) -> Result<BTreeMap<K, V>, DecodeError> {
+        decode_map(input)
+    }
+}
+
+macro_rules! impl_codec_for_tuples {
+    ($(($($name:ident $index:tt),+);)*) => {$(
+        impl<$($name: Encode),+> Encode for ($($name,)+) {
+            fn encode(&self, out: &mut Vec<u8>) {
+                $(self.$index.encode(out);)+
+            }
+        }
+
+        impl<$($name: Decode),+> Decode for ($($name,)+) {
+            fn decode(input: &mut Decoder<'_>) -> Result<($($name,)+), DecodeError> {
+                Ok(($($name::decode(input)?,)+))
+            }
+        }
+    )*};
+}
+
+impl_codec_for_tuples! {
+    (A 0);
+    (A 0, B 1);
+    (A 0, B 1, C 2);
+    (A 0, B 1, C 2, D 3);
+}
+
+impl Encode for Coffee {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.id.encode(out);
+        self.name.encode(out);
+        self.count.encode(out);
+    }
+}
+
+impl Decode for Coffee {
+    fn decode(input: &mut Decoder<'_>) -> Result<Coffee, DecodeError> {
+        Ok(Coffee { id: i32::decode(input)?, name: String::decode(input)?, count: i32::decode(input)? })
+    }
+}
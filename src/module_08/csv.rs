@@ -0,0 +1,401 @@
+// Reading and writing CSV files (RFC 4180).
+//
+// A CSV file is lines of fields separated by commas. A field that contains a comma, a double quote
+// or a line break is put in double quotes, and a double quote inside it is written twice:
+//
+//     id,name,count
+//     1000,"Latte, large",10
+//     1001,"The ""Mocha""",5
+//
+// `Reader` reads one record at a time from any `BufRead`, so a file is never loaded into memory
+// as a whole. A quoted field can span several lines, and lines may end in "\r\n" or "\n".
+// `Writer` quotes only the fields that need it and ends lines with "\r\n" as the RFC says.
+//
+// The first record is the header. Types that implement `CsvRecord` are read by column name, so
+// the columns can be in any order and extra columns are ignored.
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+use crate::coffee::Coffee;
+
+#[derive(Debug)]
+pub enum CsvError {
+    Io(io::Error),
+    // A quoted field that is still open at the end of the input
+    UnterminatedQuote { line: usize },
+    // A quote inside an unquoted field, or text right after a closing quote
+    UnexpectedQuote { line: usize, column: usize },
+    // A record with more or fewer fields than the header
+    FieldCount { line: usize, expected: usize, found: usize },
+    MissingColumn { name: &'static str },
+    // The header names a column twice, so it is not clear which field to read
+    DuplicateColumn { name: String },
+    InvalidValue { line: usize, column: &'static str, value: String },
+    // Two records with the same key when loading a map
+    DuplicateKey { line: usize, key: String },
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CsvError::Io(error) => write!(f, "I/O error: {}", error),
+            CsvError::UnterminatedQuote { line } => write!(f, "line {}: quoted field is never closed", line),
+            CsvError::UnexpectedQuote { line, column } => write!(f, "line {}, field {}: unexpected quote", line, column),
+            CsvError::FieldCount { line, expected, found } => {
+                write!(f, "line {}: expected {} fields, found {}", line, expected, found)
+            }
+            CsvError::MissingColumn { name } => write!(f, "the header has no `{}` column", name),
+            CsvError::DuplicateColumn { name } => write!(f, "the header has the `{}` column twice", name),
+            CsvError::InvalidValue { line, column, value } => {
+                write!(f, "line {}: {:?} is not a valid `{}`", line, value, column)
+            }
+            CsvError::DuplicateKey { line, key } => write!(f, "line {}: duplicate key {:?}", line, key),
+        }
+    }
+}
+
+impl Error for CsvError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CsvError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CsvError {
+    fn from(error: io::Error) -> Self {
+        CsvError::Io(error)
+    }
+}
+
+/// Reads CSV records one at a time.
+pub struct Reader<R> {
+    input: R,
+    line: String,
+    // Lines read so far
+    line_number: usize,
+    // The line the last record started on
+    record_line: usize,
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(input: R) -> Reader<R> {
+        Reader { input, line: String::new(), line_number: 0, record_line: 0 }
+    }
+
+    // The line the last record returned by `read_record` started on, counted from 1
+    pub fn record_line(&self) -> usize {
+        self.record_line
+    }
+
+    fn read_line(&mut self) -> io::Result<bool> {
+        self.line.clear();
+        if self.input.read_line(&mut self.line)? == 0 {
+            return Ok(false);
+        }
+        self.line_number += 1;
+        // Spreadsheets often start UTF-8 files with a byte order mark
+        if self.line_number == 1 && self.line.starts_with('\u{feff}') {
+            self.line.drain(..'\u{feff}'.len_utf8());
+        }
+        Ok(true)
+    }
+
+    // Reads the next record into `fields`. Returns `false` at the end of the input. Empty lines
+    // between records are skipped.
+    pub fn read_record(&mut self, fields: &mut Vec<String>) -> Result<bool, CsvError> {
+        fields.clear();
+        loop {
+            if !self.read_line()? {
+                return Ok(false);
+            }
+            if !self.line.trim_end_matches(['\r', '\n']).is_empty() {
+                break;
+            }
+        }
+        self.record_line = self.line_number;
+
+        let mut field = String::new();
+        let mut in_quotes = false;
+        // The field was quoted and its closing quote has been read
+        let mut closed = false;
+        loop {
+            let mut chars = self.line.chars().peekable();
+            while let Some(ch) = chars.next() {
+                if in_quotes {
+                    match ch {
+                        '"' if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            field.push('"');
+                        }
+                        '"' => {
+                            in_quotes = false;
+                            closed = true;
+                        }
+                        _ => field.push(ch),
+                    }
+                    continue;
+                }
+                match ch {
+                    ',' => {
+                        fields.push(std::mem::take(&mut field));
+                        closed = false;
+                    }
+                    '\n' => {}
+                    '\r' if chars.peek() == Some(&'\n') => {}
+                    '"' if field.is_empty() && !closed => in_quotes = true,
+                    _ if ch == '"' || closed => {
+                        return Err(CsvError::UnexpectedQuote { line: self.line_number, column: fields.len() + 1 });
+                    }
+                    _ => field.push(ch),
+                }
+            }
+            if !in_quotes {
+                break;
+            }
+            // The line break belongs to the quoted field, which goes on on the next line
+            if !self.read_line()? {
+                return Err(CsvError::UnterminatedQuote { line: self.record_line });
+            }
+        }
+        fields.push(field);
+        Ok(true)
+    }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+    type Item = Result<Vec<String>, CsvError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut fields = Vec::new();
+        match self.read_record(&mut fields) {
+            Ok(true) => Some(Ok(fields)),
+            Ok(false) => None,
+            Err(error) => Some(Err(error)),
+        }
+    }
+}
+
+/// Writes CSV records, quoting the fields that need it.
+pub struct Writer<W: Write> {
+    output: W,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(output: W) -> Writer<W> {
+        Writer { output }
+    }
+
+    pub fn write_record<I>(&mut self, fields: I) -> io::Result<()>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let mut fields = fields.into_iter().peekable();
+        let mut first = true;
+        while let Some(field) = fields.next() {
+            if !first {
+                self.output.write_all(b",")?;
+            }
+            let field = field.as_ref();
+            // A record of one empty field would be an empty line, which the reader skips
+            let only_field = first && fields.peek().is_none();
+            first = false;
+            if field.contains([',', '"', '\r', '\n']) || (only_field && field.is_empty()) {
+                write!(self.output, "\"{}\"", field.replace('"', "\"\""))?;
+            } else {
+                self.output.write_all(field.as_bytes())?;
+            }
+        }
+        self.output.write_all(b"\r\n")
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+}
+
+/// One record with the header it belongs to, so fields can be looked up by column name.
+pub struct Row<'a> {
+    columns: &'a HashMap<String, usize>,
+    fields: &'a [String],
+    line: usize,
+}
+
+impl<'a> Row<'a> {
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn get(&self, column: &'static str) -> Result<&'a str, CsvError> {
+        match self.columns.get(column) {
+            Some(&index) => Ok(&self.fields[index]),
+            None => Err(CsvError::MissingColumn { name: column }),
+        }
+    }
+
+    // The field parsed with `FromStr`, surrounding spaces ignored
+    pub fn parse<T: FromStr>(&self, column: &'static str) -> Result<T, CsvError> {
+        let value = self.get(column)?;
+        value.trim().parse().map_err(|_| CsvError::InvalidValue { line: self.line, column, value: value.to_string() })
+    }
+}
+
+/// A type that is stored as one CSV record.
+pub trait CsvRecord: Sized {
+    // The columns `to_record` writes, in order
+    const HEADER: &'static [&'static str];
+
+    fn from_row(row: &Row<'_>) -> Result<Self, CsvError>;
+
+    fn to_record(&self) -> Vec<String>;
+}
+
+impl CsvRecord for Coffee {
+    const HEADER: &'static [&'static str] = &["id", "name", "count"];
+
+    fn from_row(row: &Row<'_>) -> Result<Coffee, CsvError> {
+        Ok(Coffee { id: row.parse("id")?, name: row.get("name")?.to_string(), count: row.parse("count")? })
+    }
+
+    fn to_record(&self) -> Vec<String> {
+        vec![self.id.to_string(), self.name.clone(), self.count.to_string()]
+    }
+}
+
+/// One line of a price list, like the coffee prices in the module_04 maps.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Price {
+    pub name: String,
+    pub price: f64,
+}
+
+impl CsvRecord for Price {
+    const HEADER: &'static [&'static str] = &["name", "price"];
+
+    fn from_row(row: &Row<'_>) -> Result<Price, CsvError> {
+        Ok(Price { name: row.get("name")?.to_string(), price: row.parse("price")? })
+    }
+
+    fn to_record(&self) -> Vec<String> {
+        vec![self.name.clone(), self.price.to_string()]
+    }
+}
+
+/// Typed records read one at a time, after the header.
+pub struct Records<T, R> {
+    reader: Reader<R>,
+    columns: HashMap<String, usize>,
+    // Every record has as many fields as the header
+    header_len: usize,
+    fields: Vec<String>,
+    record: std::marker::PhantomData<T>,
+}
+
+impl<T: CsvRecord, R: BufRead> Iterator for Records<T, R> {
+    type Item = Result<T, CsvError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.reader.read_record(&mut self.fields) {
+            Ok(true) => {}
+            Ok(false) => return None,
+            Err(error) => return Some(Err(error)),
+        }
+        let line = self.reader.record_line();
+        if self.fields.len() != self.header_len {
+            return Some(Err(CsvError::FieldCount { line, expected: self.header_len, found: self.fields.len() }));
+        }
+        Some(T::from_row(&Row { columns: &self.columns, fields: &self.fields, line }))
+    }
+}
+
+// Reads the header and checks that it has every column `T` needs. An empty input has no header
+// and gives no records.
+pub fn records<T: CsvRecord, R: BufRead>(input: R) -> Result<Records<T, R>, CsvError> {
+    let mut reader = Reader::new(input);
+    let mut header = Vec::new();
+    let mut columns = HashMap::new();
+    if reader.read_record(&mut header)? {
+        for (index, name) in header.iter().enumerate() {
+            let name = name.trim();
+            if columns.insert(name.to_string(), index).is_some() {
+                return Err(CsvError::DuplicateColumn { name: name.to_string() });
+            }
+        }
+        if let Some(name) = T::HEADER.iter().find(|&&name| !columns.contains_key(name)) {
+            return Err(CsvError::MissingColumn { name });
+        }
+    }
+    Ok(Records { reader, columns, header_len: header.len(), fields: Vec::new(), record: std::marker::PhantomData })
+}
+
+pub fn write_records<'a, T, W, I>(output: W, records: I) -> io::Result<W>
+where
+    T: CsvRecord + 'a,
+    W: Write,
+    I: IntoIterator<Item = &'a T>,
+{
+    let mut writer = Writer::new(output);
+    writer.write_record(T::HEADER)?;
+    for record in records {
+        writer.write_record(record.to_record())?;
+    }
+    writer.flush()?;
+    Ok(writer.into_inner())
+}
+
+pub fn load_vec<R: BufRead>(input: R) -> Result<Vec<Coffee>, CsvError> {
+    records(input)?.collect()
+}
+
+pub fn save_vec<W: Write>(output: W, coffees: &[Coffee]) -> io::Result<W> {
+    write_records(output, coffees)
+}
+
+// Coffees keyed by name. A name that is in the file twice is an error, not a silent overwrite.
+pub fn load_map<R: BufRead>(input: R) -> Result<HashMap<String, Coffee>, CsvError> {
+    let mut records = records::<Coffee, R>(input)?;
+    let mut map = HashMap::new();
+    while let Some(coffee) = records.next() {
+        let coffee = coffee?;
+        if map.contains_key(&coffee.name) {
+            return Err(CsvError::DuplicateKey { line: records.reader.record_line(), key: coffee.name });
+        }
+        map.insert(coffee.name.clone(), coffee);
+    }
+    Ok(map)
+}
+
+// Sorted by name, so saving the same map twice writes the same file
+pub fn save_map<W: Write>(output: W, coffees: &HashMap<String, Coffee>) -> io::Result<W> {
+    let mut sorted: Vec<&Coffee> = coffees.values().collect();
+    sorted.sort_by(|a, b| a.name.cmp(&b.name));
+    write_records(output, sorted)
+}
+
+pub fn load_price_list<R: BufRead>(input: R) -> Result<BTreeMap<String, f64>, CsvError> {
+    let mut records = records::<Price, R>(input)?;
+    let mut prices = BTreeMap::new();
+    while let Some(price) = records.next() {
+        let price = price?;
+        if prices.contains_key(&price.name) {
+            return Err(CsvError::DuplicateKey { line: records.reader.record_line(), key: price.name });
+        }
+        prices.insert(price.name, price.price);
+    }
+    Ok(prices)
+}
+
+pub fn save_price_list<W: Write>(output: W, prices: &BTreeMap<String, f64>) -> io::Result<W> {
+    let records: Vec<Price> = prices.iter().map(|(name, &price)| Price { name: name.clone(), price }).collect();
+    write_records(output, &records)
+}
@@ -0,0 +1,372 @@
+// A `BTreeMap` that survives restarts: a write-ahead log plus snapshots.
+//
+// All reads are served from a `BTreeMap` in memory. Every change is first appended to a log file,
+// so after a crash the map is rebuilt by replaying the log. To keep the log from growing forever,
+// the whole map is written to a snapshot file every so often, after which the log starts over.
+//
+// A map lives in a directory with two files:
+//
+//     snapshot.bin   the map as of the last snapshot, in the `binary` envelope format
+//     wal.log        "RWAL", a version byte, then one record per change since that snapshot
+//
+// Each log record is `payload length (varint) | CRC-32 of the payload | payload`, where the payload
+// is a tag (1 insert, 2 remove), the key and for inserts the value. A crash can leave the last
+// record half written. On open, replay stops at the first record that is incomplete or fails its
+// checksum, and the log is cut back to the records before it: the map comes back exactly as it was
+// after the last complete change.
+//
+// A snapshot is written to a temporary file and renamed over the old one, so there is always one
+// whole snapshot on disk. If the process dies after the rename but before the log is emptied, the
+// old log is replayed on top of the new snapshot. That is harmless: replaying inserts and removes
+// in order gives the same map no matter how many of them the snapshot already contains.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::ops::RangeBounds;
+use std::path::{Path, PathBuf};
+
+use super::binary::{self, crc32, Decode, DecodeError, Decoder, Encode};
+
+const LOG_MAGIC: &[u8; 4] = b"RWAL";
+const LOG_VERSION: u8 = 1;
+const LOG_HEADER_LEN: u64 = 5;
+const INSERT: u8 = 1;
+const REMOVE: u8 = 2;
+
+const SNAPSHOT_FILE: &str = "snapshot.bin";
+const SNAPSHOT_TEMP_FILE: &str = "snapshot.tmp";
+const LOG_FILE: &str = "wal.log";
+
+#[derive(Debug)]
+pub enum DiskMapError {
+    Io(io::Error),
+    // The snapshot is damaged. Unlike a torn log record this can not be the result of a crash,
+    // because snapshots are replaced in one rename, so it is not silently ignored.
+    CorruptSnapshot(DecodeError),
+    // The log file does not start with the log header
+    NotALog,
+}
+
+impl fmt::Display for DiskMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiskMapError::Io(error) => write!(f, "I/O error: {}", error),
+            DiskMapError::CorruptSnapshot(error) => write!(f, "the snapshot is corrupt: {}", error),
+            DiskMapError::NotALog => write!(f, "{} is not a write-ahead log", LOG_FILE),
+        }
+    }
+}
+
+impl Error for DiskMapError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DiskMapError::Io(error) => Some(error),
+            DiskMapError::CorruptSnapshot(error) => Some(error),
+            DiskMapError::NotALog => None,
+        }
+    }
+}
+
+impl From<io::Error> for DiskMapError {
+    fn from(error: io::Error) -> Self {
+        DiskMapError::Io(error)
+    }
+}
+
+/// What `open` found on disk.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Recovery {
+    pub snapshot_entries: usize,
+    pub records_replayed: usize,
+    // The bytes after the last complete record, cut off the log
+    pub bytes_discarded: u64,
+}
+
+/// How a `DiskMap` is opened.
+#[derive(Clone, Copy, Debug)]
+pub struct DiskMapOptions {
+    snapshot_every: Option<usize>,
+    sync_writes: bool,
+}
+
+impl DiskMapOptions {
+    pub fn new() -> DiskMapOptions {
+        DiskMapOptions { snapshot_every: Some(1000), sync_writes: false }
+    }
+
+    // Write a snapshot after this many changes. `None` only snapshots when asked to.
+    pub fn snapshot_every(mut self, changes: Option<usize>) -> DiskMapOptions {
+        self.snapshot_every = changes;
+        self
+    }
+
+    // Wait for every change to reach the disk before returning. Without it a change is handed to
+    // the operating system, which survives the process crashing but not the machine losing power.
+    pub fn sync_writes(mut self, sync: bool) -> DiskMapOptions {
+        self.sync_writes = sync;
+        self
+    }
+
+    pub fn open<K, V>(self, directory: impl AsRef<Path>) -> Result<DiskMap<K, V>, DiskMapError>
+    where
+        K: Encode + Decode + Ord,
+        V: Encode + Decode,
+    {
+        DiskMap::open_with(directory.as_ref(), self)
+    }
+}
+
+impl Default for DiskMapOptions {
+    fn default() -> Self {
+        DiskMapOptions::new()
+    }
+}
+
+/// A `BTreeMap` backed by a write-ahead log and snapshots in a directory.
+pub struct DiskMap<K, V> {
+    map: BTreeMap<K, V>,
+    directory: PathBuf,
+    log: File,
+    log_len: u64,
+    changes_since_snapshot: usize,
+    options: DiskMapOptions,
+    recovery: Recovery,
+}
+
+impl<K, V> DiskMap<K, V>
+where
+    K: Encode + Decode + Ord,
+    V: Encode + Decode,
+{
+    // Opens the map in `directory`, creating it if needed, with the default options
+    pub fn open(directory: impl AsRef<Path>) -> Result<DiskMap<K, V>, DiskMapError> {
+        DiskMap::open_with(directory.as_ref(), DiskMapOptions::new())
+    }
+
+    fn open_with(directory: &Path, options: DiskMapOptions) -> Result<DiskMap<K, V>, DiskMapError> {
+        fs::create_dir_all(directory)?;
+        let mut recovery = Recovery::default();
+
+        let mut map = match fs::read(directory.join(SNAPSHOT_FILE)) {
+            Ok(bytes) => binary::from_bytes::<BTreeMap<K, V>>(&bytes).map_err(DiskMapError::CorruptSnapshot)?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(error) => return Err(error.into()),
+        };
+        recovery.snapshot_entries = map.len();
+
+        let log_path = directory.join(LOG_FILE);
+        let bytes = match fs::read(&log_path) {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(error) => return Err(error.into()),
+        };
+
+        // A log shorter than its header was being created when the process stopped
+        let valid_len = if (bytes.len() as u64) < LOG_HEADER_LEN {
+            recovery.bytes_discarded = bytes.len() as u64;
+            fs::write(&log_path, [&LOG_MAGIC[..], &[LOG_VERSION]].concat())?;
+            LOG_HEADER_LEN
+        } else {
+            if &bytes[..4] != LOG_MAGIC || bytes[4] != LOG_VERSION {
+                return Err(DiskMapError::NotALog);
+            }
+            let (valid_len, replayed) = replay(&bytes[LOG_HEADER_LEN as usize..], &mut map);
+            let valid_len = LOG_HEADER_LEN + valid_len as u64;
+            recovery.records_replayed = replayed;
+            recovery.bytes_discarded = bytes.len() as u64 - valid_len;
+            valid_len
+        };
+
+        // Appends go after the last complete record, whatever was behind it is cut off
+        let log = OpenOptions::new().append(true).open(&log_path)?;
+        if recovery.bytes_discarded > 0 {
+            log.set_len(valid_len)?;
+        }
+        log.sync_all()?;
+        Ok(DiskMap {
+            map,
+            directory: directory.to_path_buf(),
+            log,
+            log_len: valid_len,
+            changes_since_snapshot: recovery.records_replayed,
+            options,
+            recovery,
+        })
+    }
+
+    pub fn recovery(&self) -> Recovery {
+        self.recovery
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    // The size of the log file in bytes
+    pub fn log_len(&self) -> u64 {
+        self.log_len
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.map.get(key)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.map.contains_key(key)
+    }
+
+    pub fn iter(&self) -> std::collections::btree_map::Iter<'_, K, V> {
+        self.map.iter()
+    }
+
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> std::collections::btree_map::Range<'_, K, V> {
+        self.map.range(range)
+    }
+
+    // The map as it is in memory, for everything that only reads
+    pub fn as_map(&self) -> &BTreeMap<K, V> {
+        &self.map
+    }
+
+    // The change is in the log before the map is updated, so an error leaves both unchanged. Once
+    // it is logged the change stands, even if the automatic snapshot after it fails.
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>, DiskMapError> {
+        let mut payload = vec![INSERT];
+        key.encode(&mut payload);
+        value.encode(&mut payload);
+        self.append(&payload)?;
+        let previous = self.map.insert(key, value);
+        self.after_change();
+        Ok(previous)
+    }
+
+    pub fn remove(&mut self, key: &K) -> Result<Option<V>, DiskMapError> {
+        // Removing a key that is not there changes nothing and is not logged
+        if !self.map.contains_key(key) {
+            return Ok(None);
+        }
+        let mut payload = vec![REMOVE];
+        key.encode(&mut payload);
+        self.append(&payload)?;
+        let previous = self.map.remove(key);
+        self.after_change();
+        Ok(previous)
+    }
+
+    fn append(&mut self, payload: &[u8]) -> Result<(), DiskMapError> {
+        let mut record = Vec::with_capacity(payload.len() + 9);
+        binary::write_varint(&mut record, payload.len() as u64);
+        record.extend_from_slice(&crc32(payload).to_le_bytes());
+        record.extend_from_slice(payload);
+        // One `write_all` per record, so a crash tears at most the last record
+        let mut written = self.log.write_all(&record);
+        if written.is_ok() && self.options.sync_writes {
+            written = self.log.sync_data();
+        }
+        if let Err(error) = written {
+            // Part of the record may have been written. Left there, it would end the replay on
+            // the next open and take every later change with it.
+            let _ = self.log.set_len(self.log_len);
+            return Err(error.into());
+        }
+        self.log_len += record.len() as u64;
+        Ok(())
+    }
+
+    fn after_change(&mut self) {
+        self.changes_since_snapshot += 1;
+        if let Some(every) = self.options.snapshot_every {
+            if self.changes_since_snapshot >= every {
+                // The change is durable in the log already, and a failed snapshot leaves the
+                // counter as it is, so the next change tries again
+                let _ = self.snapshot();
+            }
+        }
+    }
+
+    // Writes the whole map to the snapshot file and empties the log
+    pub fn snapshot(&mut self) -> Result<(), DiskMapError> {
+        let temp_path = self.directory.join(SNAPSHOT_TEMP_FILE);
+        let mut temp = File::create(&temp_path)?;
+        temp.write_all(&binary::to_bytes(&self.map))?;
+        temp.sync_all()?;
+        fs::rename(&temp_path, self.directory.join(SNAPSHOT_FILE))?;
+        // Make the rename itself durable. Directories can not be opened like this on Windows,
+        // where the rename is durable already.
+        if let Ok(directory) = File::open(&self.directory) {
+            let _ = directory.sync_all();
+        }
+
+        self.log.set_len(LOG_HEADER_LEN)?;
+        self.log.sync_all()?;
+        self.log_len = LOG_HEADER_LEN;
+        self.changes_since_snapshot = 0;
+        Ok(())
+    }
+
+    // Waits until every change so far is on the disk
+    pub fn sync(&mut self) -> Result<(), DiskMapError> {
+        self.log.sync_data()?;
+        Ok(())
+    }
+}
+
+// Applies the complete records at the start of `log` to `map`. Returns how many bytes they take
+// up and how many there were.
+fn replay<K: Decode + Ord, V: Decode>(log: &[u8], map: &mut BTreeMap<K, V>) -> (usize, usize) {
+    let mut input = Decoder::new(log);
+    let mut valid_len = 0;
+    let mut records = 0;
+    while input.remaining() > 0 {
+        match read_record(&mut input) {
+            Some(Change::Insert(key, value)) => {
+                map.insert(key, value);
+            }
+            Some(Change::Remove(key)) => {
+                map.remove(&key);
+            }
+            // Everything from here on is a torn or damaged record
+            None => break,
+        }
+        valid_len = input.offset();
+        records += 1;
+    }
+    (valid_len, records)
+}
+
+enum Change<K, V> {
+    Insert(K, V),
+    Remove(K),
+}
+
+fn read_record<K: Decode, V: Decode>(input: &mut Decoder<'_>) -> Option<Change<K, V>> {
+    let length = input.read_varint().ok()?;
+    let stored = u32::from_le_bytes(input.read_bytes(4).ok()?.try_into().unwrap());
+    if length > input.remaining() as u64 {
+        return None;
+    }
+    let payload = input.read_bytes(length as usize).ok()?;
+    if crc32(payload) != stored {
+        return None;
+    }
+
+    let mut payload = Decoder::new(payload);
+    let change = match payload.read_byte().ok()? {
+        INSERT => Change::Insert(K::decode(&mut payload).ok()?, V::decode(&mut payload).ok()?),
+        REMOVE => Change::Remove(K::decode(&mut payload).ok()?),
+        _ => return None,
+    };
+    payload.finish().ok()?;
+    Some(change)
+}
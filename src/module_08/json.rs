@@ -0,0 +1,901 @@
+// JSON (RFC 8259) values, a streaming parser and conversions for the std collections.
+//
+// `Json` is the parsed form of a document. `ToJson` turns a Rust value into one and `FromJson`
+// reads it back:
+//
+// - sequences and sets (`Vec`, `VecDeque`, `LinkedList`, `HashSet`, `BTreeSet`, arrays and
+//   tuples) become JSON arrays,
+// - maps (`HashMap`, `BTreeMap`) become objects, with the keys written with `to_string` and read
+//   with `FromStr`, so maps keyed by numbers work too,
+// - `Coffee` becomes `{"count": 10, "id": 1000, "name": "Latte"}`.
+//
+// Objects are kept in a `BTreeMap`, so the keys are written in sorted order and the same value
+// always gives the same text. Numbers are `f64` like in JavaScript: integers are exact up to 2^53.
+//
+// `Parser` reads from a `BufRead` one byte at a time, so it does not need the whole document in a
+// string, and it can read several documents from one stream (one JSON value per line is a common
+// log format). Every error says on which line and column it happened.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, LinkedList, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::hash::Hash;
+use std::io::{self, BufRead};
+use std::str::FromStr;
+
+use crate::coffee::Coffee;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(BTreeMap<String, Json>),
+}
+
+impl Json {
+    // The name used in error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Json::Null => "null",
+            Json::Bool(_) => "a boolean",
+            Json::Number(_) => "a number",
+            Json::String(_) => "a string",
+            Json::Array(_) => "an array",
+            Json::Object(_) => "an object",
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.get(key),
+            _ => None,
+        }
+    }
+
+    // Two spaces per level, one array element or object member per line
+    pub fn to_string_pretty(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, Some(0));
+        out
+    }
+
+    // `indent` is the current depth when pretty printing, `None` for compact output
+    fn write(&self, out: &mut String, indent: Option<usize>) {
+        let newline = |out: &mut String, depth: usize| {
+            out.push('\n');
+            out.extend(std::iter::repeat_n("  ", depth));
+        };
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(value) => out.push_str(if *value { "true" } else { "false" }),
+            // JSON has no NaN or infinity, JavaScript writes them as null too
+            Json::Number(value) if !value.is_finite() => out.push_str("null"),
+            Json::Number(value) => out.push_str(&value.to_string()),
+            Json::String(value) => write_string(out, value),
+            Json::Array(elements) if elements.is_empty() => out.push_str("[]"),
+            Json::Array(elements) => {
+                out.push('[');
+                for (index, element) in elements.iter().enumerate() {
+                    if index > 0 {
+                        out.push(',');
+                    }
+                    if let Some(depth) = indent {
+                        newline(out, depth + 1);
+                    }
+                    element.write(out, indent.map(|depth| depth + 1));
+                }
+                if let Some(depth) = indent {
+                    newline(out, depth);
+                }
+                out.push(']');
+            }
+            Json::Object(members) if members.is_empty() => out.push_str("{}"),
+            Json::Object(members) => {
+                out.push('{');
+                for (index, (key, value)) in members.iter().enumerate() {
+                    if index > 0 {
+                        out.push(',');
+                    }
+                    if let Some(depth) = indent {
+                        newline(out, depth + 1);
+                    }
+                    write_string(out, key);
+                    out.push_str(if indent.is_some() { ": " } else { ":" });
+                    value.write(out, indent.map(|depth| depth + 1));
+                }
+                if let Some(depth) = indent {
+                    newline(out, depth);
+                }
+                out.push('}');
+            }
+        }
+    }
+}
+
+fn write_string(out: &mut String, value: &str) {
+    out.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            ch if ch < ' ' => out.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+}
+
+// Compact, with no white space
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        self.write(&mut out, None);
+        f.write_str(&out)
+    }
+}
+
+#[derive(Debug)]
+pub enum JsonError {
+    Io(io::Error),
+    // The text is not valid JSON
+    Syntax { line: usize, column: usize, message: String },
+    // Valid JSON, but not the shape `FromJson` expected. `path` is where in the document, like
+    // `$[2].name`.
+    Type { path: String, expected: &'static str, found: &'static str },
+    MissingField { path: String, field: &'static str },
+    InvalidValue { path: String, message: String },
+}
+
+impl JsonError {
+    fn type_error(expected: &'static str, found: &Json) -> JsonError {
+        JsonError::Type { path: String::from("$"), expected, found: found.type_name() }
+    }
+
+    fn invalid(message: impl Into<String>) -> JsonError {
+        JsonError::InvalidValue { path: String::from("$"), message: message.into() }
+    }
+
+    // The same error for a value that is `segment` inside its parent, e.g. `[2]` or `.name`
+    fn inside(mut self, segment: &str) -> JsonError {
+        match &mut self {
+            JsonError::Type { path, .. } | JsonError::MissingField { path, .. } | JsonError::InvalidValue { path, .. } => {
+                path.insert_str(1, segment);
+            }
+            JsonError::Io(_) | JsonError::Syntax { .. } => {}
+        }
+        self
+    }
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonError::Io(error) => write!(f, "I/O error: {}", error),
+            JsonError::Syntax { line, column, message } => write!(f, "line {}, column {}: {}", line, column, message),
+            JsonError::Type { path, expected, found } => write!(f, "{}: expected {}, found {}", path, expected, found),
+            JsonError::MissingField { path, field } => write!(f, "{}: missing field `{}`", path, field),
+            JsonError::InvalidValue { path, message } => write!(f, "{}: {}", path, message),
+        }
+    }
+}
+
+impl Error for JsonError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            JsonError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for JsonError {
+    fn from(error: io::Error) -> Self {
+        JsonError::Io(error)
+    }
+}
+
+// Deeper documents are rejected instead of overflowing the stack
+const MAX_DEPTH: usize = 128;
+
+/// Reads JSON values from a byte stream.
+pub struct Parser<R> {
+    input: R,
+    // Of the next byte, both counted from 1. Columns count chars, not bytes.
+    line: usize,
+    column: usize,
+    depth: usize,
+}
+
+impl<R: BufRead> Parser<R> {
+    pub fn new(input: R) -> Parser<R> {
+        Parser { input, line: 1, column: 1, depth: 0 }
+    }
+
+    fn peek(&mut self) -> Result<Option<u8>, JsonError> {
+        Ok(self.input.fill_buf()?.first().copied())
+    }
+
+    fn bump(&mut self) -> Result<Option<u8>, JsonError> {
+        let byte = self.peek()?;
+        if let Some(byte) = byte {
+            self.input.consume(1);
+            if byte == b'\n' {
+                self.line += 1;
+                self.column = 1;
+            } else if byte & 0xc0 != 0x80 {
+                // Continuation bytes of a multi-byte char do not start a new column
+                self.column += 1;
+            }
+        }
+        Ok(byte)
+    }
+
+    fn error_at<T>(&self, line: usize, column: usize, message: impl Into<String>) -> Result<T, JsonError> {
+        Err(JsonError::Syntax { line, column, message: message.into() })
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, JsonError> {
+        self.error_at(self.line, self.column, message)
+    }
+
+    // Describes the next byte for an error message
+    fn unexpected<T>(&mut self, expected: &str) -> Result<T, JsonError> {
+        match self.peek()? {
+            None => self.error(format!("expected {}, found the end of the input", expected)),
+            Some(byte) if byte.is_ascii_graphic() => self.error(format!("expected {}, found `{}`", expected, byte as char)),
+            Some(byte) => self.error(format!("expected {}, found byte 0x{:02x}", expected, byte)),
+        }
+    }
+
+    fn skip_white_space(&mut self) -> Result<(), JsonError> {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek()? {
+            self.bump()?;
+        }
+        Ok(())
+    }
+
+    fn expect(&mut self, byte: u8, expected: &str) -> Result<(), JsonError> {
+        if self.peek()? == Some(byte) {
+            self.bump()?;
+            Ok(())
+        } else {
+            self.unexpected(expected)
+        }
+    }
+
+    // The next value in the stream, or `None` if only white space is left
+    pub fn next_value(&mut self) -> Result<Option<Json>, JsonError> {
+        self.skip_white_space()?;
+        if self.peek()?.is_none() {
+            return Ok(None);
+        }
+        self.parse_value().map(Some)
+    }
+
+    // A single value that makes up the whole input
+    pub fn parse_document(&mut self) -> Result<Json, JsonError> {
+        match self.next_value()? {
+            None => self.unexpected("a value"),
+            Some(value) => {
+                self.skip_white_space()?;
+                if self.peek()?.is_some() {
+                    return self.unexpected("the end of the input");
+                }
+                Ok(value)
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Json, JsonError> {
+        match self.peek()? {
+            Some(b'{') => self.nested(Parser::parse_object),
+            Some(b'[') => self.nested(Parser::parse_array),
+            Some(b'"') => self.parse_string().map(Json::String),
+            Some(b'-' | b'0'..=b'9') => self.parse_number(),
+            Some(b't') => self.parse_literal("true", Json::Bool(true)),
+            Some(b'f') => self.parse_literal("false", Json::Bool(false)),
+            Some(b'n') => self.parse_literal("null", Json::Null),
+            _ => self.unexpected("a value"),
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Parser<R>) -> Result<Json, JsonError>) -> Result<Json, JsonError> {
+        if self.depth == MAX_DEPTH {
+            return self.error(format!("more than {} nested arrays and objects", MAX_DEPTH));
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn parse_literal(&mut self, literal: &str, value: Json) -> Result<Json, JsonError> {
+        let (line, column) = (self.line, self.column);
+        for expected in literal.bytes() {
+            if self.peek()? != Some(expected) {
+                return self.error_at(line, column, format!("expected `{}`", literal));
+            }
+            self.bump()?;
+        }
+        Ok(value)
+    }
+
+    fn parse_digits(&mut self, text: &mut String) -> Result<(), JsonError> {
+        if !matches!(self.peek()?, Some(b'0'..=b'9')) {
+            return self.unexpected("a digit");
+        }
+        while let Some(digit @ b'0'..=b'9') = self.peek()? {
+            text.push(digit as char);
+            self.bump()?;
+        }
+        Ok(())
+    }
+
+    fn parse_number(&mut self) -> Result<Json, JsonError> {
+        let (line, column) = (self.line, self.column);
+        let mut text = String::new();
+        if self.peek()? == Some(b'-') {
+            text.push('-');
+            self.bump()?;
+        }
+        // No leading zeros: `0` and `0.5` but not `05`
+        if self.peek()? == Some(b'0') {
+            text.push('0');
+            self.bump()?;
+        } else {
+            self.parse_digits(&mut text)?;
+        }
+        if self.peek()? == Some(b'.') {
+            text.push('.');
+            self.bump()?;
+            self.parse_digits(&mut text)?;
+        }
+        if let Some(b'e' | b'E') = self.peek()? {
+            text.push('e');
+            self.bump()?;
+            if let Some(sign @ (b'+' | b'-')) = self.peek()? {
+                text.push(sign as char);
+                self.bump()?;
+            }
+            self.parse_digits(&mut text)?;
+        }
+        match text.parse::<f64>() {
+            Ok(value) if value.is_finite() => Ok(Json::Number(value)),
+            _ => self.error_at(line, column, format!("number {} is out of range", text)),
+        }
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, JsonError> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let digit = match self.peek()? {
+                Some(byte) => (byte as char).to_digit(16),
+                None => None,
+            };
+            match digit {
+                Some(digit) => value = value * 16 + digit,
+                None => return self.unexpected("a hex digit"),
+            }
+            self.bump()?;
+        }
+        Ok(value)
+    }
+
+    fn parse_string(&mut self) -> Result<String, JsonError> {
+        let (line, column) = (self.line, self.column);
+        self.bump()?;
+        let mut bytes = Vec::new();
+        loop {
+            let (escape_line, escape_column) = (self.line, self.column);
+            match self.bump()? {
+                None => return self.error_at(line, column, "string is never closed"),
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    let ch = match self.bump()? {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => self.parse_unicode_escape(escape_line, escape_column)?,
+                        _ => return self.error_at(escape_line, escape_column, "invalid escape sequence"),
+                    };
+                    bytes.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                Some(byte) if byte < 0x20 => {
+                    return self.error_at(escape_line, escape_column, "control characters must be escaped in strings");
+                }
+                Some(byte) => bytes.push(byte),
+            }
+        }
+        match String::from_utf8(bytes) {
+            Ok(text) => Ok(text),
+            Err(_) => self.error_at(line, column, "string is not valid UTF-8"),
+        }
+    }
+
+    // After `\u`. Chars outside the Basic Multilingual Plane are written as two escapes, a
+    // surrogate pair: "☕" is `\u2615` but "🍩" is `\ud83c\udf69`.
+    fn parse_unicode_escape(&mut self, line: usize, column: usize) -> Result<char, JsonError> {
+        let first = self.parse_hex4()?;
+        let code = match first {
+            0xd800..=0xdbff => {
+                if self.bump()? != Some(b'\\') || self.bump()? != Some(b'u') {
+                    return self.error_at(line, column, "unpaired surrogate in \\u escape");
+                }
+                let second = self.parse_hex4()?;
+                if !(0xdc00..=0xdfff).contains(&second) {
+                    return self.error_at(line, column, "unpaired surrogate in \\u escape");
+                }
+                0x10000 + ((first - 0xd800) << 10) + (second - 0xdc00)
+            }
+            0xdc00..=0xdfff => return self.error_at(line, column, "unpaired surrogate in \\u escape"),
+            code => code,
+        };
+        match char::from_u32(code) {
+            Some(ch) => Ok(ch),
+            None => self.error_at(line, column, "invalid \\u escape"),
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Json, JsonError> {
+        self.bump()?;
+        let mut elements = Vec::new();
+        self.skip_white_space()?;
+        if self.peek()? == Some(b']') {
+            self.bump()?;
+            return Ok(Json::Array(elements));
+        }
+        loop {
+            self.skip_white_space()?;
+            elements.push(self.parse_value()?);
+            self.skip_white_space()?;
+            match self.peek()? {
+                Some(b',') => self.bump()?,
+                Some(b']') => break,
+                _ => return self.unexpected("`,` or `]`"),
+            };
+        }
+        self.bump()?;
+        Ok(Json::Array(elements))
+    }
+
+    fn parse_object(&mut self) -> Result<Json, JsonError> {
+        self.bump()?;
+        let mut members = BTreeMap::new();
+        self.skip_white_space()?;
+        if self.peek()? == Some(b'}') {
+            self.bump()?;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.skip_white_space()?;
+            let (line, column) = (self.line, self.column);
+            if self.peek()? != Some(b'"') {
+                return self.unexpected("a string key");
+            }
+            let key = self.parse_string()?;
+            self.skip_white_space()?;
+            self.expect(b':', "`:`")?;
+            self.skip_white_space()?;
+            let value = self.parse_value()?;
+            // The RFC leaves duplicate keys to the parser, silently keeping one would lose data
+            if members.insert(key.clone(), value).is_some() {
+                return self.error_at(line, column, format!("duplicate key {:?}", key));
+            }
+            self.skip_white_space()?;
+            match self.peek()? {
+                Some(b',') => self.bump()?,
+                Some(b'}') => break,
+                _ => return self.unexpected("`,` or `}`"),
+            };
+        }
+        self.bump()?;
+        Ok(Json::Object(members))
+    }
+}
+
+impl FromStr for Json {
+    type Err = JsonError;
+
+    fn from_str(text: &str) -> Result<Json, JsonError> {
+        Parser::new(text.as_bytes()).parse_document()
+    }
+}
+
+pub fn from_reader<T: FromJson, R: BufRead>(input: R) -> Result<T, JsonError> {
+    T::from_json(&Parser::new(input).parse_document()?)
+}
+
+pub fn from_str<T: FromJson>(text: &str) -> Result<T, JsonError> {
+    from_reader(text.as_bytes())
+}
+
+pub fn to_string<T: ToJson + ?Sized>(value: &T) -> String {
+    value.to_json().to_string()
+}
+
+pub fn to_string_pretty<T: ToJson + ?Sized>(value: &T) -> String {
+    value.to_json().to_string_pretty()
+}
+
+pub trait ToJson {
+    fn to_json(&self) -> Json;
+}
+
+pub trait FromJson: Sized {
+    fn from_json(json: &Json) -> Result<Self, JsonError>;
+}
+
+impl ToJson for Json {
+    fn to_json(&self) -> Json {
+        self.clone()
+    }
+}
+
+impl FromJson for Json {
+    fn from_json(json: &Json) -> Result<Json, JsonError> {
+        Ok(json.clone())
+    }
+}
+
+impl ToJson for bool {
+    fn to_json(&self) -> Json {
+        Json::Bool(*self)
+    }
+}
+
+impl FromJson for bool {
+    fn from_json(json: &Json) -> Result<bool, JsonError> {
+        match json {
+            Json::Bool(value) => Ok(*value),
+            _ => Err(JsonError::type_error("a boolean", json)),
+        }
+    }
+}
+
+// Integers are read back only if the number is whole and fits in the type
+macro_rules! impl_json_for_integers {
+    ($($integer:ty),*) => {$(
+        impl ToJson for $integer {
+            fn to_json(&self) -> Json {
+                Json::Number(*self as f64)
+            }
+        }
+
+        impl FromJson for $integer {
+            fn from_json(json: &Json) -> Result<$integer, JsonError> {
+                match *json {
+                    // Through i128, which holds every whole number below 2^127 exactly. Comparing
+                    // with `MAX as f64` would not do, that rounds up to 2^63 or 2^64 for the
+                    // 64-bit types and lets the next number past the end through.
+                    Json::Number(value) => {
+                        let whole = value.fract() == 0.0 && value.abs() < 2f64.powi(127);
+                        whole
+                            .then(|| <$integer>::try_from(value as i128).ok())
+                            .flatten()
+                            .ok_or_else(|| JsonError::invalid(format!("{} is not a valid {}", value, stringify!($integer))))
+                    }
+                    _ => Err(JsonError::type_error("a number", json)),
+                }
+            }
+        }
+    )*};
+}
+
+impl_json_for_integers!(i8, i16, i32, i64, u8, u16, u32, u64, usize);
+
+impl ToJson for f64 {
+    fn to_json(&self) -> Json {
+        Json::Number(*self)
+    }
+}
+
+impl FromJson for f64 {
+    fn from_json(json: &Json) -> Result<f64, JsonError> {
+        match json {
+            Json::Number(value) => Ok(*value),
+            _ => Err(JsonError::type_error("a number", json)),
+        }
+    }
+}
+
+impl ToJson for f32 {
+    fn to_json(&self) -> Json {
+        Json::Number(*self as f64)
+    }
+}
+
+impl FromJson for f32 {
+    fn from_json(json: &Json) -> Result<f32, JsonError> {
+        f64::from_json(json).map(|value| value as f32)
+    }
+}
+
+impl ToJson for str {
+    fn to_json(&self) -> Json {
+        Json::String(self.to_string())
+    }
+}
+
+impl ToJson for String {
+    fn to_json(&self) -> Json {
+        Json::String(self.clone())
+    }
+}
+
+impl FromJson for String {
+    fn from_json(json: &Json) -> Result<String, JsonError> {
+        match json {
+            Json::String(value) => Ok(value.clone()),
+            _ => Err(JsonError::type_error("a string", json)),
+        }
+    }
+}
+
+impl ToJson for char {
+    fn to_json(&self) -> Json {
+        Json::String(self.to_string())
+    }
+}
+
+impl FromJson for char {
+    fn from_json(json: &Json) -> Result<char, JsonError> {
+        let text = String::from_json(json)?;
+        let mut chars = text.chars();
+        match (chars.next(), chars.next()) {
+            (Some(ch), None) => Ok(ch),
+            _ => Err(JsonError::invalid(format!("{:?} is not a single char", text))),
+        }
+    }
+}
+
+impl<T: ToJson + ?Sized> ToJson for &T {
+    fn to_json(&self) -> Json {
+        (**self).to_json()
+    }
+}
+
+impl<T: ToJson + ?Sized> ToJson for Box<T> {
+    fn to_json(&self) -> Json {
+        (**self).to_json()
+    }
+}
+
+// `None` is `null`
+impl<T: ToJson> ToJson for Option<T> {
+    fn to_json(&self) -> Json {
+        match self {
+            Some(value) => value.to_json(),
+            None => Json::Null,
+        }
+    }
+}
+
+impl<T: FromJson> FromJson for Option<T> {
+    fn from_json(json: &Json) -> Result<Option<T>, JsonError> {
+        match json {
+            Json::Null => Ok(None),
+            _ => T::from_json(json).map(Some),
+        }
+    }
+}
+
+fn array_to_json<'a, T: ToJson + 'a>(elements: impl Iterator<Item = &'a T>) -> Json {
+    Json::Array(elements.map(ToJson::to_json).collect())
+}
+
+// Every element of an array, converted and collected into `C`
+fn array_from_json<T: FromJson, C: FromIterator<T>>(json: &Json) -> Result<C, JsonError> {
+    match json {
+        Json::Array(elements) => elements
+            .iter()
+            .enumerate()
+            .map(|(index, element)| T::from_json(element).map_err(|error| error.inside(&format!("[{}]", index))))
+            .collect(),
+        _ => Err(JsonError::type_error("an array", json)),
+    }
+}
+
+impl<T: ToJson> ToJson for [T] {
+    fn to_json(&self) -> Json {
+        array_to_json(self.iter())
+    }
+}
+
+impl<T: ToJson, const N: usize> ToJson for [T; N] {
+    fn to_json(&self) -> Json {
+        array_to_json(self.iter())
+    }
+}
+
+impl<T: FromJson, const N: usize> FromJson for [T; N] {
+    fn from_json(json: &Json) -> Result<[T; N], JsonError> {
+        let elements: Vec<T> = array_from_json(json)?;
+        let found = elements.len();
+        elements
+            .try_into()
+            .map_err(|_| JsonError::invalid(format!("expected an array of {} elements, found {}", N, found)))
+    }
+}
+
+impl<T: ToJson> ToJson for Vec<T> {
+    fn to_json(&self) -> Json {
+        array_to_json(self.iter())
+    }
+}
+
+impl<T: FromJson> FromJson for Vec<T> {
+    fn from_json(json: &Json) -> Result<Vec<T>, JsonError> {
+        array_from_json(json)
+    }
+}
+
+impl<T: ToJson> ToJson for VecDeque<T> {
+    fn to_json(&self) -> Json {
+        array_to_json(self.iter())
+    }
+}
+
+impl<T: FromJson> FromJson for VecDeque<T> {
+    fn from_json(json: &Json) -> Result<VecDeque<T>, JsonError> {
+        array_from_json(json)
+    }
+}
+
+impl<T: ToJson> ToJson for LinkedList<T> {
+    fn to_json(&self) -> Json {
+        array_to_json(self.iter())
+    }
+}
+
+impl<T: FromJson> FromJson for LinkedList<T> {
+    fn from_json(json: &Json) -> Result<LinkedList<T>, JsonError> {
+        array_from_json(json)
+    }
+}
+
+// In the set's iteration order, which for a `HashSet` changes from run to run
+impl<T: ToJson> ToJson for HashSet<T> {
+    fn to_json(&self) -> Json {
+        array_to_json(self.iter())
+    }
+}
+
+impl<T: FromJson + Hash + Eq> FromJson for HashSet<T> {
+    fn from_json(json: &Json) -> Result<HashSet<T>, JsonError> {
+        array_from_json(json)
+    }
+}
+
+impl<T: ToJson> ToJson for BTreeSet<T> {
+    fn to_json(&self) -> Json {
+        array_to_json(self.iter())
+    }
+}
+
+impl<T: FromJson + Ord> FromJson for BTreeSet<T> {
+    fn from_json(json: &Json) -> Result<BTreeSet<T>, JsonError> {
+        array_from_json(json)
+    }
+}
+
+fn object_to_json<'a, K: ToString + 'a, V: ToJson + 'a>(members: impl Iterator<Item = (&'a K, &'a V)>) -> Json {
+    Json::Object(members.map(|(key, value)| (key.to_string(), value.to_json())).collect())
+}
+
+fn object_from_json<K: FromStr, V: FromJson, C: FromIterator<(K, V)>>(json: &Json) -> Result<C, JsonError> {
+    match json {
+        Json::Object(members) => members
+            .iter()
+            .map(|(key, value)| {
+                let parsed_key = key
+                    .parse::<K>()
+                    .map_err(|_| JsonError::invalid(format!("{:?} is not a valid key", key)));
+                let value = V::from_json(value);
+                match (parsed_key, value) {
+                    (Ok(parsed_key), Ok(value)) => Ok((parsed_key, value)),
+                    (Err(error), _) | (_, Err(error)) => Err(error.inside(&format!("[{:?}]", key))),
+                }
+            })
+            .collect(),
+        _ => Err(JsonError::type_error("an object", json)),
+    }
+}
+
+impl<K: ToString, V: ToJson> ToJson for HashMap<K, V> {
+    fn to_json(&self) -> Json {
+        object_to_json(self.iter())
+    }
+}
+
+impl<K: FromStr + Hash + Eq, V: FromJson> FromJson for HashMap<K, V> {
+    fn from_json(json: &Json) -> Result<HashMap<K, V>, JsonError> {
+        object_from_json(json)
+    }
+}
+
+impl<K: ToString, V: ToJson> ToJson for BTreeMap<K, V> {
+    fn to_json(&self) -> Json {
+        object_to_json(self.iter())
+    }
+}
+
+impl<K: FromStr + Ord, V: FromJson> FromJson for BTreeMap<K, V> {
+    fn from_json(json: &Json) -> Result<BTreeMap<K, V>, JsonError> {
+        object_from_json(json)
+    }
+}
+
+// Tuples are short arrays: `(1, "Latte")` is `[1, "Latte"]`
+macro_rules! impl_json_for_tuples {
+    ($(($($name:ident $index:tt),+) with $length:expr;)*) => {$(
+        impl<$($name: ToJson),+> ToJson for ($($name,)+) {
+            fn to_json(&self) -> Json {
+                Json::Array(vec![$(self.$index.to_json()),+])
+            }
+        }
+
+        impl<$($name: FromJson),+> FromJson for ($($name,)+) {
+            fn from_json(json: &Json) -> Result<($($name,)+), JsonError> {
+                match json {
+                    Json::Array(elements) if elements.len() == $length => Ok(($(
+                        $name::from_json(&elements[$index])
+                            .map_err(|error| error.inside(&format!("[{}]", $index)))?,
+                    )+)),
+                    Json::Array(elements) => Err(JsonError::invalid(format!(
+                        "expected an array of {} elements, found {}",
+                        $length,
+                        elements.len()
+                    ))),
+                    _ => Err(JsonError::type_error("an array", json)),
+                }
+            }
+        }
+    )*};
+}
+
+impl_json_for_tuples! {
+    (A 0) with 1;
+    (A 0, B 1) with 2;
+    (A 0, B 1, C 2) with 3;
+    (A 0, B 1, C 2, D 3) with 4;
+}
+
+// A field of an object, converted to `T`
+pub fn field<T: FromJson>(json: &Json, name: &'static str) -> Result<T, JsonError> {
+    match json {
+        Json::Object(members) => match members.get(name) {
+            Some(value) => T::from_json(value).map_err(|error| error.inside(&format!(".{}", name))),
+            None => Err(JsonError::MissingField { path: String::from("$"), field: name }),
+        },
+        _ => Err(JsonError::type_error("an object", json)),
+    }
+}
+
+impl ToJson for Coffee {
+    fn to_json(&self) -> Json {
+        Json::Object(BTreeMap::from([
+            (String::from("id"), self.id.to_json()),
+            (String::from("name"), self.name.to_json()),
+            (String::from("count"), self.count.to_json()),
+        ]))
+    }
+}
+
+impl FromJson for Coffee {
+    fn from_json(json: &Json) -> Result<Coffee, JsonError> {
+        Ok(Coffee { id: field(json, "id")?, name: field(json, "name")?, count: field(json, "count")? })
+    }
+}
@@ -0,0 +1,542 @@
+// A sorted string table: an immutable file of sorted key-value pairs built for fast reads.
+//
+// An SSTable is written once from a sorted map and then only read. The file is split into data
+// blocks of a few kilobytes, followed by an index with the last key of every block, an optional
+// bloom filter of all the keys, and a fixed size footer that says where those are:
+//
+//     data block 0 | data block 1 | ... | index block | bloom filter block | footer (52 bytes)
+//
+// Opening a table reads only the footer, the index and the bloom filter. A lookup finds the one
+// block that can hold the key with a binary search of the index and reads just that block, and a
+// range scan reads the blocks it covers one after another. The bloom filter answers most lookups
+// of missing keys without reading any block at all.
+//
+// Databases usually memory-map these files. Mapping needs platform specific system calls that the
+// standard library does not offer, so the reader asks for the bytes of one block at a time
+// instead, and leaves caching to the operating system's page cache, which is what a memory map
+// would use too.
+//
+// Keys in a block share prefixes with the key before them, which sorted keys tend to do
+// ("coffee:000017", "coffee:000018"), so only the differing suffix is stored:
+//
+//     shared prefix length | suffix length | value length | suffix | value   (lengths are varints)
+//
+// Every block ends with a CRC-32 of its contents, so damage is reported instead of returning
+// wrong data. The footer is five little endian u64s (index offset and length, bloom filter offset
+// and length, entry count), their CRC-32, the format version and the magic bytes "RSST".
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+
+use super::binary::{self, crc32, DecodeError, Decoder};
+
+const MAGIC: &[u8; 4] = b"RSST";
+const VERSION: u32 = 1;
+const FOOTER_LEN: u64 = 52;
+
+#[derive(Debug)]
+pub enum TableError {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u32),
+    // A block whose checksum or contents are wrong, by the offset it starts at
+    Corrupt { offset: u64, reason: String },
+}
+
+impl fmt::Display for TableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TableError::Io(error) => write!(f, "I/O error: {}", error),
+            TableError::BadMagic => write!(f, "not an SSTable file"),
+            TableError::UnsupportedVersion(version) => write!(f, "SSTable version {} is not supported", version),
+            TableError::Corrupt { offset, reason } => write!(f, "block at byte {} is corrupt: {}", offset, reason),
+        }
+    }
+}
+
+impl Error for TableError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TableError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TableError {
+    fn from(error: io::Error) -> Self {
+        TableError::Io(error)
+    }
+}
+
+fn corrupt(offset: u64) -> impl Fn(DecodeError) -> TableError {
+    move |error| TableError::Corrupt { offset, reason: error.to_string() }
+}
+
+/// How a table is written.
+#[derive(Clone, Copy, Debug)]
+pub struct TableOptions {
+    block_size: usize,
+    bloom_bits_per_key: Option<usize>,
+}
+
+impl TableOptions {
+    pub fn new() -> TableOptions {
+        TableOptions { block_size: 4096, bloom_bits_per_key: Some(10) }
+    }
+
+    // A block is closed once it holds this many bytes, so it can be a little bigger
+    pub fn block_size(mut self, bytes: usize) -> TableOptions {
+        self.block_size = bytes.max(1);
+        self
+    }
+
+    // Ten bits per key give about 1% false positives. `None` writes no filter.
+    pub fn bloom_bits_per_key(mut self, bits: Option<usize>) -> TableOptions {
+        self.bloom_bits_per_key = bits;
+        self
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>, map: &BTreeMap<String, Vec<u8>>) -> io::Result<TableStats> {
+        let mut builder = TableBuilder::new(File::create(path)?, *self);
+        for (key, value) in map {
+            builder.add(key, value)?;
+        }
+        builder.finish()
+    }
+}
+
+impl Default for TableOptions {
+    fn default() -> Self {
+        TableOptions::new()
+    }
+}
+
+// With the default options
+pub fn write_table(path: impl AsRef<Path>, map: &BTreeMap<String, Vec<u8>>) -> io::Result<TableStats> {
+    TableOptions::new().write(path, map)
+}
+
+/// What was written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TableStats {
+    pub entries: u64,
+    pub data_blocks: usize,
+    pub file_len: u64,
+}
+
+/// Writes a table from keys added in increasing order.
+pub struct TableBuilder<W: Write> {
+    output: BufWriter<W>,
+    options: TableOptions,
+    offset: u64,
+    block: Vec<u8>,
+    previous_key: Option<String>,
+    // The last key and the position of every finished block
+    index: Vec<(String, u64, u64)>,
+    key_hashes: Vec<u64>,
+    entries: u64,
+}
+
+impl<W: Write> TableBuilder<W> {
+    pub fn new(output: W, options: TableOptions) -> TableBuilder<W> {
+        TableBuilder {
+            output: BufWriter::new(output),
+            options,
+            offset: 0,
+            block: Vec::new(),
+            previous_key: None,
+            index: Vec::new(),
+            key_hashes: Vec::new(),
+            entries: 0,
+        }
+    }
+
+    pub fn add(&mut self, key: &str, value: &[u8]) -> io::Result<()> {
+        // Nothing is written for a key out of order, so the table so far stays valid
+        if let Some(previous) = self.previous_key.as_deref().filter(|&previous| key <= previous) {
+            let message = format!("keys must be added in increasing order: {:?} after {:?}", key, previous);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+        // The first key of a block is stored whole, so every block can be read on its own
+        let shared = match &self.previous_key {
+            Some(previous) if !self.block.is_empty() => shared_prefix_len(previous, key),
+            _ => 0,
+        };
+        binary::write_varint(&mut self.block, shared as u64);
+        binary::write_varint(&mut self.block, (key.len() - shared) as u64);
+        binary::write_varint(&mut self.block, value.len() as u64);
+        self.block.extend_from_slice(&key.as_bytes()[shared..]);
+        self.block.extend_from_slice(value);
+
+        self.previous_key = Some(key.to_string());
+        if self.options.bloom_bits_per_key.is_some() {
+            self.key_hashes.push(hash_key(key));
+        }
+        self.entries += 1;
+        if self.block.len() >= self.options.block_size {
+            self.finish_block()?;
+        }
+        Ok(())
+    }
+
+    fn finish_block(&mut self) -> io::Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        let block = std::mem::take(&mut self.block);
+        let (offset, len) = self.write_block(block)?;
+        let last_key = self.previous_key.clone().expect("a block holds at least one key");
+        self.index.push((last_key, offset, len));
+        Ok(())
+    }
+
+    // Writes the block with its checksum, returns where it starts and how long it is
+    fn write_block(&mut self, mut block: Vec<u8>) -> io::Result<(u64, u64)> {
+        let checksum = crc32(&block);
+        block.extend_from_slice(&checksum.to_le_bytes());
+        self.output.write_all(&block)?;
+        let offset = self.offset;
+        self.offset += block.len() as u64;
+        Ok((offset, block.len() as u64))
+    }
+
+    pub fn finish(mut self) -> io::Result<TableStats> {
+        self.finish_block()?;
+
+        let mut index = Vec::new();
+        binary::write_varint(&mut index, self.index.len() as u64);
+        for (last_key, offset, len) in &self.index {
+            binary::write_varint(&mut index, last_key.len() as u64);
+            index.extend_from_slice(last_key.as_bytes());
+            binary::write_varint(&mut index, *offset);
+            binary::write_varint(&mut index, *len);
+        }
+        let (index_offset, index_len) = self.write_block(index)?;
+
+        let (bloom_offset, bloom_len) = match self.options.bloom_bits_per_key {
+            Some(bits_per_key) => {
+                let filter = BloomFilter::build(&self.key_hashes, bits_per_key);
+                self.write_block(filter.to_bytes())?
+            }
+            None => (0, 0),
+        };
+
+        let mut footer = Vec::with_capacity(FOOTER_LEN as usize);
+        for number in [index_offset, index_len, bloom_offset, bloom_len, self.entries] {
+            footer.extend_from_slice(&number.to_le_bytes());
+        }
+        let checksum = crc32(&footer);
+        footer.extend_from_slice(&checksum.to_le_bytes());
+        footer.extend_from_slice(&VERSION.to_le_bytes());
+        footer.extend_from_slice(MAGIC);
+        self.output.write_all(&footer)?;
+        self.output.flush()?;
+
+        Ok(TableStats { entries: self.entries, data_blocks: self.index.len(), file_len: self.offset + FOOTER_LEN })
+    }
+}
+
+fn shared_prefix_len(a: &str, b: &str) -> usize {
+    a.bytes().zip(b.bytes()).take_while(|(a, b)| a == b).count()
+}
+
+// FNV-1a. The hash is part of the file format, so it can not be `DefaultHasher`, whose output may
+// change between Rust releases.
+fn hash_key(key: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in key.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// A set that can answer "definitely not here" or "probably here" in a few bits per key.
+struct BloomFilter {
+    bits: Vec<u8>,
+    hash_count: u32,
+}
+
+impl BloomFilter {
+    fn build(hashes: &[u64], bits_per_key: usize) -> BloomFilter {
+        let bit_count = (hashes.len() * bits_per_key).max(64);
+        // ln 2 * bits per key hash functions give the fewest false positives
+        let hash_count = ((bits_per_key as f64 * 0.69) as u32).clamp(1, 30);
+        let mut filter = BloomFilter { bits: vec![0; bit_count.div_ceil(8)], hash_count };
+        for &hash in hashes {
+            for bit in filter.bit_positions(hash) {
+                filter.bits[bit / 8] |= 1 << (bit % 8);
+            }
+        }
+        filter
+    }
+
+    // Double hashing: the positions are h1, h1 + h2, h1 + 2 * h2, ... with h2 made from h1
+    fn bit_positions(&self, hash: u64) -> impl Iterator<Item = usize> {
+        let bit_count = (self.bits.len() * 8) as u64;
+        let delta = hash.rotate_right(17) | 1;
+        (0..self.hash_count as u64).map(move |i| (hash.wrapping_add(i.wrapping_mul(delta)) % bit_count) as usize)
+    }
+
+    fn may_contain(&self, key: &str) -> bool {
+        self.bit_positions(hash_key(key)).all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.hash_count as u8];
+        bytes.extend_from_slice(&self.bits);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<BloomFilter> {
+        let (&hash_count, bits) = bytes.split_first()?;
+        if hash_count == 0 || bits.is_empty() {
+            return None;
+        }
+        Some(BloomFilter { bits: bits.to_vec(), hash_count: hash_count as u32 })
+    }
+}
+
+struct IndexEntry {
+    last_key: String,
+    offset: u64,
+    len: u64,
+}
+
+/// An open SSTable file. Only the index and the bloom filter are kept in memory.
+pub struct SsTable {
+    file: File,
+    index: Vec<IndexEntry>,
+    bloom: Option<BloomFilter>,
+    entries: u64,
+    blocks_read: std::cell::Cell<usize>,
+}
+
+impl SsTable {
+    pub fn open(path: impl AsRef<Path>) -> Result<SsTable, TableError> {
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        if file_len < FOOTER_LEN {
+            return Err(TableError::BadMagic);
+        }
+        let mut footer = [0; FOOTER_LEN as usize];
+        read_at(&file, file_len - FOOTER_LEN, &mut footer)?;
+        if &footer[48..] != MAGIC {
+            return Err(TableError::BadMagic);
+        }
+        let version = u32::from_le_bytes(footer[44..48].try_into().unwrap());
+        if version != VERSION {
+            return Err(TableError::UnsupportedVersion(version));
+        }
+        let stored = u32::from_le_bytes(footer[40..44].try_into().unwrap());
+        if crc32(&footer[..40]) != stored {
+            return Err(TableError::Corrupt { offset: file_len - FOOTER_LEN, reason: String::from("footer checksum mismatch") });
+        }
+        let number = |index: usize| u64::from_le_bytes(footer[index * 8..index * 8 + 8].try_into().unwrap());
+        let (index_offset, index_len, bloom_offset, bloom_len, entries) = (number(0), number(1), number(2), number(3), number(4));
+        let data_end = file_len - FOOTER_LEN;
+        for (offset, len) in [(index_offset, index_len), (bloom_offset, bloom_len)] {
+            if offset.checked_add(len).is_none_or(|end| end > data_end) {
+                return Err(TableError::Corrupt { offset, reason: String::from("block is outside the file") });
+            }
+        }
+
+        let index_block = read_block(&file, index_offset, index_len)?;
+        let mut input = Decoder::new(&index_block);
+        let index = read_index(&mut input).map_err(corrupt(index_offset))?;
+        let bloom = if bloom_len == 0 {
+            None
+        } else {
+            let bytes = read_block(&file, bloom_offset, bloom_len)?;
+            let filter = BloomFilter::from_bytes(&bytes)
+                .ok_or_else(|| TableError::Corrupt { offset: bloom_offset, reason: String::from("empty bloom filter") })?;
+            Some(filter)
+        };
+
+        Ok(SsTable { file, index, bloom, entries, blocks_read: std::cell::Cell::new(0) })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries == 0
+    }
+
+    pub fn data_blocks(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn has_bloom_filter(&self) -> bool {
+        self.bloom.is_some()
+    }
+
+    // How many data blocks have been read from the file so far
+    pub fn blocks_read(&self) -> usize {
+        self.blocks_read.get()
+    }
+
+    // The first block whose last key is not smaller than `key`, the only one that can hold it
+    fn block_for(&self, key: &str) -> usize {
+        self.index.partition_point(|entry| entry.last_key.as_str() < key)
+    }
+
+    fn load_block(&self, block: usize) -> Result<Vec<(String, Vec<u8>)>, TableError> {
+        let entry = &self.index[block];
+        self.blocks_read.set(self.blocks_read.get() + 1);
+        let bytes = read_block(&self.file, entry.offset, entry.len)?;
+        decode_block(&bytes, entry.offset)
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>, TableError> {
+        if let Some(bloom) = &self.bloom {
+            if !bloom.may_contain(key) {
+                return Ok(None);
+            }
+        }
+        let block = self.block_for(key);
+        if block == self.index.len() {
+            return Ok(None);
+        }
+        let entries = self.load_block(block)?;
+        Ok(entries
+            .binary_search_by(|(entry_key, _)| entry_key.as_str().cmp(key))
+            .ok()
+            .map(|position| entries[position].1.clone()))
+    }
+
+    pub fn contains_key(&self, key: &str) -> Result<bool, TableError> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    // The entries with keys in `range`, in order, reading one block at a time
+    pub fn range<'a, R: RangeBounds<&'a str>>(&self, range: R) -> Range<'_> {
+        let start = range.start_bound().map(|key| key.to_string());
+        let end = range.end_bound().map(|key| key.to_string());
+        let next_block = match &start {
+            Bound::Included(key) | Bound::Excluded(key) => self.block_for(key),
+            Bound::Unbounded => 0,
+        };
+        Range { table: self, start, end, next_block, entries: Vec::new().into_iter(), done: false }
+    }
+
+    pub fn iter(&self) -> Range<'_> {
+        self.range(..)
+    }
+}
+
+/// The entries of a table in a key range, see `SsTable::range`.
+pub struct Range<'t> {
+    table: &'t SsTable,
+    start: Bound<String>,
+    end: Bound<String>,
+    next_block: usize,
+    entries: std::vec::IntoIter<(String, Vec<u8>)>,
+    done: bool,
+}
+
+impl Iterator for Range<'_> {
+    type Item = Result<(String, Vec<u8>), TableError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            for (key, value) in self.entries.by_ref() {
+                let after_start = match &self.start {
+                    Bound::Included(start) => key >= *start,
+                    Bound::Excluded(start) => key > *start,
+                    Bound::Unbounded => true,
+                };
+                if !after_start {
+                    continue;
+                }
+                let before_end = match &self.end {
+                    Bound::Included(end) => key <= *end,
+                    Bound::Excluded(end) => key < *end,
+                    Bound::Unbounded => true,
+                };
+                if !before_end {
+                    self.done = true;
+                    return None;
+                }
+                return Some(Ok((key, value)));
+            }
+            if self.next_block == self.table.index.len() {
+                self.done = true;
+                return None;
+            }
+            match self.table.load_block(self.next_block) {
+                Ok(entries) => self.entries = entries.into_iter(),
+                Err(error) => {
+                    self.done = true;
+                    return Some(Err(error));
+                }
+            }
+            self.next_block += 1;
+        }
+        None
+    }
+}
+
+fn read_at(mut file: &File, offset: u64, buffer: &mut [u8]) -> io::Result<()> {
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(buffer)
+}
+
+// The contents of a block, after checking its checksum
+fn read_block(file: &File, offset: u64, len: u64) -> Result<Vec<u8>, TableError> {
+    if len < 4 {
+        return Err(TableError::Corrupt { offset, reason: String::from("block is too short") });
+    }
+    let mut bytes = vec![0; len as usize];
+    read_at(file, offset, &mut bytes)?;
+    let stored = u32::from_le_bytes(bytes[bytes.len() - 4..].try_into().unwrap());
+    bytes.truncate(bytes.len() - 4);
+    if crc32(&bytes) != stored {
+        return Err(TableError::Corrupt { offset, reason: String::from("checksum mismatch") });
+    }
+    Ok(bytes)
+}
+
+fn read_string(input: &mut Decoder<'_>) -> Result<String, DecodeError> {
+    let offset = input.offset();
+    let len = input.read_length()?;
+    String::from_utf8(input.read_bytes(len)?.to_vec()).map_err(|_| DecodeError::InvalidUtf8 { offset })
+}
+
+fn read_index(input: &mut Decoder<'_>) -> Result<Vec<IndexEntry>, DecodeError> {
+    let count = input.read_length()?;
+    let mut index = Vec::with_capacity(count);
+    for _ in 0..count {
+        index.push(IndexEntry { last_key: read_string(input)?, offset: input.read_varint()?, len: input.read_varint()? });
+    }
+    Ok(index)
+}
+
+fn decode_block(bytes: &[u8], block_offset: u64) -> Result<Vec<(String, Vec<u8>)>, TableError> {
+    let mut input = Decoder::new(bytes);
+    let mut entries: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut key = Vec::new();
+    while input.remaining() > 0 {
+        let offset = input.offset();
+        let shared = input.read_varint().map_err(corrupt(block_offset))? as usize;
+        if shared > key.len() {
+            let reason = format!("entry at byte {} shares {} bytes with a key of {}", offset, shared, key.len());
+            return Err(TableError::Corrupt { offset: block_offset, reason });
+        }
+        let suffix_len = input.read_length().map_err(corrupt(block_offset))?;
+        let value_len = input.read_length().map_err(corrupt(block_offset))?;
+        key.truncate(shared);
+        key.extend_from_slice(input.read_bytes(suffix_len).map_err(corrupt(block_offset))?);
+        let value = input.read_bytes(value_len).map_err(corrupt(block_offset))?.to_vec();
+        let text = String::from_utf8(key.clone()).map_err(|_| corrupt(block_offset)(DecodeError::InvalidUtf8 { offset }))?;
+        entries.push((text, value));
+    }
+    Ok(entries)
+}
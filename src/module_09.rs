@@ -0,0 +1,1415 @@
+pub mod bench;
+pub mod property;
+
+pub mod property_testing {
+    use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+    use std::fs;
+    use std::hash::{BuildHasher, BuildHasherDefault, Hasher};
+    use std::path::PathBuf;
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::property::{expect_eq, shrink_number, steps, Config, Operation, Passed, Random};
+    use crate::module_05::fuzzy::{levenshtein, BkTree};
+    use crate::module_05::gap_buffer::GapBuffer;
+    use crate::module_05::rope::Rope;
+    use crate::module_05::text_buffer::TextBuffer;
+    use crate::module_06::arena::{Arena, ArenaList, ArenaTree};
+    use crate::module_06::inline_string::InlineString;
+    use crate::module_06::interner::Interner;
+    use crate::module_06::persistent::{PMap, PVec};
+    use crate::module_06::small_vec::SmallVec;
+    use crate::module_07::lock_free::{LockFreeQueue, LockFreeStack};
+    use crate::module_07::work_stealing::{Steal, Worker};
+    use crate::module_08::disk_map::{DiskMap, DiskMapOptions};
+
+    // A growable ring buffer with a classic bug: growing it keeps the slots where they are, which
+    // scrambles the order of the elements if they had wrapped around the end
+    struct RingBuffer {
+        slots: Vec<i32>,
+        head: usize,
+        len: usize,
+    }
+
+    impl RingBuffer {
+        fn new() -> RingBuffer {
+            RingBuffer { slots: vec![0; 4], head: 0, len: 0 }
+        }
+
+        fn push_back(&mut self, value: i32) {
+            if self.len == self.slots.len() {
+                self.slots.resize(self.slots.len() * 2, 0);
+            }
+            let slot = (self.head + self.len) % self.slots.len();
+            self.slots[slot] = value;
+            self.len += 1;
+        }
+
+        fn pop_front(&mut self) -> Option<i32> {
+            if self.len == 0 {
+                return None;
+            }
+            let value = self.slots[self.head];
+            self.head = (self.head + 1) % self.slots.len();
+            self.len -= 1;
+            Some(value)
+        }
+
+        fn to_vec(&self) -> Vec<i32> {
+            (0..self.len).map(|index| self.slots[(self.head + index) % self.slots.len()]).collect()
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    enum RingOp {
+        PushBack(i32),
+        PopFront,
+    }
+
+    impl Operation for RingOp {
+        fn generate(random: &mut Random) -> RingOp {
+            if random.chance(2, 3) {
+                RingOp::PushBack(random.below(100) as i32)
+            } else {
+                RingOp::PopFront
+            }
+        }
+
+        fn shrink(&self) -> Vec<RingOp> {
+            match self {
+                RingOp::PushBack(value) => shrink_value(*value).into_iter().map(RingOp::PushBack).collect(),
+                RingOp::PopFront => Vec::new(),
+            }
+        }
+    }
+
+    fn run_ring_buffer(ops: &[RingOp]) -> Result<(), String> {
+        let mut ring = RingBuffer::new();
+        let mut model = VecDeque::new();
+        steps(ops, |op| {
+            match op {
+                RingOp::PushBack(value) => {
+                    ring.push_back(*value);
+                    model.push_back(*value);
+                }
+                RingOp::PopFront => expect_eq("pop_front()", ring.pop_front(), model.pop_front())?,
+            }
+            expect_eq("contents", ring.to_vec(), Vec::from(model.clone()))
+        })
+    }
+
+    pub fn demo_property_testing() {
+        // Short sequences rarely wrap the buffer around and then grow it, long ones do
+        let config = Config::new().cases(100).max_operations(100);
+        let failure = config.check(run_ring_buffer).unwrap_err();
+        println!("The ring buffer {}\n", failure);
+
+        // The same seed finds the same failure again, other seeds find it in other cases
+        let again = config.seed(failure.seed).check(run_ring_buffer).unwrap_err();
+        assert_eq!((again.case, format!("{:?}", again.ops)), (failure.case, format!("{:?}", failure.ops)));
+        for seed in 1..=3 {
+            let other = config.seed(seed).check(run_ring_buffer).unwrap_err();
+            println!("Seed {}: case {}, shrunk from {} to {} operations", seed, other.case, other.original_len, other.ops.len());
+        }
+
+        // A correct collection passes every case
+        let passed = config.assert("SmallVec behaves like Vec", run_small_vec);
+        println!("SmallVec: {:?}", passed);
+    }
+
+    // Runs every custom collection in the course against the std collection it imitates
+    pub fn check_collections() {
+        let config = Config::new();
+        let report = |name: &str, passed: Passed| println!("{:<40} {} cases, {} operations", name, passed.cases, passed.operations);
+
+        report("SmallVec<_, 4> behaves like Vec", config.assert("SmallVec", run_small_vec));
+        report("PVec behaves like Vec", config.assert("PVec", run_pvec));
+        report("PMap behaves like HashMap", config.assert("PMap", |ops| run_pmap(ops, std::hash::RandomState::new())));
+        report("PMap with colliding hashes too", config.assert("PMap", |ops| run_pmap(ops, BuildHasherDefault::<ThreeHashes>::default())));
+        report("ArenaList behaves like VecDeque", config.assert("ArenaList", run_arena_list));
+        report("ArenaTree behaves like BTreeMap", config.assert("ArenaTree", run_arena_tree));
+        report("DiskMap behaves like BTreeMap", config.cases(50).assert("DiskMap", run_disk_map));
+        report("LockFreeStack behaves like Vec", config.assert("LockFreeStack", run_lock_free_stack));
+        report("LockFreeQueue behaves like VecDeque", config.assert("LockFreeQueue", run_lock_free_queue));
+        report("Worker behaves like VecDeque", config.assert("Worker", run_worker));
+        report("BkTree behaves like BTreeSet", config.assert("BkTree", run_bk_tree));
+        report("Interner behaves like HashMap", config.assert("Interner", run_interner));
+        report("InlineString behaves like String", config.assert("InlineString", run_inline_string));
+        report("GapBuffer behaves like String", config.assert("GapBuffer", run_gap_buffer));
+        report("Rope behaves like String", config.assert("Rope", run_rope));
+    }
+
+    // Values are small, so that retain and the maps see many equal ones
+    fn value(random: &mut Random) -> i32 {
+        random.below(50) as i32
+    }
+
+    fn shrink_value(value: i32) -> Vec<i32> {
+        shrink_number(value.unsigned_abs() as usize).into_iter().map(|smaller| smaller as i32).collect()
+    }
+
+    // Operations are generated without looking at the collection, so positions are wrapped
+    // around its length when they are used. That keeps them valid while shrinking, too.
+    fn position(position: usize, len: usize) -> usize {
+        position % (len + 1)
+    }
+
+    fn element(position: usize, len: usize) -> Option<usize> {
+        (len > 0).then(|| position % len)
+    }
+
+    fn span(start: usize, end: usize, len: usize) -> (usize, usize) {
+        let (start, end) = (position(start, len), position(end, len));
+        (start.min(end), start.max(end))
+    }
+
+    // A `retain` predicate as data, so that a failure can print it
+    #[derive(Clone, Debug)]
+    enum Keep {
+        Even,
+        Below(i32),
+    }
+
+    impl Keep {
+        fn keeps(&self, value: i32) -> bool {
+            match self {
+                Keep::Even => value % 2 == 0,
+                Keep::Below(limit) => value < *limit,
+            }
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    enum VecOp {
+        Push(i32),
+        Pop,
+        Insert(usize, i32),
+        Remove(usize),
+        SwapRemove(usize),
+        Truncate(usize),
+        Retain(Keep),
+        // Drains a range, but only looks at the first few drained elements
+        Drain(usize, usize, usize),
+        ExtendFromSlice(Vec<i32>),
+        SplitOff(usize),
+        Clear,
+    }
+
+    impl Operation for VecOp {
+        fn generate(random: &mut Random) -> VecOp {
+            match random.below(24) {
+                0..=7 => VecOp::Push(value(random)),
+                8..=9 => VecOp::Pop,
+                10..=11 => VecOp::Insert(random.index(40), value(random)),
+                12..=13 => VecOp::Remove(random.index(40)),
+                14 => VecOp::SwapRemove(random.index(40)),
+                15 => VecOp::Truncate(random.index(40)),
+                16 if random.chance(1, 2) => VecOp::Retain(Keep::Even),
+                16 => VecOp::Retain(Keep::Below(value(random))),
+                17..=18 => VecOp::Drain(random.index(40), random.index(40), random.index(10)),
+                19..=20 => VecOp::ExtendFromSlice((0..random.below(10)).map(|_| value(random)).collect()),
+                21 => VecOp::SplitOff(random.index(40)),
+                22 => VecOp::Clear,
+                _ => VecOp::Push(value(random)),
+            }
+        }
+
+        fn shrink(&self) -> Vec<VecOp> {
+            match self {
+                VecOp::Push(value) => shrink_value(*value).into_iter().map(VecOp::Push).collect(),
+                VecOp::Insert(at, value) => {
+                    let mut simpler: Vec<VecOp> = shrink_number(*at).into_iter().map(|at| VecOp::Insert(at, *value)).collect();
+                    simpler.extend(shrink_value(*value).into_iter().map(|value| VecOp::Insert(*at, value)));
+                    simpler
+                }
+                VecOp::Remove(at) => shrink_number(*at).into_iter().map(VecOp::Remove).collect(),
+                VecOp::SwapRemove(at) => shrink_number(*at).into_iter().map(VecOp::SwapRemove).collect(),
+                VecOp::Truncate(len) => shrink_number(*len).into_iter().map(VecOp::Truncate).collect(),
+                VecOp::Drain(start, end, taken) => shrink_number(*taken).into_iter().map(|taken| VecOp::Drain(*start, *end, taken)).collect(),
+                VecOp::ExtendFromSlice(values) if !values.is_empty() => {
+                    vec![VecOp::ExtendFromSlice(values[..values.len() / 2].to_vec()), VecOp::ExtendFromSlice(values[1..].to_vec())]
+                }
+                VecOp::SplitOff(at) => shrink_number(*at).into_iter().map(VecOp::SplitOff).collect(),
+                _ => Vec::new(),
+            }
+        }
+    }
+
+    // An element that counts how many of its kind are alive, to catch leaks and double drops
+    #[derive(Clone, Debug)]
+    struct Counted(i32, Rc<()>);
+
+    fn run_small_vec(ops: &[VecOp]) -> Result<(), String> {
+        let alive = Rc::new(());
+        let counted = |value: i32| Counted(value, Rc::clone(&alive));
+        let values = |items: &[Counted]| items.iter().map(|item| item.0).collect::<Vec<i32>>();
+        let mut small: SmallVec<Counted, 4> = SmallVec::new();
+        let mut model: Vec<i32> = Vec::new();
+
+        steps(ops, |op| {
+            let len = model.len();
+            match op {
+                VecOp::Push(value) => {
+                    small.push(counted(*value));
+                    model.push(*value);
+                }
+                VecOp::Pop => expect_eq("pop()", small.pop().map(|item| item.0), model.pop())?,
+                VecOp::Insert(at, value) => {
+                    small.insert(position(*at, len), counted(*value));
+                    model.insert(position(*at, len), *value);
+                }
+                VecOp::Remove(at) => {
+                    if let Some(index) = element(*at, len) {
+                        expect_eq("remove()", small.remove(index).0, model.remove(index))?;
+                    }
+                }
+                VecOp::SwapRemove(at) => {
+                    if let Some(index) = element(*at, len) {
+                        expect_eq("swap_remove()", small.swap_remove(index).0, model.swap_remove(index))?;
+                    }
+                }
+                VecOp::Truncate(at) => {
+                    small.truncate(position(*at, len));
+                    model.truncate(position(*at, len));
+                }
+                VecOp::Retain(keep) => {
+                    small.retain(|item| keep.keeps(item.0));
+                    model.retain(|&value| keep.keeps(value));
+                }
+                VecOp::Drain(start, end, taken) => {
+                    let (start, end) = span(*start, *end, len);
+                    let drained: Vec<i32> = small.drain(start..end).take(*taken).map(|item| item.0).collect();
+                    expect_eq("drain()", drained, model.drain(start..end).take(*taken).collect())?;
+                }
+                VecOp::ExtendFromSlice(items) => {
+                    let items: Vec<Counted> = items.iter().map(|&value| counted(value)).collect();
+                    small.extend_from_slice(&items);
+                    model.extend(items.iter().map(|item| item.0));
+                }
+                VecOp::SplitOff(at) => {
+                    let tail = small.split_off(position(*at, len));
+                    expect_eq("split_off()", values(&tail), model.split_off(position(*at, len)))?;
+                }
+                VecOp::Clear => {
+                    small.clear();
+                    model.clear();
+                }
+            }
+            expect_eq("contents", values(&small), model.clone())?;
+            expect_eq("elements alive", Rc::strong_count(&alive) - 1, small.len())?;
+            if small.capacity() < small.len() {
+                return Err(format!("capacity {} is less than the length {}", small.capacity(), small.len()));
+            }
+            Ok(())
+        })
+    }
+
+    #[derive(Clone, Debug)]
+    enum PVecOp {
+        Push(i32),
+        // Enough pushes and pops to grow and shrink the tree by whole levels
+        PushMany(usize),
+        PopMany(usize),
+        Pop,
+        Set(usize, i32),
+        // Keeps a copy of the vector, which later changes must not affect
+        Snapshot,
+        Restore(usize),
+    }
+
+    impl Operation for PVecOp {
+        fn generate(random: &mut Random) -> PVecOp {
+            match random.below(20) {
+                0..=7 => PVecOp::Push(value(random)),
+                8 => PVecOp::PushMany(random.index(1200)),
+                9 => PVecOp::PopMany(random.index(600)),
+                10..=11 => PVecOp::Pop,
+                12..=15 => PVecOp::Set(random.index(2000), value(random)),
+                16..=17 => PVecOp::Snapshot,
+                _ => PVecOp::Restore(random.index(8)),
+            }
+        }
+
+        fn shrink(&self) -> Vec<PVecOp> {
+            match self {
+                PVecOp::PushMany(count) => shrink_number(*count).into_iter().map(PVecOp::PushMany).collect(),
+                PVecOp::PopMany(count) => shrink_number(*count).into_iter().map(PVecOp::PopMany).collect(),
+                PVecOp::Set(at, value) => shrink_number(*at).into_iter().map(|at| PVecOp::Set(at, *value)).collect(),
+                PVecOp::Restore(which) => shrink_number(*which).into_iter().map(PVecOp::Restore).collect(),
+                _ => Vec::new(),
+            }
+        }
+    }
+
+    fn same_pvec(what: &str, pvec: &PVec<i32>, model: &[i32]) -> Result<(), String> {
+        expect_eq(&format!("{}.len()", what), pvec.len(), model.len())?;
+        if !pvec.iter().eq(model.iter()) {
+            return Err(format!("{} is {:?}, std says {:?}", what, pvec, model));
+        }
+        for index in [0, model.len() / 2, model.len().saturating_sub(1), model.len()] {
+            expect_eq(&format!("{}.get({})", what, index), pvec.get(index), model.get(index))?;
+        }
+        Ok(())
+    }
+
+    fn run_pvec(ops: &[PVecOp]) -> Result<(), String> {
+        let mut pvec: PVec<i32> = PVec::new();
+        let mut model: Vec<i32> = Vec::new();
+        let mut snapshots: Vec<(PVec<i32>, Vec<i32>)> = Vec::new();
+
+        steps(ops, |op| {
+            match op {
+                PVecOp::Push(value) => {
+                    pvec.push(*value);
+                    model.push(*value);
+                }
+                PVecOp::PushMany(count) => {
+                    for value in 0..*count as i32 {
+                        pvec.push(value);
+                        model.push(value);
+                    }
+                }
+                PVecOp::PopMany(count) => {
+                    for _ in 0..*count {
+                        expect_eq("pop()", pvec.pop(), model.pop())?;
+                    }
+                }
+                PVecOp::Pop => expect_eq("pop()", pvec.pop(), model.pop())?,
+                PVecOp::Set(at, value) => {
+                    if let Some(index) = element(*at, model.len()) {
+                        let old = std::mem::replace(&mut model[index], *value);
+                        expect_eq("set()", pvec.set(index, *value), old)?;
+                    }
+                }
+                PVecOp::Snapshot => {
+                    if snapshots.len() == 8 {
+                        snapshots.remove(0);
+                    }
+                    snapshots.push((pvec.clone(), model.clone()));
+                }
+                PVecOp::Restore(which) => {
+                    if let Some(index) = element(*which, snapshots.len()) {
+                        pvec = snapshots[index].0.clone();
+                        model = snapshots[index].1.clone();
+                    }
+                }
+            }
+            same_pvec("the vector", &pvec, &model)?;
+            for (index, (snapshot, expected)) in snapshots.iter().enumerate() {
+                same_pvec(&format!("snapshot {}", index), snapshot, expected)?;
+            }
+            Ok(())
+        })
+    }
+
+    #[derive(Clone, Debug)]
+    enum MapOp {
+        Insert(u8, i32),
+        Remove(u8),
+        Get(u8),
+        Snapshot,
+        Restore(usize),
+    }
+
+    impl Operation for MapOp {
+        fn generate(random: &mut Random) -> MapOp {
+            let key = random.below(48) as u8;
+            match random.below(12) {
+                0..=4 => MapOp::Insert(key, value(random)),
+                5..=6 => MapOp::Remove(key),
+                7..=8 => MapOp::Get(key),
+                9 => MapOp::Snapshot,
+                _ => MapOp::Restore(random.index(8)),
+            }
+        }
+
+        fn shrink(&self) -> Vec<MapOp> {
+            match self {
+                MapOp::Insert(key, value) => shrink_value(*value).into_iter().map(|value| MapOp::Insert(*key, value)).collect(),
+                MapOp::Restore(which) => shrink_number(*which).into_iter().map(MapOp::Restore).collect(),
+                _ => Vec::new(),
+            }
+        }
+    }
+
+    // Hashes every key to one of three values, so that most keys collide completely
+    #[derive(Default)]
+    struct ThreeHashes(u64);
+
+    impl Hasher for ThreeHashes {
+        fn write(&mut self, bytes: &[u8]) {
+            for &byte in bytes {
+                self.0 += byte as u64;
+            }
+        }
+
+        fn finish(&self) -> u64 {
+            self.0 % 3
+        }
+    }
+
+    fn sorted_entries<'a>(entries: impl Iterator<Item = (&'a u8, &'a i32)>) -> Vec<(u8, i32)> {
+        let mut entries: Vec<(u8, i32)> = entries.map(|(&key, &value)| (key, value)).collect();
+        entries.sort();
+        entries
+    }
+
+    fn run_pmap<S: BuildHasher + Clone>(ops: &[MapOp], hasher: S) -> Result<(), String> {
+        let mut pmap: PMap<u8, i32, S> = PMap::with_hasher(hasher);
+        let mut model: HashMap<u8, i32> = HashMap::new();
+        let mut snapshots = Vec::new();
+
+        steps(ops, |op| {
+            match op {
+                MapOp::Insert(key, value) => expect_eq("insert()", pmap.insert(*key, *value), model.insert(*key, *value))?,
+                MapOp::Remove(key) => expect_eq("remove()", pmap.remove(key), model.remove(key))?,
+                MapOp::Get(key) => {
+                    expect_eq("get()", pmap.get(key), model.get(key))?;
+                    expect_eq("contains_key()", pmap.contains_key(key), model.contains_key(key))?;
+                }
+                MapOp::Snapshot => {
+                    if snapshots.len() == 8 {
+                        snapshots.remove(0);
+                    }
+                    snapshots.push((pmap.clone(), model.clone()));
+                }
+                MapOp::Restore(which) => {
+                    if let Some(index) = element(*which, snapshots.len()) {
+                        pmap = snapshots[index].0.clone();
+                        model = snapshots[index].1.clone();
+                    }
+                }
+            }
+            expect_eq("len()", pmap.len(), model.len())?;
+            expect_eq("entries", sorted_entries(pmap.iter()), sorted_entries(model.iter()))?;
+            for (index, (snapshot, expected)) in snapshots.iter().enumerate() {
+                expect_eq(&format!("snapshot {}", index), sorted_entries(snapshot.iter()), sorted_entries(expected.iter()))?;
+            }
+            Ok(())
+        })
+    }
+
+    #[derive(Clone, Debug)]
+    enum DequeOp {
+        PushBack(i32),
+        PushFront(i32),
+        PopBack,
+        PopFront,
+        SplitOff(usize),
+        Append(Vec<i32>),
+    }
+
+    impl Operation for DequeOp {
+        fn generate(random: &mut Random) -> DequeOp {
+            match random.below(12) {
+                0..=2 => DequeOp::PushBack(value(random)),
+                3..=5 => DequeOp::PushFront(value(random)),
+                6..=7 => DequeOp::PopBack,
+                8..=9 => DequeOp::PopFront,
+                10 => DequeOp::SplitOff(random.index(40)),
+                _ => DequeOp::Append((0..random.below(6)).map(|_| value(random)).collect()),
+            }
+        }
+
+        fn shrink(&self) -> Vec<DequeOp> {
+            match self {
+                DequeOp::PushBack(value) => shrink_value(*value).into_iter().map(DequeOp::PushBack).collect(),
+                DequeOp::PushFront(value) => shrink_value(*value).into_iter().map(DequeOp::PushFront).collect(),
+                DequeOp::SplitOff(at) => shrink_number(*at).into_iter().map(DequeOp::SplitOff).collect(),
+                DequeOp::Append(values) if !values.is_empty() => vec![DequeOp::Append(values[1..].to_vec())],
+                _ => Vec::new(),
+            }
+        }
+    }
+
+    fn run_arena_list(ops: &[DequeOp]) -> Result<(), String> {
+        let arena = Arena::new();
+        let mut list: ArenaList<'_, i32> = ArenaList::new(&arena);
+        let mut model: VecDeque<i32> = VecDeque::new();
+
+        steps(ops, |op| {
+            match op {
+                DequeOp::PushBack(value) => {
+                    list.push_back(*value);
+                    model.push_back(*value);
+                }
+                DequeOp::PushFront(value) => {
+                    list.push_front(*value);
+                    model.push_front(*value);
+                }
+                DequeOp::PopBack => expect_eq("pop_back()", list.pop_back(), model.pop_back().as_ref())?,
+                DequeOp::PopFront => expect_eq("pop_front()", list.pop_front(), model.pop_front().as_ref())?,
+                DequeOp::SplitOff(at) => {
+                    let at = position(*at, model.len());
+                    let tail: Vec<i32> = list.split_off(at).iter().copied().collect();
+                    expect_eq("split_off()", tail, Vec::from(model.split_off(at)))?;
+                }
+                DequeOp::Append(values) => {
+                    let mut other = ArenaList::new(&arena);
+                    for &value in values {
+                        other.push_back(value);
+                    }
+                    list.append(&mut other);
+                    model.extend(values);
+                    expect_eq("the other list's len() after append()", other.len(), 0)?;
+                }
+            }
+            expect_eq("len()", list.len(), model.len())?;
+            expect_eq("front()", list.front(), model.front())?;
+            expect_eq("back()", list.back(), model.back())?;
+            expect_eq("contents", list.iter().copied().collect::<Vec<i32>>(), Vec::from(model.clone()))
+        })
+    }
+
+    #[derive(Clone, Debug)]
+    enum TreeOp {
+        Insert(u8, i32),
+        Get(u8),
+    }
+
+    impl Operation for TreeOp {
+        fn generate(random: &mut Random) -> TreeOp {
+            let key = random.below(64) as u8;
+            if random.chance(2, 3) {
+                TreeOp::Insert(key, value(random))
+            } else {
+                TreeOp::Get(key)
+            }
+        }
+    }
+
+    fn run_arena_tree(ops: &[TreeOp]) -> Result<(), String> {
+        let arena = Arena::new();
+        let mut tree: ArenaTree<'_, u8, i32> = ArenaTree::new(&arena);
+        let mut model: BTreeMap<u8, i32> = BTreeMap::new();
+
+        steps(ops, |op| {
+            match op {
+                // Like `entry().or_insert()`, an existing value stays
+                TreeOp::Insert(key, value) => expect_eq("insert()", *tree.insert(*key, *value), *model.entry(*key).or_insert(*value))?,
+                TreeOp::Get(key) => {
+                    expect_eq("get()", tree.get(key), model.get(key))?;
+                    expect_eq("contains_key()", tree.contains_key(key), model.contains_key(key))?;
+                }
+            }
+            expect_eq("len()", tree.len(), model.len())?;
+            if !tree.iter().map(|(&key, &value)| (key, value)).eq(model.iter().map(|(&key, &value)| (key, value))) {
+                return Err(format!("entries are {:?}, std says {:?}", tree, model));
+            }
+            Ok(())
+        })
+    }
+
+    #[derive(Clone, Debug)]
+    enum DiskOp {
+        Insert(u8, u16),
+        Remove(u8),
+        // Closes the map and opens it again from its files
+        Reopen,
+        Snapshot,
+    }
+
+    impl Operation for DiskOp {
+        fn generate(random: &mut Random) -> DiskOp {
+            let key = random.below(32) as u8;
+            match random.below(10) {
+                0..=4 => DiskOp::Insert(key, random.below(1000) as u16),
+                5..=6 => DiskOp::Remove(key),
+                7..=8 => DiskOp::Reopen,
+                _ => DiskOp::Snapshot,
+            }
+        }
+    }
+
+    // A directory for one run, removed when the run ends, even if it panics
+    struct TempDirectory(PathBuf);
+
+    impl TempDirectory {
+        fn new(name: &str) -> TempDirectory {
+            static RUNS: AtomicUsize = AtomicUsize::new(0);
+            let run = RUNS.fetch_add(1, Ordering::Relaxed);
+            let directory = std::env::temp_dir().join(format!("rust-2021-collections-{}-{}-{}", name, std::process::id(), run));
+            let _ = fs::remove_dir_all(&directory);
+            TempDirectory(directory)
+        }
+    }
+
+    impl Drop for TempDirectory {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn run_disk_map(ops: &[DiskOp]) -> Result<(), String> {
+        let directory = TempDirectory::new("property");
+        // Frequent automatic snapshots, so that recovery reads a snapshot and a log
+        let options = DiskMapOptions::new().snapshot_every(Some(7));
+        let open = || options.open::<u8, u16>(&directory.0).map_err(|error| error.to_string());
+        let mut map = open()?;
+        let mut model: BTreeMap<u8, u16> = BTreeMap::new();
+
+        steps(ops, |op| {
+            match op {
+                DiskOp::Insert(key, value) => {
+                    let old = map.insert(*key, *value).map_err(|error| error.to_string())?;
+                    expect_eq("insert()", old, model.insert(*key, *value))?;
+                }
+                DiskOp::Remove(key) => {
+                    let old = map.remove(key).map_err(|error| error.to_string())?;
+                    expect_eq("remove()", old, model.remove(key))?;
+                }
+                // The old map still has the files open while the new one reads them, as if the
+                // program had crashed, and every change it made has to be there
+                DiskOp::Reopen => map = open()?,
+                DiskOp::Snapshot => map.snapshot().map_err(|error| error.to_string())?,
+            }
+            expect_eq("contents", map.as_map(), &model)
+        })
+    }
+
+    #[derive(Clone, Debug)]
+    enum StackOp {
+        Push(i32),
+        Pop,
+        // Only the work stealing deque has this: take from the other end
+        Steal,
+    }
+
+    impl Operation for StackOp {
+        fn generate(random: &mut Random) -> StackOp {
+            match random.below(5) {
+                0..=2 => StackOp::Push(value(random)),
+                3 => StackOp::Pop,
+                _ => StackOp::Steal,
+            }
+        }
+
+        fn shrink(&self) -> Vec<StackOp> {
+            match self {
+                StackOp::Push(value) => shrink_value(*value).into_iter().map(StackOp::Push).collect(),
+                _ => Vec::new(),
+            }
+        }
+    }
+
+    fn run_lock_free_stack(ops: &[StackOp]) -> Result<(), String> {
+        let stack = LockFreeStack::new();
+        let mut model: Vec<i32> = Vec::new();
+        steps(ops, |op| {
+            match op {
+                StackOp::Push(value) => {
+                    stack.push(*value);
+                    model.push(*value);
+                }
+                StackOp::Pop | StackOp::Steal => expect_eq("pop()", stack.pop(), model.pop())?,
+            }
+            expect_eq("len()", stack.len(), model.len())?;
+            expect_eq("is_empty()", stack.is_empty(), model.is_empty())
+        })
+    }
+
+    fn run_lock_free_queue(ops: &[StackOp]) -> Result<(), String> {
+        let queue = LockFreeQueue::new();
+        let mut model: VecDeque<i32> = VecDeque::new();
+        steps(ops, |op| {
+            match op {
+                StackOp::Push(value) => {
+                    queue.push(*value);
+                    model.push_back(*value);
+                }
+                StackOp::Pop | StackOp::Steal => expect_eq("pop()", queue.pop(), model.pop_front())?,
+            }
+            expect_eq("len()", queue.len(), model.len())?;
+            expect_eq("is_empty()", queue.is_empty(), model.is_empty())
+        })
+    }
+
+    // The owner pushes and pops at the back, thieves take from the front
+    fn run_worker(ops: &[StackOp]) -> Result<(), String> {
+        let worker = Worker::new();
+        let stealer = worker.stealer();
+        let mut model: VecDeque<i32> = VecDeque::new();
+        steps(ops, |op| {
+            match op {
+                StackOp::Push(value) => {
+                    worker.push(*value);
+                    model.push_back(*value);
+                }
+                StackOp::Pop => expect_eq("pop()", worker.pop(), model.pop_back())?,
+                StackOp::Steal => {
+                    // Nobody else is stealing, so there is no reason to retry
+                    let stolen = match stealer.steal() {
+                        Steal::Success(value) => Some(value),
+                        Steal::Empty => None,
+                        Steal::Retry => return Err(String::from("steal() asked for a retry without contention")),
+                    };
+                    expect_eq("steal()", stolen, model.pop_front())?;
+                }
+            }
+            expect_eq("len()", worker.len(), model.len())?;
+            expect_eq("is_empty()", stealer.is_empty(), model.is_empty())
+        })
+    }
+
+    // Short words from a few letters, so that many are within a small distance of each other
+    fn word(random: &mut Random) -> String {
+        (0..random.below(6)).map(|_| *random.pick(&['a', 'b', 'c', 'é'])).collect()
+    }
+
+    fn shrink_word(word: &str) -> Vec<String> {
+        let mut chars: Vec<char> = word.chars().collect();
+        match chars.pop() {
+            Some(_) => vec![String::new(), chars.into_iter().collect()],
+            None => Vec::new(),
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    enum SetOp {
+        Insert(String),
+        Find(String, usize),
+        Nearest(String),
+    }
+
+    impl Operation for SetOp {
+        fn generate(random: &mut Random) -> SetOp {
+            match random.below(4) {
+                0..=1 => SetOp::Insert(word(random)),
+                2 => SetOp::Find(word(random), random.index(4)),
+                _ => SetOp::Nearest(word(random)),
+            }
+        }
+
+        fn shrink(&self) -> Vec<SetOp> {
+            match self {
+                SetOp::Insert(word) => shrink_word(word).into_iter().map(SetOp::Insert).collect(),
+                SetOp::Find(word, distance) => {
+                    let mut simpler: Vec<SetOp> = shrink_word(word).into_iter().map(|word| SetOp::Find(word, *distance)).collect();
+                    simpler.extend(shrink_number(*distance).into_iter().map(|distance| SetOp::Find(word.clone(), distance)));
+                    simpler
+                }
+                SetOp::Nearest(word) => shrink_word(word).into_iter().map(SetOp::Nearest).collect(),
+            }
+        }
+    }
+
+    // The model answers fuzzy lookups by measuring the distance to every word
+    fn run_bk_tree(ops: &[SetOp]) -> Result<(), String> {
+        let mut tree: BkTree<String> = BkTree::new();
+        let mut model: BTreeSet<String> = BTreeSet::new();
+        steps(ops, |op| {
+            match op {
+                SetOp::Insert(word) => expect_eq("insert()", tree.insert(word.clone()), model.insert(word.clone()))?,
+                SetOp::Find(query, max_distance) => {
+                    let found: Vec<(usize, String)> = tree.find(query, *max_distance).into_iter().map(|(distance, word)| (distance, word.clone())).collect();
+                    if !found.windows(2).all(|pair| pair[0].0 <= pair[1].0) {
+                        return Err(format!("find() is not closest first: {:?}", found));
+                    }
+                    let mut found = found;
+                    found.sort();
+                    let mut expected: Vec<(usize, String)> =
+                        model.iter().map(|word| (levenshtein(query, word), word.clone())).filter(|&(distance, _)| distance <= *max_distance).collect();
+                    expected.sort();
+                    expect_eq("find()", found, expected)?;
+                }
+                SetOp::Nearest(query) => {
+                    let nearest = tree.nearest(query).map(|(distance, word)| (distance, levenshtein(query, word)));
+                    let closest = model.iter().map(|word| levenshtein(query, word)).min();
+                    expect_eq("nearest() distance, and the distance to the word it returned", nearest, closest.map(|distance| (distance, distance)))?;
+                }
+            }
+            expect_eq("len()", tree.len(), model.len())?;
+            expect_eq("words", tree.iter().cloned().collect::<BTreeSet<String>>(), model.clone())
+        })
+    }
+
+    #[derive(Clone, Debug)]
+    enum InternOp {
+        Intern(String),
+        Get(String),
+    }
+
+    impl Operation for InternOp {
+        fn generate(random: &mut Random) -> InternOp {
+            if random.chance(2, 3) {
+                InternOp::Intern(word(random))
+            } else {
+                InternOp::Get(word(random))
+            }
+        }
+
+        fn shrink(&self) -> Vec<InternOp> {
+            match self {
+                InternOp::Intern(word) => shrink_word(word).into_iter().map(InternOp::Intern).collect(),
+                InternOp::Get(word) => shrink_word(word).into_iter().map(InternOp::Get).collect(),
+            }
+        }
+    }
+
+    // The model hands out symbols in order, remembering them in a `HashMap`
+    fn run_interner(ops: &[InternOp]) -> Result<(), String> {
+        let mut interner = Interner::new();
+        let mut model: HashMap<String, u32> = HashMap::new();
+        let mut names: Vec<String> = Vec::new();
+        steps(ops, |op| {
+            match op {
+                InternOp::Intern(name) => {
+                    let expected = *model.entry(name.clone()).or_insert_with(|| {
+                        names.push(name.clone());
+                        names.len() as u32 - 1
+                    });
+                    let symbol = interner.intern(name);
+                    expect_eq("intern()", symbol.as_u32(), expected)?;
+                    expect_eq("resolve()", interner.resolve(symbol), name.as_str())?;
+                }
+                InternOp::Get(name) => expect_eq("get()", interner.get(name).map(|symbol| symbol.as_u32()), model.get(name).copied())?,
+            }
+            expect_eq("len()", interner.len(), names.len())?;
+            let interned: Vec<&str> = interner.iter().map(|(_, name)| name).collect();
+            expect_eq("iter()", interned, names.iter().map(String::as_str).collect())
+        })
+    }
+
+    // Positions count chars, and are turned into byte offsets for the containers that use those
+    #[derive(Clone, Debug)]
+    enum TextOp {
+        Push(char),
+        PushStr(String),
+        Insert(usize, char),
+        InsertStr(usize, String),
+        Pop,
+        Remove(usize),
+        Truncate(usize),
+        ReplaceRange(usize, usize, String),
+        SplitOff(usize),
+        Find(String),
+        // Only moves the gap of a gap buffer, the text stays the same
+        MoveCursor(usize),
+        Clear,
+    }
+
+    fn text_char(random: &mut Random) -> char {
+        *random.pick(&['a', 'b', 'é', '☕', '\n'])
+    }
+
+    fn text(random: &mut Random) -> String {
+        (0..random.below(8)).map(|_| text_char(random)).collect()
+    }
+
+    impl Operation for TextOp {
+        fn generate(random: &mut Random) -> TextOp {
+            match random.below(26) {
+                0..=3 => TextOp::Push(text_char(random)),
+                4..=6 => TextOp::PushStr(text(random)),
+                7..=9 => TextOp::Insert(random.index(40), text_char(random)),
+                10..=12 => TextOp::InsertStr(random.index(40), text(random)),
+                13..=14 => TextOp::Pop,
+                15..=16 => TextOp::Remove(random.index(40)),
+                17 => TextOp::Truncate(random.index(40)),
+                18..=19 => TextOp::ReplaceRange(random.index(40), random.index(40), text(random)),
+                20 => TextOp::SplitOff(random.index(40)),
+                21..=22 => TextOp::Find((0..1 + random.below(2)).map(|_| text_char(random)).collect()),
+                23..=24 => TextOp::MoveCursor(random.index(40)),
+                _ => TextOp::Clear,
+            }
+        }
+
+        fn shrink(&self) -> Vec<TextOp> {
+            let shorter = |text: &str| shrink_word(text);
+            match self {
+                TextOp::PushStr(text) => shorter(text).into_iter().map(TextOp::PushStr).collect(),
+                TextOp::Insert(at, ch) => shrink_number(*at).into_iter().map(|at| TextOp::Insert(at, *ch)).collect(),
+                TextOp::InsertStr(at, text) => {
+                    let mut simpler: Vec<TextOp> = shrink_number(*at).into_iter().map(|at| TextOp::InsertStr(at, text.clone())).collect();
+                    simpler.extend(shorter(text).into_iter().map(|text| TextOp::InsertStr(*at, text)));
+                    simpler
+                }
+                TextOp::Remove(at) => shrink_number(*at).into_iter().map(TextOp::Remove).collect(),
+                TextOp::Truncate(at) => shrink_number(*at).into_iter().map(TextOp::Truncate).collect(),
+                TextOp::ReplaceRange(start, end, text) => {
+                    let mut simpler: Vec<TextOp> = shorter(text).into_iter().map(|text| TextOp::ReplaceRange(*start, *end, text)).collect();
+                    simpler.extend(shrink_number(*start).into_iter().map(|start| TextOp::ReplaceRange(start, *end, text.clone())));
+                    simpler
+                }
+                TextOp::SplitOff(at) => shrink_number(*at).into_iter().map(TextOp::SplitOff).collect(),
+                TextOp::MoveCursor(at) => shrink_number(*at).into_iter().map(TextOp::MoveCursor).collect(),
+                _ => Vec::new(),
+            }
+        }
+    }
+
+    fn byte_offset(text: &str, chars: usize) -> usize {
+        text.char_indices().nth(chars).map_or(text.len(), |(offset, _)| offset)
+    }
+
+    fn char_count(text: &str) -> usize {
+        text.chars().count()
+    }
+
+    // `split_off`, `truncate`, `insert` and `pop`, and what `Deref<Target = str>` gives it
+    fn run_inline_string(ops: &[TextOp]) -> Result<(), String> {
+        let mut inline = InlineString::new();
+        let mut model = String::new();
+        steps(ops, |op| {
+            let chars = char_count(&model);
+            let at = |position_in_chars: usize| byte_offset(&model, position(position_in_chars, chars));
+            match op {
+                TextOp::Push(ch) => {
+                    inline.push(*ch);
+                    model.push(*ch);
+                }
+                TextOp::PushStr(text) => {
+                    inline.push_str(text);
+                    model.push_str(text);
+                }
+                TextOp::Insert(position, ch) => {
+                    let offset = at(*position);
+                    inline.insert(offset, *ch);
+                    model.insert(offset, *ch);
+                }
+                TextOp::InsertStr(position, text) => {
+                    let offset = at(*position);
+                    inline.insert_str(offset, text);
+                    model.insert_str(offset, text);
+                }
+                TextOp::Pop => expect_eq("pop()", inline.pop(), model.pop())?,
+                TextOp::Truncate(position) => {
+                    let offset = at(*position);
+                    inline.truncate(offset);
+                    model.truncate(offset);
+                }
+                TextOp::SplitOff(position) => {
+                    let offset = at(*position);
+                    expect_eq("split_off()", inline.split_off(offset).as_str(), model.split_off(offset).as_str())?;
+                }
+                TextOp::Find(pattern) => expect_eq("find()", inline.find(pattern.as_str()), model.find(pattern.as_str()))?,
+                TextOp::Clear => {
+                    inline.clear();
+                    model.clear();
+                }
+                TextOp::Remove(_) | TextOp::ReplaceRange(..) | TextOp::MoveCursor(_) => {}
+            }
+            expect_eq("contents", inline.as_str(), model.as_str())?;
+            if inline.capacity() < inline.len() {
+                return Err(format!("capacity {} is less than the length {}", inline.capacity(), inline.len()));
+            }
+            Ok(())
+        })
+    }
+
+    // Everything `TextBuffer` offers, plus moving the gap around between edits
+    fn run_gap_buffer(ops: &[TextOp]) -> Result<(), String> {
+        let mut buffer = GapBuffer::new();
+        let mut model = String::new();
+        steps(ops, |op| {
+            let chars = char_count(&model);
+            let at = |position_in_chars: usize| byte_offset(&model, position(position_in_chars, chars));
+            match op {
+                TextOp::Push(ch) => {
+                    TextBuffer::push(&mut buffer, *ch);
+                    model.push(*ch);
+                }
+                TextOp::PushStr(text) => {
+                    TextBuffer::push_str(&mut buffer, text);
+                    model.push_str(text);
+                }
+                TextOp::Insert(position, ch) => {
+                    let offset = at(*position);
+                    TextBuffer::insert(&mut buffer, offset, *ch);
+                    model.insert(offset, *ch);
+                }
+                TextOp::InsertStr(position, text) => {
+                    let offset = at(*position);
+                    TextBuffer::insert_str(&mut buffer, offset, text);
+                    model.insert_str(offset, text);
+                }
+                TextOp::Pop => expect_eq("pop()", TextBuffer::pop(&mut buffer), model.pop())?,
+                TextOp::Remove(position) => {
+                    if let Some(index) = element(*position, chars) {
+                        let offset = byte_offset(&model, index);
+                        expect_eq("remove()", TextBuffer::remove(&mut buffer, offset), model.remove(offset))?;
+                    }
+                }
+                TextOp::Truncate(position) => {
+                    let offset = at(*position);
+                    buffer.delete(offset..model.len());
+                    model.truncate(offset);
+                }
+                TextOp::ReplaceRange(start, end, text) => {
+                    let (start, end) = span(*start, *end, chars);
+                    let range = byte_offset(&model, start)..byte_offset(&model, end);
+                    TextBuffer::replace_range(&mut buffer, range.clone(), text);
+                    model.replace_range(range, text);
+                }
+                TextOp::Find(pattern) => expect_eq("find()", TextBuffer::find(&buffer, pattern), model.find(pattern.as_str()))?,
+                TextOp::MoveCursor(position) => buffer.set_cursor(at(*position)),
+                TextOp::Clear => {
+                    buffer.delete(0..model.len());
+                    model.clear();
+                }
+                TextOp::SplitOff(_) => {}
+            }
+            let (before, after) = buffer.as_slices();
+            expect_eq("contents", format!("{}{}", before, after), model.clone())?;
+            expect_eq("len()", TextBuffer::len(&buffer), model.len())
+        })
+    }
+
+    // A rope counts in chars, so positions are used as they are
+    fn run_rope(ops: &[TextOp]) -> Result<(), String> {
+        let mut rope = Rope::new();
+        let mut model = String::new();
+        steps(ops, |op| {
+            let chars = char_count(&model);
+            match op {
+                TextOp::Push(ch) => {
+                    rope.push(*ch);
+                    model.push(*ch);
+                }
+                TextOp::PushStr(text) => {
+                    rope.push_str(text);
+                    model.push_str(text);
+                }
+                TextOp::Insert(at, ch) => {
+                    let at = position(*at, chars);
+                    rope.insert_char(at, *ch);
+                    model.insert(byte_offset(&model, at), *ch);
+                }
+                TextOp::InsertStr(at, text) => {
+                    let at = position(*at, chars);
+                    rope.insert(at, text);
+                    model.insert_str(byte_offset(&model, at), text);
+                }
+                TextOp::Pop => expect_eq("pop()", rope.pop(), model.pop())?,
+                TextOp::Remove(at) => {
+                    if let Some(index) = element(*at, chars) {
+                        expect_eq("char()", rope.char(index), model.chars().nth(index).unwrap())?;
+                        rope.remove(index..index + 1);
+                        model.remove(byte_offset(&model, index));
+                    }
+                }
+                TextOp::Truncate(at) => {
+                    let at = position(*at, chars);
+                    rope.remove(at..);
+                    model.truncate(byte_offset(&model, at));
+                }
+                TextOp::ReplaceRange(start, end, text) => {
+                    let (start, end) = span(*start, *end, chars);
+                    rope.replace_range(start..end, text);
+                    model.replace_range(byte_offset(&model, start)..byte_offset(&model, end), text);
+                }
+                TextOp::SplitOff(at) => {
+                    let at = position(*at, chars);
+                    let tail = rope.slice(at..);
+                    rope.remove(at..);
+                    expect_eq("the tail", String::from(&tail), model.split_off(byte_offset(&model, at)))?;
+                }
+                TextOp::Find(pattern) => {
+                    let expected = model.find(pattern.as_str()).map(|offset| char_count(&model[..offset]));
+                    expect_eq("find()", rope.find(pattern), expected)?;
+                }
+                TextOp::Clear => {
+                    rope = Rope::new();
+                    model.clear();
+                }
+                TextOp::MoveCursor(_) => {}
+            }
+            expect_eq("contents", String::from(&rope), model.clone())?;
+            expect_eq("len_bytes()", rope.len_bytes(), model.len())?;
+            expect_eq("len_chars()", rope.len_chars(), char_count(&model))?;
+            expect_eq("len_lines()", rope.len_lines(), model.matches('\n').count() + 1)?;
+            let last_line = rope.len_lines() - 1;
+            expect_eq("the last line", String::from(&rope.line(last_line)), model.rsplit('\n').next().unwrap().to_string())
+        })
+    }
+}
+
+pub mod benchmarking {
+    use std::collections::{LinkedList, VecDeque};
+    use std::hint::black_box;
+
+    use super::bench::{time, Bench, Report, Stats};
+
+    // The operations `demo_vec`, `demo_vecdeque` and `demo_linkedlist` show, on any of the three
+    trait Sequence: FromIterator<u64> {
+        const NAME: &'static str;
+
+        fn push_back(&mut self, value: u64);
+        fn push_front(&mut self, value: u64);
+        fn pop_back(&mut self) -> Option<u64>;
+        fn pop_front(&mut self) -> Option<u64>;
+        fn insert_middle(&mut self, value: u64);
+        fn sum(&self) -> u64;
+        fn split_off_half(&mut self) -> Self;
+        fn append(&mut self, other: &mut Self);
+    }
+
+    impl Sequence for Vec<u64> {
+        const NAME: &'static str = "Vec";
+
+        fn push_back(&mut self, value: u64) {
+            self.push(value);
+        }
+
+        fn push_front(&mut self, value: u64) {
+            self.insert(0, value);
+        }
+
+        fn pop_back(&mut self) -> Option<u64> {
+            self.pop()
+        }
+
+        fn pop_front(&mut self) -> Option<u64> {
+            (!self.is_empty()).then(|| self.remove(0))
+        }
+
+        fn insert_middle(&mut self, value: u64) {
+            self.insert(self.len() / 2, value);
+        }
+
+        fn sum(&self) -> u64 {
+            self.iter().sum()
+        }
+
+        fn split_off_half(&mut self) -> Self {
+            self.split_off(self.len() / 2)
+        }
+
+        fn append(&mut self, other: &mut Self) {
+            Vec::append(self, other);
+        }
+    }
+
+    impl Sequence for VecDeque<u64> {
+        const NAME: &'static str = "VecDeque";
+
+        fn push_back(&mut self, value: u64) {
+            VecDeque::push_back(self, value);
+        }
+
+        fn push_front(&mut self, value: u64) {
+            VecDeque::push_front(self, value);
+        }
+
+        fn pop_back(&mut self) -> Option<u64> {
+            VecDeque::pop_back(self)
+        }
+
+        fn pop_front(&mut self) -> Option<u64> {
+            VecDeque::pop_front(self)
+        }
+
+        fn insert_middle(&mut self, value: u64) {
+            self.insert(self.len() / 2, value);
+        }
+
+        fn sum(&self) -> u64 {
+            self.iter().sum()
+        }
+
+        fn split_off_half(&mut self) -> Self {
+            self.split_off(self.len() / 2)
+        }
+
+        fn append(&mut self, other: &mut Self) {
+            VecDeque::append(self, other);
+        }
+    }
+
+    impl Sequence for LinkedList<u64> {
+        const NAME: &'static str = "LinkedList";
+
+        fn push_back(&mut self, value: u64) {
+            LinkedList::push_back(self, value);
+        }
+
+        fn push_front(&mut self, value: u64) {
+            LinkedList::push_front(self, value);
+        }
+
+        fn pop_back(&mut self) -> Option<u64> {
+            LinkedList::pop_back(self)
+        }
+
+        fn pop_front(&mut self) -> Option<u64> {
+            LinkedList::pop_front(self)
+        }
+
+        // Stable Rust has no cursors to insert in the middle of a list, so it is split there and
+        // put back together, which walks to the middle like a cursor would
+        fn insert_middle(&mut self, value: u64) {
+            let mut back = self.split_off(self.len() / 2);
+            LinkedList::push_back(self, value);
+            LinkedList::append(self, &mut back);
+        }
+
+        fn sum(&self) -> u64 {
+            self.iter().sum()
+        }
+
+        fn split_off_half(&mut self) -> Self {
+            self.split_off(self.len() / 2)
+        }
+
+        fn append(&mut self, other: &mut Self) {
+            LinkedList::append(self, other);
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    enum Operation {
+        PushBack,
+        PushFront,
+        PopBack,
+        PopFront,
+        InsertMiddle,
+        Iterate,
+        SplitOff,
+        Append,
+    }
+
+    impl Operation {
+        const ALL: [Operation; 8] = [
+            Operation::PushBack,
+            Operation::PushFront,
+            Operation::PopBack,
+            Operation::PopFront,
+            Operation::InsertMiddle,
+            Operation::Iterate,
+            Operation::SplitOff,
+            Operation::Append,
+        ];
+
+        fn name(&self) -> &'static str {
+            match self {
+                Operation::PushBack => "push_back",
+                Operation::PushFront => "push_front",
+                Operation::PopBack => "pop_back",
+                Operation::PopFront => "pop_front",
+                Operation::InsertMiddle => "insert_middle",
+                Operation::Iterate => "iterate",
+                Operation::SplitOff => "split_off",
+                Operation::Append => "append",
+            }
+        }
+    }
+
+    // The time of one operation on a sequence of `size` elements. Building the sequences and
+    // dropping them again is never part of the timing.
+    fn measure<S: Sequence>(bench: &Bench, operation: Operation, size: usize) -> Stats {
+        let filled = |len: usize| -> S { (0..len as u64).collect() };
+        // Pushing thousands of elements onto a sequence of ten would measure a much longer one,
+        // so the iterations are spread over several sequences that each grow or shrink by at most
+        // `size` elements
+        let changes = |iterations: usize| -> Vec<usize> {
+            let per_sequence = size.max(1);
+            (0..iterations).step_by(per_sequence).map(|start| per_sequence.min(iterations - start)).collect()
+        };
+        match operation {
+            Operation::PushBack | Operation::PushFront | Operation::InsertMiddle => bench.run(|iterations| {
+                let mut sequences: Vec<(S, usize)> = changes(iterations).into_iter().map(|count| (filled(size), count)).collect();
+                let elapsed = time(|| {
+                    for (sequence, count) in &mut sequences {
+                        for value in 0..*count as u64 {
+                            match operation {
+                                Operation::PushBack => sequence.push_back(value),
+                                Operation::PushFront => sequence.push_front(value),
+                                _ => sequence.insert_middle(value),
+                            }
+                        }
+                    }
+                });
+                black_box(&sequences);
+                elapsed
+            }),
+            Operation::PopBack | Operation::PopFront => bench.run(|iterations| {
+                let mut sequences: Vec<(S, usize)> = changes(iterations).into_iter().map(|count| (filled(size + count), count)).collect();
+                let elapsed = time(|| {
+                    for (sequence, count) in &mut sequences {
+                        for _ in 0..*count {
+                            let value = match operation {
+                                Operation::PopBack => sequence.pop_back(),
+                                _ => sequence.pop_front(),
+                            };
+                            black_box(value);
+                        }
+                    }
+                });
+                black_box(&sequences);
+                elapsed
+            }),
+            // One iteration reads every element. `black_box` keeps the compiler from summing
+            // only once for all iterations.
+            Operation::Iterate => bench.run(|iterations| {
+                let sequence = filled(size);
+                time(|| {
+                    for _ in 0..iterations {
+                        black_box(black_box(&sequence).sum());
+                    }
+                })
+            }),
+            // These change the size for good, so every iteration gets sequences of its own, at
+            // most about a million elements of them per sample
+            Operation::SplitOff => bench.max_iterations((1 << 20) / size).run(|iterations| {
+                let mut sequences: Vec<S> = (0..iterations).map(|_| filled(size)).collect();
+                let mut halves = Vec::with_capacity(iterations);
+                let elapsed = time(|| {
+                    for sequence in &mut sequences {
+                        halves.push(sequence.split_off_half());
+                    }
+                });
+                black_box(&halves);
+                elapsed
+            }),
+            Operation::Append => bench.max_iterations((1 << 20) / size).run(|iterations| {
+                let mut pairs: Vec<(S, S)> = (0..iterations).map(|_| (filled(size), filled(size))).collect();
+                let elapsed = time(|| {
+                    for (sequence, other) in &mut pairs {
+                        sequence.append(other);
+                    }
+                });
+                black_box(&pairs);
+                elapsed
+            }),
+        }
+    }
+
+    fn run_sequence_benchmarks(sizes: &[usize]) -> Report {
+        let bench = Bench::new();
+        let mut report = Report::new();
+        for operation in Operation::ALL {
+            for &size in sizes {
+                report.add(operation.name(), Vec::NAME, size, measure::<Vec<u64>>(&bench, operation, size));
+                report.add(operation.name(), VecDeque::NAME, size, measure::<VecDeque<u64>>(&bench, operation, size));
+                report.add(operation.name(), LinkedList::NAME, size, measure::<LinkedList<u64>>(&bench, operation, size));
+            }
+        }
+        report
+    }
+
+    const SIZES: [usize; 6] = [10, 100, 1_000, 10_000, 100_000, 1_000_000];
+
+    // The evidence for the advice `demo_linkedlist` quotes from the docs. Run it with
+    // `cargo run --release`, debug builds measure something else entirely.
+    pub fn benchmark_sequences() {
+        let report = run_sequence_benchmarks(&SIZES);
+        println!("Median time per operation, * marks the fastest. iterate reads all elements once.\n");
+        println!("{}", report);
+
+        let cells = Operation::ALL.len() * SIZES.len();
+        for collection in [Vec::NAME, VecDeque::NAME, LinkedList::NAME] {
+            let wins: Vec<String> = Operation::ALL
+                .iter()
+                .flat_map(|operation| SIZES.iter().map(move |&size| (operation.name(), size)))
+                .filter(|&(operation, size)| report.fastest(operation, size) == Some(collection))
+                .map(|(operation, size)| format!("{} {}", operation, size))
+                .collect();
+            println!("{:<10} fastest in {:>2} of {}: {}", collection, wins.len(), cells, wins.join(", "));
+        }
+    }
+
+    // The same measurements as CSV on standard output, for `cargo run --release > sequences.csv`
+    pub fn benchmark_sequences_csv() {
+        print!("{}", run_sequence_benchmarks(&SIZES).to_csv());
+    }
+}
@@ -0,0 +1,247 @@
+// A small benchmark harness: warmup, calibrated iterations, and statistics over many samples.
+//
+// Timing one run of a fast operation mostly measures the clock, and timing one slow run measures
+// whatever else the machine was doing at that moment. `Bench` first warms up, running the code
+// until caches, the allocator and the CPU's clock speed have settled, and on the way finds how
+// many iterations it takes for one sample to last long enough to time reliably. Then it takes a
+// number of samples and keeps the time per iteration of each. The median of those is the number
+// to compare, and the spread between the 10th and 90th percentile shows how noisy it was.
+//
+// The code being measured decides what is timed: `Bench::run` hands it a number of iterations and
+// takes back a `Duration`, so setup and cleanup, such as building the collection to work on and
+// dropping it again, can happen outside of the timed part.
+//
+// `Report` collects the results of many benchmarks and prints them as one comparison table per
+// operation, or as CSV for a spreadsheet.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::module_08::csv;
+
+/// How long to warm up, how long a sample should take and how many to take.
+#[derive(Clone, Copy, Debug)]
+pub struct Bench {
+    warmup: Duration,
+    sample_time: Duration,
+    samples: usize,
+    max_iterations: usize,
+}
+
+impl Bench {
+    pub fn new() -> Bench {
+        Bench { warmup: Duration::from_millis(20), sample_time: Duration::from_millis(1), samples: 25, max_iterations: 4096 }
+    }
+
+    pub fn warmup(mut self, warmup: Duration) -> Bench {
+        self.warmup = warmup;
+        self
+    }
+
+    pub fn sample_time(mut self, sample_time: Duration) -> Bench {
+        self.sample_time = sample_time;
+        self
+    }
+
+    pub fn samples(mut self, samples: usize) -> Bench {
+        self.samples = samples.max(1);
+        self
+    }
+
+    // An upper bound for the iterations per sample, for code whose setup grows with them
+    pub fn max_iterations(mut self, max_iterations: usize) -> Bench {
+        self.max_iterations = max_iterations.max(1);
+        self
+    }
+
+    /// `sample(iterations)` runs the measured code `iterations` times and returns how long that
+    /// took, without any setup or cleanup it needed.
+    pub fn run(&self, mut sample: impl FnMut(usize) -> Duration) -> Stats {
+        // Double the iterations until a sample is long enough, and keep going until the warmup
+        // time is over
+        let mut iterations = 1;
+        let start = Instant::now();
+        loop {
+            let elapsed = sample(iterations);
+            let calibrated = elapsed >= self.sample_time || iterations == self.max_iterations;
+            if !calibrated {
+                iterations = (iterations * 2).min(self.max_iterations);
+            } else if start.elapsed() >= self.warmup {
+                break;
+            }
+        }
+
+        // In nanoseconds as floating point, because the fastest operations take about one and a
+        // `Duration` can not hold fractions of one
+        let mut nanos: Vec<f64> = (0..self.samples).map(|_| sample(iterations).as_nanos() as f64 / iterations as f64).collect();
+        nanos.sort_by(f64::total_cmp);
+        Stats { iterations, nanos }
+    }
+}
+
+impl Default for Bench {
+    fn default() -> Self {
+        Bench::new()
+    }
+}
+
+// Times `f`, for use inside the closure given to `Bench::run`
+pub fn time(f: impl FnOnce()) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
+
+/// The time per iteration of every sample in nanoseconds, fastest first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stats {
+    pub iterations: usize,
+    nanos: Vec<f64>,
+}
+
+impl Stats {
+    pub fn samples(&self) -> &[f64] {
+        &self.nanos
+    }
+
+    // Nearest rank: the smallest sample that at least `percent`% of the samples are not above
+    pub fn percentile(&self, percent: f64) -> f64 {
+        let rank = (percent / 100.0 * self.nanos.len() as f64).ceil() as usize;
+        self.nanos[rank.clamp(1, self.nanos.len()) - 1]
+    }
+
+    pub fn median(&self) -> f64 {
+        self.percentile(50.0)
+    }
+
+    pub fn min(&self) -> f64 {
+        self.nanos[0]
+    }
+
+    pub fn max(&self) -> f64 {
+        self.nanos[self.nanos.len() - 1]
+    }
+}
+
+// Like `Duration`'s debug output, with three significant digits
+pub fn format_nanos(nanos: f64) -> String {
+    let (value, unit) = match nanos {
+        n if n < 1e3 => (n, "ns"),
+        n if n < 1e6 => (n / 1e3, "µs"),
+        n if n < 1e9 => (n / 1e6, "ms"),
+        n => (n / 1e9, "s"),
+    };
+    let decimals = if value < 10.0 { 2 } else if value < 100.0 { 1 } else { 0 };
+    format!("{:.*}{}", decimals, value, unit)
+}
+
+/// The result of one benchmark.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Measurement {
+    pub operation: &'static str,
+    pub collection: &'static str,
+    pub size: usize,
+    pub stats: Stats,
+}
+
+/// Measurements of the same operations on different collections and sizes.
+#[derive(Default)]
+pub struct Report {
+    measurements: Vec<Measurement>,
+}
+
+impl Report {
+    pub fn new() -> Report {
+        Report::default()
+    }
+
+    pub fn add(&mut self, operation: &'static str, collection: &'static str, size: usize, stats: Stats) {
+        self.measurements.push(Measurement { operation, collection, size, stats });
+    }
+
+    pub fn measurements(&self) -> &[Measurement] {
+        &self.measurements
+    }
+
+    // In the order they were first measured
+    fn distinct<T: PartialEq + Copy>(&self, key: impl Fn(&Measurement) -> T) -> Vec<T> {
+        let mut values = Vec::new();
+        for measurement in &self.measurements {
+            let value = key(measurement);
+            if !values.contains(&value) {
+                values.push(value);
+            }
+        }
+        values
+    }
+
+    fn find(&self, operation: &str, collection: &str, size: usize) -> Option<&Measurement> {
+        self.measurements.iter().find(|m| m.operation == operation && m.collection == collection && m.size == size)
+    }
+
+    // The collection with the lowest median for an operation at a size
+    pub fn fastest(&self, operation: &str, size: usize) -> Option<&'static str> {
+        self.measurements
+            .iter()
+            .filter(|m| m.operation == operation && m.size == size)
+            .min_by(|a, b| a.stats.median().total_cmp(&b.stats.median()))
+            .map(|m| m.collection)
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut writer = csv::Writer::new(Vec::new());
+        let header = ["operation", "collection", "size", "iterations", "samples", "median_ns", "p10_ns", "p90_ns", "min_ns", "max_ns"];
+        writer.write_record(header).unwrap();
+        for m in &self.measurements {
+            let nanos = |nanos: f64| format!("{:.2}", nanos);
+            writer
+                .write_record([
+                    m.operation.to_string(),
+                    m.collection.to_string(),
+                    m.size.to_string(),
+                    m.stats.iterations.to_string(),
+                    m.stats.samples().len().to_string(),
+                    nanos(m.stats.median()),
+                    nanos(m.stats.percentile(10.0)),
+                    nanos(m.stats.percentile(90.0)),
+                    nanos(m.stats.min()),
+                    nanos(m.stats.max()),
+                ])
+                .unwrap();
+        }
+        String::from_utf8(writer.into_inner()).unwrap()
+    }
+}
+
+// One table per operation, a row per size and a column per collection, with the median time and
+// a `*` on the fastest
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let collections = self.distinct(|m| m.collection);
+        let sizes = self.distinct(|m| m.size);
+        for operation in self.distinct(|m| m.operation) {
+            write!(f, "{:>14}", operation)?;
+            for collection in &collections {
+                write!(f, " | {:>12}", collection)?;
+            }
+            writeln!(f)?;
+            writeln!(f, "{:->14}{}", "", "-+-------------".repeat(collections.len()))?;
+            for &size in &sizes {
+                write!(f, "{:>14}", size)?;
+                let fastest = self.fastest(operation, size);
+                for &collection in &collections {
+                    match self.find(operation, collection, size) {
+                        Some(m) => {
+                            let marker = if fastest == Some(collection) { "*" } else { " " };
+                            write!(f, " | {:>11}{}", format_nanos(m.stats.median()), marker)?;
+                        }
+                        None => write!(f, " | {:>12}", "-")?,
+                    }
+                }
+                writeln!(f)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
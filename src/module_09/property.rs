@@ -0,0 +1,266 @@
+// Model-based property testing: random operations against a collection and the std collection
+// that models it.
+//
+// A property test describes the operations a collection supports as a type, generates long random
+// sequences of them, and runs every sequence against the custom collection and a std collection
+// side by side, comparing what each operation returns and what the collections hold afterwards.
+// Any difference, or a panic, fails the test.
+//
+// A random sequence that fails is usually long and mostly irrelevant, so it is shrunk before it
+// is reported: chunks of operations are removed, halves first and single operations last, and the
+// remaining operations are replaced by simpler ones, for as long as the sequence still fails. What
+// is left is typically a handful of operations that show the bug directly.
+//
+// Everything is driven by one seed, so a run with the same seed generates the same cases and
+// fails in the same way. A failure reports the seed and the case it failed in.
+
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+/// A small, fast, seeded random number generator (SplitMix64). Not for cryptography.
+#[derive(Clone, Debug)]
+pub struct Random(u64);
+
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        Random(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // A number in `0..bound`, `bound` has to be more than zero
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    pub fn index(&mut self, len: usize) -> usize {
+        self.below(len as u64) as usize
+    }
+
+    // True `numerator` times in `denominator`
+    pub fn chance(&mut self, numerator: u64, denominator: u64) -> bool {
+        self.below(denominator) < numerator
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.index(items.len())]
+    }
+}
+
+/// One step of a property test.
+pub trait Operation: Clone + fmt::Debug {
+    fn generate(random: &mut Random) -> Self;
+
+    // Simpler operations to try in place of this one while shrinking, simplest first
+    fn shrink(&self) -> Vec<Self> {
+        Vec::new()
+    }
+}
+
+// Smaller numbers to try in place of `n` while shrinking
+pub fn shrink_number(n: usize) -> Vec<usize> {
+    let mut smaller = vec![0, n / 2, n.saturating_sub(1)];
+    smaller.retain(|&m| m < n);
+    smaller.dedup();
+    smaller
+}
+
+/// How many cases to run, how long they are, and the seed that decides everything else.
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    seed: u64,
+    cases: usize,
+    max_operations: usize,
+    max_shrink_runs: usize,
+}
+
+impl Config {
+    pub fn new() -> Config {
+        Config { seed: 0x2021_c011_ec71_0a5e, cases: 200, max_operations: 200, max_shrink_runs: 20_000 }
+    }
+
+    pub fn seed(mut self, seed: u64) -> Config {
+        self.seed = seed;
+        self
+    }
+
+    pub fn cases(mut self, cases: usize) -> Config {
+        self.cases = cases;
+        self
+    }
+
+    // Early cases are short and later ones get longer, up to this many operations
+    pub fn max_operations(mut self, max_operations: usize) -> Config {
+        self.max_operations = max_operations;
+        self
+    }
+
+    pub fn max_shrink_runs(mut self, runs: usize) -> Config {
+        self.max_shrink_runs = runs;
+        self
+    }
+
+    /// Runs random sequences of operations through `run`, which returns an error describing the
+    /// first difference it sees. Panics inside `run` count as failures too.
+    ///
+    /// The panic hook is replaced while the cases run, so that expected panics print nothing.
+    /// Don't run property tests on several threads at once.
+    pub fn check<O: Operation>(&self, run: impl Fn(&[O]) -> Result<(), String>) -> Result<Passed, Failure<O>> {
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(|_| {}));
+        // A panic outside of `run` is a bug in the harness. It is raised again once the hook is
+        // back, with `panic!` rather than `resume_unwind`, which would skip the hook.
+        let result = panic::catch_unwind(AssertUnwindSafe(|| self.check_quietly(&run)));
+        panic::set_hook(hook);
+        result.unwrap_or_else(|payload| panic!("property test harness panicked: {}", panic_message(&*payload)))
+    }
+
+    fn check_quietly<O: Operation>(&self, run: &dyn Fn(&[O]) -> Result<(), String>) -> Result<Passed, Failure<O>> {
+        let mut operations = 0;
+        for case in 0..self.cases {
+            let mut random = Random::new(self.seed.wrapping_add((case as u64).wrapping_mul(0x5851_f42d_4c95_7f2d)));
+            let max_len = 1 + self.max_operations * (case + 1) / self.cases.max(1);
+            let len = random.index(max_len + 1);
+            let ops: Vec<O> = (0..len).map(|_| O::generate(&mut random)).collect();
+            operations += ops.len();
+
+            if let Err(message) = run_once(run, &ops) {
+                let original_len = ops.len();
+                let (ops, message, shrink_runs) = self.shrink(run, ops, message);
+                return Err(Failure { seed: self.seed, case, original_len, ops, message, shrink_runs });
+            }
+        }
+        Ok(Passed { cases: self.cases, operations })
+    }
+
+    fn shrink<O: Operation>(&self, run: &dyn Fn(&[O]) -> Result<(), String>, mut ops: Vec<O>, mut message: String) -> (Vec<O>, String, usize) {
+        let mut runs = 0;
+        loop {
+            let mut progress = false;
+
+            // Remove chunks of operations, the biggest first
+            let mut chunk = (ops.len() / 2).max(1);
+            while chunk > 0 {
+                let mut start = 0;
+                while start + chunk <= ops.len() && runs < self.max_shrink_runs {
+                    let candidate = [&ops[..start], &ops[start + chunk..]].concat();
+                    runs += 1;
+                    match run_once(run, &candidate) {
+                        Err(candidate_message) => {
+                            ops = candidate;
+                            message = candidate_message;
+                            progress = true;
+                        }
+                        Ok(()) => start += chunk,
+                    }
+                }
+                chunk /= 2;
+            }
+
+            // Make the operations that are left simpler
+            for index in 0..ops.len() {
+                for simpler in ops[index].shrink() {
+                    if runs >= self.max_shrink_runs {
+                        break;
+                    }
+                    let mut candidate = ops.clone();
+                    candidate[index] = simpler;
+                    runs += 1;
+                    if let Err(candidate_message) = run_once(run, &candidate) {
+                        ops = candidate;
+                        message = candidate_message;
+                        progress = true;
+                        break;
+                    }
+                }
+            }
+
+            if !progress || runs >= self.max_shrink_runs {
+                return (ops, message, runs);
+            }
+        }
+    }
+
+    // Like `check`, but panics with the shrunk failure
+    pub fn assert<O: Operation>(&self, name: &str, run: impl Fn(&[O]) -> Result<(), String>) -> Passed {
+        match self.check(run) {
+            Ok(passed) => passed,
+            Err(failure) => panic!("property `{}` {}", name, failure),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::new()
+    }
+}
+
+fn run_once<O>(run: &dyn Fn(&[O]) -> Result<(), String>, ops: &[O]) -> Result<(), String> {
+    match panic::catch_unwind(AssertUnwindSafe(|| run(ops))) {
+        Ok(result) => result,
+        Err(payload) => Err(format!("panicked: {}", panic_message(&*payload))),
+    }
+}
+
+fn panic_message(payload: &dyn Any) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "(not a string)"
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Passed {
+    pub cases: usize,
+    pub operations: usize,
+}
+
+/// The shrunk sequence of operations that failed, and how it failed.
+#[derive(Clone, Debug)]
+pub struct Failure<O> {
+    pub seed: u64,
+    pub case: usize,
+    pub original_len: usize,
+    pub ops: Vec<O>,
+    pub message: String,
+    pub shrink_runs: usize,
+}
+
+impl<O: fmt::Debug> fmt::Display for Failure<O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "failed in case {} of the run with seed {:#x}", self.case, self.seed)?;
+        writeln!(f, "shrunk from {} to {} operations in {} runs:", self.original_len, self.ops.len(), self.shrink_runs)?;
+        for (index, op) in self.ops.iter().enumerate() {
+            writeln!(f, "  {:>3}: {:?}", index, op)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Runs `step` for every operation, and says which operation the first error came from.
+pub fn steps<O: fmt::Debug>(ops: &[O], mut step: impl FnMut(&O) -> Result<(), String>) -> Result<(), String> {
+    for (index, op) in ops.iter().enumerate() {
+        step(op).map_err(|message| format!("after operation {} ({:?}): {}", index, op, message))?;
+    }
+    Ok(())
+}
+
+/// An error unless the custom collection's answer is the model's.
+pub fn expect_eq<T: PartialEq + fmt::Debug>(what: &str, found: T, expected: T) -> Result<(), String> {
+    if found == expected {
+        Ok(())
+    } else {
+        Err(format!("{} is {:?}, std says {:?}", what, found, expected))
+    }
+}